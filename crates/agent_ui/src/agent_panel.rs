@@ -2099,14 +2099,11 @@ impl AgentPanel {
     fn webview_navigate_to(&mut self, url: String, window: &mut Window, cx: &mut Context<Self>) {
         self.webview_current_url = url.clone();
 
-        let needs_new_webview = match &self.webview_manager {
-            None => true,
-            Some(manager) => !manager.is_visible(),
-        };
-
-        if needs_new_webview {
-            self.webview_manager = None;
-
+        // A hidden or closed webview keeps its page, so reuse it rather than reloading everything.
+        if let Some(manager) = &mut self.webview_manager {
+            manager.set_hidden(false);
+            manager.navigate(&url).log_err();
+        } else {
             let width = self.width.unwrap_or(px(400.0));
             let height = px(600.0);
             let bounds = gpui::Bounds {
@@ -2114,7 +2111,12 @@ impl AgentPanel {
                 size: gpui::Size { width, height },
             };
             let ns_window = window.native_window_handle().unwrap_or(std::ptr::null_mut());
-            match crate::webview_manager::WebViewManager::new(ns_window, bounds, &url) {
+            match crate::webview_manager::WebViewManager::builder()
+                .url(&url)
+                .bounds(bounds)
+                .activate(true)
+                .build(ns_window, cx)
+            {
                 Ok(manager) => {
                     self.webview_manager = Some(manager);
                     log::info!("Created webview for: {}", url);
@@ -2123,8 +2125,6 @@ impl AgentPanel {
                     log::error!("Failed to create webview: {}", e);
                 }
             }
-        } else if let Some(manager) = &mut self.webview_manager {
-            manager.navigate(&url);
         }
 
         cx.notify();
@@ -1,178 +1,6841 @@
 use anyhow::Result;
-use gpui::{Bounds, Pixels};
+use gpui::{App, Bounds, Edges, Pixels, Point, Size, point, px, size};
+use serde::{Deserialize, Serialize};
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+use uuid::Uuid;
 
 #[cfg(target_os = "macos")]
-use cocoa::appkit::{NSWindowStyleMask, NSBackingStoreType};
+use block::{Block, ConcreteBlock};
 #[cfg(target_os = "macos")]
-use cocoa::base::{id, nil, YES, NO};
+use cocoa::appkit::{NSBackingStoreType, NSWindowStyleMask};
 #[cfg(target_os = "macos")]
-use cocoa::foundation::{NSRect, NSPoint, NSSize, NSString};
+use cocoa::base::{BOOL, NO, YES, id, nil};
 #[cfg(target_os = "macos")]
-use objc::{msg_send, sel, sel_impl, class};
+use cocoa::foundation::{NSAutoreleasePool, NSPoint, NSRect, NSSize, NSString};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+use collections::HashMap;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+use futures::channel::oneshot;
 #[cfg(target_os = "macos")]
-use objc::runtime::Class;
+use gpui::Keystroke;
+#[cfg(any(test, target_os = "macos"))]
+use gpui::Modifiers;
+#[cfg(target_os = "macos")]
+use objc::{class, msg_send, sel, sel_impl};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+use std::{cell::RefCell, rc::Rc};
+#[cfg(target_os = "macos")]
+use util::ResultExt as _;
 
-/// A floating webview window for embedding web content
-pub struct WebViewManager {
-    #[cfg(target_os = "macos")]
-    floating_window: id,
-    #[cfg(target_os = "macos")]
-    ns_webview: id,
-    current_url: String,
+/// Logs routine per-instance events, like creating a webview or navigating it, at info level
+/// unless they were turned off with [`set_webview_logging`].
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+macro_rules! webview_info {
+    ($($arg:tt)+) => {
+        if $crate::webview_manager::logging_enabled() {
+            log::info!($($arg)+);
+        }
+    };
 }
 
-impl WebViewManager {
-    #[cfg(target_os = "macos")]
-    pub fn new(
-        _parent_window_ptr: *mut std::ffi::c_void,
-        bounds: Bounds<Pixels>,
-        url: &str,
-    ) -> Result<Self> {
-        unsafe {
-            let wk_config_class = Class::get("WKWebViewConfiguration")
-                .ok_or_else(|| anyhow::anyhow!("WKWebViewConfiguration class not found"))?;
-            let wk_webview_class = Class::get("WKWebView")
-                .ok_or_else(|| anyhow::anyhow!("WKWebView class not found"))?;
+mod backend;
+#[cfg(target_os = "linux")]
+mod linux;
+mod registry;
+#[cfg(target_os = "windows")]
+mod windows;
 
-            let screen: id = msg_send![class!(NSScreen), mainScreen];
-            let screen_frame: NSRect = msg_send![screen, frame];
+#[cfg(any(test, feature = "test-support"))]
+pub use backend::MockWebViewBackend;
+pub use backend::WebViewBackend;
+pub use registry::{WebViewId, WebViewRegistry};
 
-            let width: f64 = bounds.size.width.into();
-            let height: f64 = bounds.size.height.into();
-            let x: f64 = screen_frame.origin.x + 100.0;
-            let y: f64 = screen_frame.origin.y + screen_frame.size.height - height - 100.0;
+#[cfg(target_os = "linux")]
+use linux::open_url;
+#[cfg(target_os = "windows")]
+use windows::open_url;
 
-            let window_rect = NSRect {
-                origin: NSPoint::new(x, y),
-                size: NSSize::new(width, height),
-            };
+#[cfg(target_os = "macos")]
+use objc::declare::ClassDecl;
+#[cfg(target_os = "macos")]
+use objc::runtime::{Class, Object, Protocol, Sel};
+#[cfg(target_os = "macos")]
+use std::{
+    cell::Cell,
+    ffi::{CStr, CString, c_char, c_void},
+    sync::OnceLock,
+};
 
-            let style_mask = NSWindowStyleMask::NSTitledWindowMask
-                | NSWindowStyleMask::NSClosableWindowMask
-                | NSWindowStyleMask::NSMiniaturizableWindowMask
-                | NSWindowStyleMask::NSResizableWindowMask
-                | NSWindowStyleMask::NSFullSizeContentViewWindowMask;
+#[cfg(target_os = "macos")]
+const WEBVIEW_STATE_IVAR: &str = "webViewState";
+#[cfg(target_os = "macos")]
+const ACCEPTS_FILE_DROPS_IVAR: &str = "acceptsFileDrops";
+/// The host view that passthrough shortcuts are forwarded to. Not retained; may be nil.
+#[cfg(target_os = "macos")]
+const PARENT_VIEW_IVAR: &str = "parentView";
+/// WebKit caches compiled rule lists on disk under this identifier.
+#[cfg(target_os = "macos")]
+const CONTENT_RULE_LIST_IDENTIFIER: &str = "dev.zed.webview.content-rules";
+#[cfg(target_os = "macos")]
+const ESTIMATED_PROGRESS_KEY_PATH: &str = "estimatedProgress";
+#[cfg(target_os = "macos")]
+const TITLE_KEY_PATH: &str = "title";
+/// Only exists on macOS 13 and later; older versions never report a change.
+#[cfg(target_os = "macos")]
+const FULLSCREEN_STATE_KEY_PATH: &str = "fullscreenState";
+/// Key paths of the `WKWebView` observed by the delegate.
+#[cfg(target_os = "macos")]
+const OBSERVED_KEY_PATHS: [&str; 3] = [
+    ESTIMATED_PROGRESS_KEY_PATH,
+    TITLE_KEY_PATH,
+    FULLSCREEN_STATE_KEY_PATH,
+];
 
-            let floating_window: id = msg_send![class!(NSWindow), alloc];
-            let floating_window: id = msg_send![
-                floating_window,
-                initWithContentRect:window_rect
-                styleMask:style_mask
-                backing:NSBackingStoreType::NSBackingStoreBuffered
-                defer:NO
-            ];
+/// Searches with `window.find`, for platforms without a native find API. Wraps around and ignores
+/// case, like the native search.
+#[cfg(any(test, target_os = "macos", target_os = "windows"))]
+fn find_script(query: &str, forward: bool) -> String {
+    format!(
+        "window.find({}, false, {}, true)",
+        serde_json::Value::from(query),
+        !forward
+    )
+}
 
-            let _: () = msg_send![floating_window, setTitlebarAppearsTransparent:YES];
-            let _: () = msg_send![floating_window, setTitleVisibility:1i64]; // NSWindowTitleHidden
-            let _: () = msg_send![floating_window, setLevel:1];
-            let _: () = msg_send![floating_window, setOpaque:YES];
-            let _: () = msg_send![floating_window, setHasShadow:YES];
-            let _: () = msg_send![floating_window, setReleasedWhenClosed:NO];
+/// Interprets the result of [`find_script`], which WebKit reports as `1` and WebView2 as `true`.
+#[cfg(any(test, target_os = "macos", target_os = "windows"))]
+fn find_script_matched(result: &str) -> bool {
+    matches!(result, "true" | "1")
+}
 
-            let config: id = msg_send![wk_config_class, new];
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const CLEAR_FIND_SCRIPT: &str = "window.getSelection().removeAllRanges()";
 
-            let data_store_class = Class::get("WKWebsiteDataStore")
-                .ok_or_else(|| anyhow::anyhow!("WKWebsiteDataStore class not found"))?;
-            let default_data_store: id = msg_send![data_store_class, defaultDataStore];
-            let _: () = msg_send![config, setWebsiteDataStore:default_data_store];
+/// Makes the page report itself as hidden or visible and fires `visibilitychange`, so that pages
+/// which pause work while hidden do so even when the platform can't suspend them. Resuming removes
+/// the override, restoring the browser's own visibility state.
+#[cfg(any(test, target_os = "macos", target_os = "linux"))]
+fn page_visibility_script(hidden: bool) -> String {
+    let override_visibility = if hidden {
+        "Object.defineProperty(document, 'hidden', { configurable: true, get: () => true }); \
+         Object.defineProperty(document, 'visibilityState', \
+            { configurable: true, get: () => 'hidden' });"
+    } else {
+        "delete document.hidden; delete document.visibilityState;"
+    };
+    format!("{override_visibility} document.dispatchEvent(new Event('visibilitychange'));")
+}
 
-            let webview_frame = NSRect {
-                origin: NSPoint::new(0.0, 0.0),
-                size: NSSize::new(width, height),
-            };
+/// Mutes or unmutes every `<audio>` and `<video>` element, including ones that start playing
+/// later, for platforms without a native mute. Unmuting also unmutes elements the page muted
+/// itself.
+#[cfg(any(test, target_os = "macos", target_os = "linux"))]
+fn mute_script(muted: bool) -> String {
+    format!(
+        "window.__zedMuted = {muted}; \
+         if (!window.__zedMuteListener) {{ \
+            window.__zedMuteListener = true; \
+            document.addEventListener('play', event => {{ \
+                if (window.__zedMuted) event.target.muted = true; \
+            }}, true); \
+         }} \
+         document.querySelectorAll('audio, video').forEach(media => {{ media.muted = {muted}; }});"
+    )
+}
 
-            let webview: id = msg_send![wk_webview_class, alloc];
-            let webview: id = msg_send![webview, initWithFrame:webview_frame configuration:config];
+/// Reports the bounding rect of the first element matching `selector` and the viewport width, in
+/// CSS pixels, as `"x,y,width,height,viewport_width"`, or an empty string without a match.
+#[cfg(any(test, target_os = "macos", target_os = "windows"))]
+fn element_rect_script(selector: &str) -> String {
+    format!(
+        "(() => {{ \
+            const element = document.querySelector({}); \
+            if (!element) return ''; \
+            const rect = element.getBoundingClientRect(); \
+            return `${{rect.x}},${{rect.y}},${{rect.width}},${{rect.height}},${{window.innerWidth}}`; \
+        }})()",
+        serde_json::Value::from(selector)
+    )
+}
 
-            let autoresizing_mask: u64 = 2 | 16;
-            let _: () = msg_send![webview, setAutoresizingMask: autoresizing_mask];
+/// An element's bounds in CSS pixels, relative to the viewport, as reported by
+/// [`element_rect_script`].
+#[cfg(any(test, target_os = "macos", target_os = "windows"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ElementRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    viewport_width: f64,
+}
 
-            let content_view: id = msg_send![floating_window, contentView];
-            let _: () = msg_send![content_view, addSubview:webview];
+/// Returns `None` when no element matched or it takes up no space.
+#[cfg(any(test, target_os = "macos", target_os = "windows"))]
+fn parse_element_rect(result: &str) -> Option<ElementRect> {
+    let mut values = result
+        .split(',')
+        .map(|value| value.trim().parse::<f64>().ok());
+    let mut next = || values.next().flatten();
+    let rect = ElementRect {
+        x: next()?,
+        y: next()?,
+        width: next()?,
+        height: next()?,
+        viewport_width: next()?,
+    };
+    (rect.width > 0.0 && rect.height > 0.0 && rect.viewport_width > 0.0).then_some(rect)
+}
 
-            let url_string = NSString::alloc(nil).init_str(url);
-            let nsurl: id = msg_send![class!(NSURL), URLWithString:url_string];
-            let request: id = msg_send![class!(NSURLRequest), requestWithURL:nsurl];
-            let _: () = msg_send![webview, loadRequest:request];
+/// Crops a PNG snapshot of the viewport to `rect`, clipping it to the visible area.
+#[cfg(any(test, target_os = "windows"))]
+fn crop_png(png: &[u8], rect: ElementRect) -> Result<Vec<u8>> {
+    let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)?;
+    let scale = image.width() as f64 / rect.viewport_width;
+    let left = (rect.x * scale).max(0.0) as u32;
+    let top = (rect.y * scale).max(0.0) as u32;
+    let right = (((rect.x + rect.width) * scale).max(0.0) as u32).min(image.width());
+    let bottom = (((rect.y + rect.height) * scale).max(0.0) as u32).min(image.height());
+    anyhow::ensure!(
+        left < right && top < bottom,
+        "the element is outside the visible page"
+    );
+    let mut cropped = Vec::new();
+    image
+        .crop_imm(left, top, right - left, bottom - top)
+        .write_to(
+            &mut std::io::Cursor::new(&mut cropped),
+            image::ImageFormat::Png,
+        )?;
+    Ok(cropped)
+}
 
-            let _: () = msg_send![floating_window, makeKeyAndOrderFront:nil];
+/// Reports the page's scroll offset as `"x,y"`, since WebKit can't return plain numbers as strings.
+const SCROLL_POSITION_SCRIPT: &str = "`${window.scrollX},${window.scrollY}`";
 
-            log::info!("Created floating webview window ({}x{})", width, height);
+fn parse_scroll_position(result: &str) -> Option<(f64, f64)> {
+    let (x, y) = result.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
 
-            Ok(Self {
-                floating_window,
-                ns_webview: webview,
-                current_url: url.to_string(),
+/// Reports the rendered text of the page as a JSON string, or `null` before the body is parsed.
+const EXTRACT_TEXT_SCRIPT: &str = "JSON.stringify(\
+    document.readyState === 'loading' || !document.body ? null : document.body.innerText)";
+
+/// Like [`EXTRACT_TEXT_SCRIPT`], but prefers the page's main content and skips navigation,
+/// headers, footers, and sidebars.
+const EXTRACT_READABLE_TEXT_SCRIPT: &str = "(() => {
+    if (document.readyState === 'loading' || !document.body) return JSON.stringify(null);
+    const chrome = 'nav, header, footer, aside, script, style, noscript, template, \
+        [role=navigation], [role=banner], [role=contentinfo], [role=complementary], \
+        [aria-hidden=true]';
+    const root = document.querySelector('main, article, [role=main]') || document.body;
+    const parts = [];
+    const visit = (element) => {
+        for (const child of element.children) {
+            if (child.matches(chrome)) continue;
+            if (child.querySelector(chrome)) visit(child);
+            else parts.push((child.innerText || '').trim());
+        }
+    };
+    visit(root);
+    return JSON.stringify(parts.filter(part => part).join('\\n\\n'));
+})()";
+
+/// Decodes the result of the text extraction scripts.
+fn parse_extracted_text(result: &str) -> Result<String> {
+    serde_json::from_str::<Option<String>>(result)?
+        .ok_or_else(|| anyhow::anyhow!("the page hasn't loaded yet"))
+}
+
+/// Wraps `scripts`, which must be expressions, into a single script that evaluates each in turn
+/// and returns a JSON array of `{"ok": value}` or `{"error": message}` entries, so one script
+/// throwing doesn't lose the others' results.
+fn batch_script(scripts: &[&str]) -> String {
+    let entries: String = scripts
+        .iter()
+        .map(|script| {
+            format!(
+                "    try {{
+        __zedResults.push({{ ok: __zedStringify((
+{script}
+)) }});
+    }} catch (error) {{
+        __zedResults.push({{ error: String(error) }});
+    }}
+"
+            )
+        })
+        .collect();
+    format!(
+        "(() => {{
+    const __zedStringify = (value) =>
+        typeof value === 'string' ? value : JSON.stringify(value) ?? String(value);
+    const __zedResults = [];
+{entries}    return JSON.stringify(__zedResults);
+}})()"
+    )
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BatchResult {
+    Ok(String),
+    Error(String),
+}
+
+/// Splits the result of [`batch_script`] into one result per script. When the batch as a whole
+/// fails, e.g. because one of the scripts doesn't parse, every script gets its error.
+fn parse_batch_results(result: Result<String>, count: usize) -> Vec<Result<String>> {
+    let results = result.and_then(|result| {
+        let results = serde_json::from_str::<Vec<BatchResult>>(&result)?;
+        anyhow::ensure!(
+            results.len() == count,
+            "expected {count} batch results, got {}",
+            results.len()
+        );
+        Ok(results)
+    });
+    match results {
+        Ok(results) => results
+            .into_iter()
+            .map(|result| match result {
+                BatchResult::Ok(value) => Ok(value),
+                BatchResult::Error(message) => Err(anyhow::anyhow!(message)),
             })
+            .collect(),
+        Err(error) => (0..count)
+            .map(|_| Err(anyhow::anyhow!("{error:#}")))
+            .collect(),
+    }
+}
+
+/// Name of the message handler [`network_idle_script`] posts to.
+const NETWORK_IDLE_MESSAGE_HANDLER: &str = "zedNetworkIdle";
+
+/// Counts in-flight `fetch` and `XMLHttpRequest` requests and, once the page has fired `load`,
+/// posts to [`NETWORK_IDLE_MESSAGE_HANDLER`] when none have been in flight for `idle_ms`. Posts
+/// at most once per page. Installing it again only updates the idle delay.
+fn network_idle_script(idle_ms: u64) -> String {
+    format!(
+        r#"window.__zedNetworkIdleMs = {idle_ms};
+if (!window.__zedNetworkIdle) {{
+    window.__zedNetworkIdle = true;
+    let inFlight = 0, timer = null, signaled = false;
+    const check = () => {{
+        clearTimeout(timer);
+        if (signaled || inFlight > 0 || document.readyState !== 'complete') return;
+        timer = setTimeout(() => {{
+            signaled = true;
+            window.webkit?.messageHandlers?.{NETWORK_IDLE_MESSAGE_HANDLER}?.postMessage('');
+        }}, window.__zedNetworkIdleMs);
+    }};
+    const started = () => {{ inFlight++; clearTimeout(timer); }};
+    const finished = () => {{ inFlight = Math.max(0, inFlight - 1); check(); }};
+    const fetch = window.fetch;
+    window.fetch = function (...args) {{
+        started();
+        return fetch.apply(this, args).finally(finished);
+    }};
+    const send = XMLHttpRequest.prototype.send;
+    XMLHttpRequest.prototype.send = function (...args) {{
+        started();
+        this.addEventListener('loadend', finished, {{ once: true }});
+        try {{
+            return send.apply(this, args);
+        }} catch (error) {{
+            this.removeEventListener('loadend', finished);
+            finished();
+            throw error;
+        }}
+    }};
+    window.addEventListener('load', check);
+    check();
+}}"#
+    )
+}
+
+static LOGGING_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns the info-level logs for every webview's creation, navigation, and teardown on or off,
+/// e.g. to quiet them when the agent churns through many webviews. Warnings and errors are always
+/// logged. Enabled by default.
+pub fn set_webview_logging(enabled: bool) {
+    LOGGING_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+fn logging_enabled() -> bool {
+    LOGGING_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Counts live webviews against a limit.
+struct WebViewSlots {
+    active: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl WebViewSlots {
+    const fn new() -> Self {
+        Self {
+            active: AtomicUsize::new(0),
+            max: AtomicUsize::new(usize::MAX),
         }
     }
 
-    #[cfg(target_os = "macos")]
-    pub fn navigate(&mut self, url: &str) {
-        if self.current_url == url {
-            return;
+    fn set_max(&self, limit: Option<usize>) {
+        self.max
+            .store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    fn acquire(&'static self) -> Result<WebViewSlot> {
+        let limit = self.max.load(Ordering::Relaxed);
+        self.active
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < limit).then_some(count + 1)
+            })
+            .map_err(|_| anyhow::anyhow!("cannot open more than {limit} webviews at once"))?;
+        Ok(WebViewSlot(self))
+    }
+}
+
+/// The slots of every webview in the app.
+static WEBVIEW_SLOTS: WebViewSlots = WebViewSlots::new();
+
+/// Makes building a webview fail while `limit` webviews are alive across the whole app, guarding
+/// against runaway creation, e.g. by an agent stuck in a loop. `None`, the default, removes the
+/// limit. Webviews that are already open are unaffected.
+pub fn set_max_webviews(limit: Option<usize>) {
+    WEBVIEW_SLOTS.set_max(limit);
+}
+
+/// How many webviews are alive across the whole app.
+pub fn active_webview_count() -> usize {
+    WEBVIEW_SLOTS.active.load(Ordering::Relaxed)
+}
+
+/// Counts a live webview towards its [`WebViewSlots`] until dropped.
+struct WebViewSlot(&'static WebViewSlots);
+
+impl Drop for WebViewSlot {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Opens `url` in the system's default browser, for content that shouldn't be embedded. A URL
+/// without a scheme is treated as `https://`. Only `http`, `https`, and `mailto` URLs are opened,
+/// since pages can pick the URL and other schemes can launch local programs.
+pub fn open_in_default_browser(url: &str) -> Result<()> {
+    open_url(&external_url(url)?)
+}
+
+/// Schemes that [`open_in_default_browser`] hands to the system.
+const EXTERNAL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Normalizes `url`, failing unless its scheme is one of [`EXTERNAL_SCHEMES`].
+fn external_url(url: &str) -> Result<String> {
+    let normalized = normalize_url(url)?;
+    let scheme = url::Url::parse(&normalized)?.scheme().to_string();
+    anyhow::ensure!(
+        EXTERNAL_SCHEMES.contains(&scheme.as_str()),
+        "refusing to open {scheme}: URLs outside the webview"
+    );
+    Ok(normalized)
+}
+
+#[cfg(target_os = "macos")]
+fn open_url(url: &str) -> Result<()> {
+    unsafe {
+        let nsurl: id = msg_send![class!(NSURL), URLWithString: ns_string(url)];
+        anyhow::ensure!(nsurl != nil, "invalid URL {url:?}");
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let opened: bool = msg_send![workspace, openURL: nsurl];
+        anyhow::ensure!(opened, "failed to open {url} in the default browser");
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+fn open_url(url: &str) -> Result<()> {
+    anyhow::bail!("opening {url} in the default browser is not supported on this platform")
+}
+
+/// The total size of the files under `path`, or of `path` itself when it is a file, skipping
+/// anything that can't be read. Symlinks aren't followed.
+fn directory_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => 0,
+        };
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => directory_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Resolves `path` to an absolute file, returning it along with its percent-encoded `file://` URL.
+/// Fails when `path` doesn't exist or isn't a file.
+fn file_url(path: &Path) -> Result<(PathBuf, String)> {
+    let path = std::fs::canonicalize(path)
+        .map_err(|error| anyhow::anyhow!("can't load {}: {error}", path.display()))?;
+    anyhow::ensure!(path.is_file(), "can't load {}: not a file", path.display());
+    let url = url::Url::from_file_path(&path)
+        .map_err(|()| anyhow::anyhow!("can't load {}: invalid file path", path.display()))?;
+    Ok((path, url.into()))
+}
+
+/// Schemes that are used without an authority, such as `about:blank`.
+const SCHEMES_WITHOUT_AUTHORITY: &[&str] = &["about:", "blob:", "data:", "javascript:", "mailto:"];
+
+/// Whether `url` can be loaded, after prepending `https://` when it has no scheme.
+pub fn is_valid_url(url: &str) -> bool {
+    normalize_url(url).is_ok()
+}
+
+/// Prepends `https://` to URLs without a scheme, so `zed.dev` loads `https://zed.dev/`, and
+/// percent-encodes characters such as spaces that WebKit would otherwise reject.
+fn normalize_url(url: &str) -> Result<String> {
+    let url = url.trim();
+    anyhow::ensure!(!url.is_empty(), "URL is empty");
+    let has_scheme = url.contains("://")
+        || SCHEMES_WITHOUT_AUTHORITY.iter().any(|scheme| {
+            url.len() >= scheme.len() && url[..scheme.len()].eq_ignore_ascii_case(scheme)
+        });
+    let parsed = if has_scheme {
+        url::Url::parse(url)
+    } else {
+        url::Url::parse(&format!("https://{url}"))
+    };
+    parsed
+        .map(String::from)
+        .map_err(|error| anyhow::anyhow!("invalid URL {url:?}: {error}"))
+}
+
+/// Normalizes `url`, returning it unless it is already current. The current URL is loaded again
+/// when its last load failed. Every backend's `navigate` goes through this, and only calls
+/// [`record_navigation`] once the load has started, so that a load that couldn't start is retried
+/// by navigating again.
+fn navigation_target(
+    current_url: &str,
+    url: &str,
+    last_load_succeeded: bool,
+) -> Result<Option<String>> {
+    let url = normalize_url(url)?;
+    if current_url == url && last_load_succeeded {
+        return Ok(None);
+    }
+    Ok(Some(url))
+}
+
+/// Makes `url` the current URL and records it in `history`.
+fn record_navigation(current_url: &mut String, history: &mut NavigationHistory, url: &str) {
+    url.clone_into(current_url);
+    history.push(url.to_string());
+}
+
+/// How a webview's main-frame loads have ended so far. Shared by the native backends and
+/// [`MockWebViewBackend`], so that both follow the same rules. `S` is a queued script.
+#[cfg(any(
+    test,
+    feature = "test-support",
+    target_os = "macos",
+    target_os = "windows",
+    target_os = "linux"
+))]
+struct LoadState<S> {
+    /// Whether the most recent main-frame load failed, so navigating to its URL should retry it.
+    last_load_failed: bool,
+    /// Set when the page was reloaded after a crash, until a load finishes, so a page that keeps
+    /// crashing while loading isn't reloaded forever.
+    reloaded_after_crash: bool,
+    /// Set once the first main-frame load ends, successfully or not.
+    has_loaded: bool,
+    /// Scripts evaluated before `has_loaded` was set, run once it is.
+    deferred_scripts: Vec<S>,
+}
+
+#[cfg(any(
+    test,
+    feature = "test-support",
+    target_os = "macos",
+    target_os = "windows",
+    target_os = "linux"
+))]
+impl<S> Default for LoadState<S> {
+    fn default() -> Self {
+        Self {
+            last_load_failed: false,
+            reloaded_after_crash: false,
+            has_loaded: false,
+            deferred_scripts: Vec::new(),
         }
+    }
+}
 
-        self.current_url = url.to_string();
+#[cfg(any(
+    test,
+    feature = "test-support",
+    target_os = "macos",
+    target_os = "windows",
+    target_os = "linux"
+))]
+impl<S> LoadState<S> {
+    /// Records a main-frame load ending, returning the scripts queued until the first one did.
+    fn end_load(&mut self, succeeded: bool) -> Vec<S> {
+        self.last_load_failed = !succeeded;
+        if succeeded {
+            self.reloaded_after_crash = false;
+        }
+        self.has_loaded = true;
+        std::mem::take(&mut self.deferred_scripts)
+    }
 
-        unsafe {
-            let url_string = NSString::alloc(nil).init_str(url);
-            let nsurl: id = msg_send![class!(NSURL), URLWithString:url_string];
-            let request: id = msg_send![class!(NSURLRequest), requestWithURL:nsurl];
-            let _: () = msg_send![self.ns_webview, loadRequest:request];
-            log::info!("WebView navigated to: {}", url);
+    /// Records a navigation policy cancelling a main-frame navigation. Nothing else ends a first
+    /// load that was cancelled, so it counts as failed and the queued scripts are returned to run
+    /// in the page that stays.
+    fn cancel_load(&mut self) -> Vec<S> {
+        if self.has_loaded {
+            return Vec::new();
         }
+        self.end_load(false)
     }
 
-    #[cfg(target_os = "macos")]
-    pub fn set_hidden(&self, hidden: bool) {
-        unsafe {
-            if hidden {
-                let _: () = msg_send![self.floating_window, orderOut:nil];
-            } else {
-                let _: () = msg_send![self.floating_window, makeKeyAndOrderFront:nil];
-            }
+    /// Records the content process terminating. Returns whether to reload the page, which a crash
+    /// handler replaces when `handled`, and the queued scripts when no reload will end the first
+    /// load.
+    fn crash(&mut self, handled: bool) -> (bool, Vec<S>) {
+        let reload = !handled && !std::mem::replace(&mut self.reloaded_after_crash, true);
+        if reload || self.has_loaded {
+            return (reload, Vec::new());
         }
+        (false, self.end_load(false))
     }
+}
 
-    #[cfg(target_os = "macos")]
-    pub fn is_visible(&self) -> bool {
-        unsafe {
-            let visible: bool = msg_send![self.floating_window, isVisible];
-            visible
+/// The frame last requested while a window was hidden, which not every platform applies to hidden
+/// windows, kept to be applied once it is shown. Shared by the native backends and
+/// [`MockWebViewBackend`].
+#[cfg(any(
+    test,
+    feature = "test-support",
+    target_os = "macos",
+    target_os = "windows",
+    target_os = "linux"
+))]
+#[derive(Default)]
+struct PendingBounds(std::cell::Cell<Option<Bounds<Pixels>>>);
+
+#[cfg(any(
+    test,
+    feature = "test-support",
+    target_os = "macos",
+    target_os = "windows",
+    target_os = "linux"
+))]
+impl PendingBounds {
+    fn remember_if_hidden(&self, bounds: Bounds<Pixels>, hidden: bool) {
+        if hidden {
+            self.0.set(Some(bounds));
         }
     }
+
+    /// Returns the bounds to apply after showing the window, if any were set while it was hidden.
+    fn take(&self) -> Option<Bounds<Pixels>> {
+        self.0.take()
+    }
 }
 
-impl Drop for WebViewManager {
-    fn drop(&mut self) {
-        #[cfg(target_os = "macos")]
-        unsafe {
-            log::info!("Cleaning up floating webview window");
-            let visible: bool = msg_send![self.floating_window, isVisible];
-            if visible {
-                let _: () = msg_send![self.floating_window, close];
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The error code passed to [`WebViewManager::on_navigation_failed`] when a navigation times out:
+/// `NSURLErrorTimedOut` on macOS and `COREWEBVIEW2_WEB_ERROR_STATUS_TIMEOUT` on Windows.
+#[cfg(not(target_os = "windows"))]
+pub const NAVIGATION_TIMED_OUT: i64 = -1001;
+#[cfg(target_os = "windows")]
+pub const NAVIGATION_TIMED_OUT: i64 = 7;
+
+/// URLs loaded through the manager, oldest first. Consecutive duplicates are collapsed and the
+/// oldest entries are dropped beyond `limit`.
+struct NavigationHistory {
+    entries: Vec<String>,
+    limit: usize,
+}
+
+impl NavigationHistory {
+    fn new(initial_url: &str) -> Self {
+        let mut history = Self {
+            entries: Vec::new(),
+            limit: DEFAULT_HISTORY_LIMIT,
+        };
+        history.push(initial_url.to_string());
+        history
+    }
+
+    fn push(&mut self, url: String) {
+        if self.entries.last() != Some(&url) {
+            self.entries.push(url);
+            self.truncate();
+        }
+    }
+
+    fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.truncate();
+    }
+
+    /// Replaces every entry, keeping only the newest ones that fit the limit.
+    fn replace(&mut self, entries: Vec<String>) {
+        self.entries = entries;
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.limit);
+        self.entries.drain(..excess);
+    }
+}
+
+/// A snapshot of a webview taken with [`WebViewManager::session_state`], for restoring it with
+/// [`WebViewManager::restore_session`] after a restart.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WebViewSession {
+    pub url: String,
+    /// See [`WebViewManager::history`].
+    pub history: Vec<String>,
+    /// `None` until the window has been moved or resized.
+    pub bounds: Option<Bounds<Pixels>>,
+    pub visible: bool,
+    /// WebKit's opaque `interactionState`, which also restores the scroll position and the native
+    /// back-forward list. Only captured on macOS 12 and later.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interaction_state: Option<Vec<u8>>,
+}
+
+const MIN_ZOOM: f64 = 0.25;
+const MAX_ZOOM: f64 = 5.0;
+
+/// Clamps a page zoom factor to the range every backend renders sensibly.
+fn clamp_zoom(factor: f64) -> f64 {
+    if factor.is_nan() {
+        1.0
+    } else {
+        factor.clamp(MIN_ZOOM, MAX_ZOOM)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AnchorSide {
+    Below,
+    Right,
+}
+
+/// Returns the origin for a window of `size` placed `gap` away from `anchor` on `side`. When it
+/// doesn't fit in `work_area` it goes on the opposite side, and is then clamped inside the area.
+fn anchored_origin(
+    anchor: Bounds<Pixels>,
+    size: Size<Pixels>,
+    gap: Pixels,
+    side: AnchorSide,
+    work_area: Option<Bounds<Pixels>>,
+) -> Point<Pixels> {
+    let mut origin = match side {
+        AnchorSide::Below => point(anchor.left(), anchor.bottom() + gap),
+        AnchorSide::Right => point(anchor.right() + gap, anchor.top()),
+    };
+    let Some(area) = work_area else {
+        return origin;
+    };
+    match side {
+        AnchorSide::Below if origin.y + size.height > area.bottom() => {
+            origin.y = anchor.top() - gap - size.height;
+        }
+        AnchorSide::Right if origin.x + size.width > area.right() => {
+            origin.x = anchor.left() - gap - size.width;
+        }
+        _ => {}
+    }
+    point(
+        clamp_span(origin.x, size.width, area.left(), area.right()),
+        clamp_span(origin.y, size.height, area.top(), area.bottom()),
+    )
+}
+
+/// Moves a span starting at `start` inside `min..max`. A span longer than the range keeps its
+/// start visible.
+fn clamp_span(start: Pixels, length: Pixels, min: Pixels, max: Pixels) -> Pixels {
+    let start = if start + length > max {
+        max - length
+    } else {
+        start
+    };
+    if start < min { min } else { start }
+}
+
+/// Converts a y coordinate between gpui's top-left origin and AppKit's bottom-left origin.
+/// The conversion is its own inverse.
+#[cfg(any(test, target_os = "macos"))]
+fn flip_y(y: f64, height: f64, screen_height: f64) -> f64 {
+    screen_height - y - height
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn ns_string(string: &str) -> id {
+    unsafe { NSString::alloc(nil).init_str(string).autorelease() }
+}
+
+/// Converts an Objective-C object into a Rust string via its `description`, treating nil as empty.
+#[cfg(target_os = "macos")]
+unsafe fn string_from_ns(object: id) -> String {
+    unsafe {
+        if object == nil {
+            return String::new();
+        }
+        let description: id = msg_send![object, description];
+        let cstr = description.UTF8String();
+        if cstr.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(cstr).to_string_lossy().into_owned()
+        }
+    }
+}
+
+/// Untitled documents report a nil title, which is returned as an empty string.
+#[cfg(target_os = "macos")]
+unsafe fn webview_title(webview: id) -> String {
+    unsafe {
+        let title: id = msg_send![webview, title];
+        string_from_ns(title)
+    }
+}
+
+/// Describes the running macOS version for error messages, e.g. `Version 14.4 (Build 23E214)`.
+#[cfg(target_os = "macos")]
+fn macos_version() -> String {
+    unsafe {
+        let process_info: id = msg_send![class!(NSProcessInfo), processInfo];
+        let version: id = msg_send![process_info, operatingSystemVersionString];
+        string_from_ns(version)
+    }
+}
+
+/// Creates an autoreleased `nw_proxy_config_t`. Its functions only exist on macOS 14 and later, so
+/// they're looked up at runtime rather than linked, which would fail to launch on older versions.
+#[cfg(target_os = "macos")]
+unsafe fn nw_proxy_config(proxy: &ProxyConfig) -> Result<id> {
+    type EndpointCreateHost = unsafe extern "C" fn(*const c_char, *const c_char) -> id;
+    type ProxyConfigCreateHttpConnect = unsafe extern "C" fn(id, id) -> id;
+    type ProxyConfigSetUsernameAndPassword = unsafe extern "C" fn(id, *const c_char, *const c_char);
+    unsafe {
+        let network = libc::dlopen(
+            c"/System/Library/Frameworks/Network.framework/Network".as_ptr(),
+            libc::RTLD_LAZY,
+        );
+        anyhow::ensure!(!network.is_null(), "Network framework not found");
+        let symbol = |name: &CStr| {
+            let symbol = libc::dlsym(network, name.as_ptr());
+            (!symbol.is_null()).then_some(symbol)
+        };
+        let (Some(create_host), Some(create_http_connect), Some(set_username_and_password)) = (
+            symbol(c"nw_endpoint_create_host"),
+            symbol(c"nw_proxy_config_create_http_connect"),
+            symbol(c"nw_proxy_config_set_username_and_password"),
+        ) else {
+            anyhow::bail!(
+                "webview proxies require macOS 14 or later (running macOS {})",
+                macos_version()
+            );
+        };
+        let create_host: EndpointCreateHost = std::mem::transmute(create_host);
+        let create_http_connect: ProxyConfigCreateHttpConnect =
+            std::mem::transmute(create_http_connect);
+        let set_username_and_password: ProxyConfigSetUsernameAndPassword =
+            std::mem::transmute(set_username_and_password);
+
+        let host = CString::new(proxy.host.as_str())?;
+        let port = CString::new(proxy.port.to_string())?;
+        let endpoint = create_host(host.as_ptr(), port.as_ptr());
+        anyhow::ensure!(endpoint != nil, "invalid proxy host {}", proxy.host);
+        let config = create_http_connect(endpoint, nil);
+        let _: () = msg_send![endpoint, release];
+        anyhow::ensure!(config != nil, "failed to create proxy configuration");
+        if let Some((username, password)) = &proxy.credentials {
+            let username = CString::new(username.as_str())?;
+            let password = CString::new(password.as_str())?;
+            set_username_and_password(config, username.as_ptr(), password.as_ptr());
+        }
+        Ok(msg_send![config, autorelease])
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn webkit_class(name: &str) -> Result<&'static Class> {
+    Class::get(name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "{name} class not found on macOS {}; is WebKit available?",
+            macos_version()
+        )
+    })
+}
+
+/// Returns the screen `selector` picks, falling back to the main screen. Only nil when there are
+/// no screens at all.
+#[cfg(target_os = "macos")]
+unsafe fn select_screen(selector: ScreenSelector, parent_view: id) -> id {
+    unsafe {
+        let main_screen: id = msg_send![class!(NSScreen), mainScreen];
+        let screen: id = match selector {
+            ScreenSelector::Parent if parent_view != nil => {
+                let window: id = msg_send![parent_view, window];
+                if window != nil {
+                    msg_send![window, screen]
+                } else {
+                    nil
+                }
             }
-            let _: () = msg_send![self.floating_window, release];
+            ScreenSelector::Parent | ScreenSelector::Main => nil,
+            ScreenSelector::ContainingPoint(point) if main_screen != nil => {
+                let main_frame: NSRect = msg_send![main_screen, frame];
+                let x = f64::from(point.x);
+                let y = flip_y(point.y.into(), 0.0, main_frame.size.height);
+                let screens: id = msg_send![class!(NSScreen), screens];
+                let count: usize = msg_send![screens, count];
+                (0..count)
+                    .map(|index| -> id { msg_send![screens, objectAtIndex: index] })
+                    .find(|&screen| {
+                        let frame: NSRect = msg_send![screen, frame];
+                        x >= frame.origin.x
+                            && x < frame.origin.x + frame.size.width
+                            && y >= frame.origin.y
+                            && y < frame.origin.y + frame.size.height
+                    })
+                    .unwrap_or(nil)
+            }
+            ScreenSelector::ContainingPoint(_) => nil,
+        };
+        if screen != nil { screen } else { main_screen }
+    }
+}
+
+/// Sets whether pages loaded with `configuration` run their own scripts. Scripts evaluated through
+/// the manager still run.
+#[cfg(target_os = "macos")]
+unsafe fn configure_javascript(configuration: id, enabled: bool) {
+    unsafe {
+        let enabled = if enabled { YES } else { NO };
+        let webpage_preferences: id = msg_send![configuration, defaultWebpagePreferences];
+        let responds: bool = msg_send![
+            webpage_preferences,
+            respondsToSelector: sel!(setAllowsContentJavaScript:)
+        ];
+        if responds {
+            let _: () = msg_send![webpage_preferences, setAllowsContentJavaScript: enabled];
+        } else {
+            // Before macOS 11, this also disables scripts evaluated through the manager.
+            let preferences: id = msg_send![configuration, preferences];
+            let _: () = msg_send![preferences, setJavaScriptEnabled: enabled];
         }
     }
 }
 
-#[cfg(not(target_os = "macos"))]
-impl WebViewManager {
-    pub fn new(_: *mut std::ffi::c_void, _bounds: Bounds<Pixels>, url: &str) -> Result<Self> {
-        Ok(Self {
-            current_url: url.to_string(),
-        })
+#[cfg(target_os = "macos")]
+unsafe fn configure_fraudulent_website_warning(configuration: id, enabled: bool) {
+    unsafe {
+        let enabled = if enabled { YES } else { NO };
+        let preferences: id = msg_send![configuration, preferences];
+        let _: () = msg_send![preferences, setFraudulentWebsiteWarningEnabled: enabled];
+    }
+}
+
+/// Returns the window frame in gpui's logical screen coordinates.
+#[cfg(target_os = "macos")]
+unsafe fn window_bounds(window: id) -> Bounds<Pixels> {
+    unsafe {
+        let screen: id = msg_send![class!(NSScreen), mainScreen];
+        let screen_frame: NSRect = msg_send![screen, frame];
+        let frame: NSRect = msg_send![window, frame];
+
+        let y = flip_y(frame.origin.y, frame.size.height, screen_frame.size.height);
+        Bounds {
+            origin: point(px(frame.origin.x as f32), px(y as f32)),
+            size: size(px(frame.size.width as f32), px(frame.size.height as f32)),
+        }
     }
+}
 
-    pub fn navigate(&mut self, url: &str) {
-        self.current_url = url.to_string();
+/// Returns an autoreleased `NSMutableURLRequest` for `url`, or an error when `NSURL` rejects it.
+#[cfg(target_os = "macos")]
+unsafe fn mutable_request(url: &str, cache_policy: CachePolicy, timeout: Duration) -> Result<id> {
+    unsafe {
+        let nsurl: id = msg_send![class!(NSURL), URLWithString: ns_string(url)];
+        anyhow::ensure!(nsurl != nil, "invalid URL {url:?}");
+        let request: id = msg_send![class!(NSMutableURLRequest), requestWithURL: nsurl];
+        let _: () = msg_send![request, setCachePolicy: cache_policy.ns_url_request_cache_policy()];
+        let _: () = msg_send![request, setTimeoutInterval: timeout.as_secs_f64()];
+        Ok(request)
     }
+}
 
-    pub fn set_hidden(&self, _hidden: bool) {}
+#[cfg(target_os = "macos")]
+unsafe fn error_from_ns(error: id) -> anyhow::Error {
+    unsafe {
+        let code: i64 = msg_send![error, code];
+        let description: id = msg_send![error, localizedDescription];
+        anyhow::anyhow!("{} (code {})", string_from_ns(description), code)
+    }
+}
 
-    pub fn is_visible(&self) -> bool {
-        false
+#[cfg(target_os = "macos")]
+unsafe fn vec_from_ns_data(data: id) -> Vec<u8> {
+    unsafe {
+        let bytes: *const u8 = msg_send![data, bytes];
+        let length: usize = msg_send![data, length];
+        if bytes.is_null() {
+            return Vec::new();
+        }
+        std::slice::from_raw_parts(bytes, length).to_vec()
+    }
+}
+
+/// Snapshots `webview` as PNG bytes. A nil `configuration` captures the visible page.
+#[cfg(target_os = "macos")]
+unsafe fn snapshot_webview(
+    webview: id,
+    configuration: id,
+    callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+) {
+    unsafe {
+        let callback = Cell::new(Some(callback));
+        let completion_handler = ConcreteBlock::new(move |image: id, error: id| {
+            if let Some(callback) = callback.take() {
+                if image == nil {
+                    callback(Err(if error == nil {
+                        anyhow::anyhow!("snapshot returned no image")
+                    } else {
+                        error_from_ns(error)
+                    }));
+                } else {
+                    callback(png_from_ns_image(image));
+                }
+            }
+        });
+        let completion_handler = completion_handler.copy();
+        let _: () = msg_send![
+            webview,
+            takeSnapshotWithConfiguration: configuration
+            completionHandler: completion_handler
+        ];
+    }
+}
+
+/// Encodes an `NSImage` as PNG.
+#[cfg(target_os = "macos")]
+unsafe fn png_from_ns_image(image: id) -> Result<Vec<u8>> {
+    unsafe {
+        const NS_BITMAP_IMAGE_FILE_TYPE_PNG: u64 = 4;
+
+        let tiff: id = msg_send![image, TIFFRepresentation];
+        if tiff == nil {
+            anyhow::bail!("snapshot has no bitmap representation");
+        }
+        let bitmap: id = msg_send![class!(NSBitmapImageRep), imageRepWithData: tiff];
+        if bitmap == nil {
+            anyhow::bail!("failed to decode snapshot bitmap");
+        }
+        let properties: id = msg_send![class!(NSDictionary), dictionary];
+        let png: id = msg_send![
+            bitmap,
+            representationUsingType: NS_BITMAP_IMAGE_FILE_TYPE_PNG
+            properties: properties
+        ];
+        if png == nil {
+            anyhow::bail!("failed to encode snapshot as PNG");
+        }
+        Ok(vec_from_ns_data(png))
+    }
+}
+
+/// Credentials answering HTTP basic auth challenges from one server, set by `set_basic_auth`.
+#[cfg(any(test, target_os = "macos", target_os = "windows", target_os = "linux"))]
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+#[derive(Clone)]
+struct BasicAuth {
+    host: String,
+    /// Any port when `None`.
+    port: Option<u16>,
+    username: String,
+    password: String,
+}
+
+#[cfg(any(test, target_os = "macos", target_os = "windows", target_os = "linux"))]
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+impl BasicAuth {
+    /// Whether a challenge from `host` and `port` should be answered with these credentials.
+    fn matches(&self, host: &str, port: u16) -> bool {
+        self.host.eq_ignore_ascii_case(host) && self.port.is_none_or(|own_port| own_port == port)
+    }
+}
+
+/// A call queued until the first load ends.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+enum DeferredScript {
+    /// A script and the callback for its result, if the caller wants one.
+    Evaluate(String, Option<Box<dyn FnOnce(Result<String>)>>),
+    /// A user script, installed for later pages and run once in the page that loaded.
+    UserScript {
+        source: String,
+        at_document_start: bool,
+    },
+}
+
+/// Rust-side state shared with the native event handlers. On macOS the Objective-C delegate
+/// object holds its own strong reference to it in the `webViewState` ivar. Linux only reports
+/// some of these events so far.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+#[derive(Default)]
+struct WebViewState {
+    did_finish_navigation: Option<Box<dyn FnMut(String)>>,
+    navigation_failed: Option<Box<dyn FnMut(String, i64)>>,
+    message_handlers: HashMap<String, Box<dyn FnMut(String)>>,
+    progress: Option<Box<dyn FnMut(f64)>>,
+    title_changed: Option<Box<dyn FnMut(String)>>,
+    /// The document title last reported by the native title change event, until the manager
+    /// picks it up.
+    new_title: Option<String>,
+    navigation_policy: Option<Box<dyn FnMut(&str) -> NavigationDecision>>,
+    new_window: Option<Box<dyn FnMut(String)>>,
+    download: Option<Box<dyn FnMut(String) -> Option<PathBuf>>>,
+    download_finished: Option<Box<dyn FnMut(Result<PathBuf>)>>,
+    close: Option<Box<dyn FnOnce()>>,
+    bounds_changed: Option<Box<dyn FnMut(Bounds<Pixels>)>>,
+    js_alert: Option<Box<dyn FnMut(String)>>,
+    js_confirm: Option<Box<dyn FnMut(String) -> bool>>,
+    js_prompt: Option<Box<dyn FnMut(String, String) -> Option<String>>>,
+    permission_request: Option<Box<dyn FnMut(PermissionKind, String) -> bool>>,
+    auth_challenge: Option<Box<dyn FnMut(AuthChallenge) -> AuthResponse>>,
+    basic_auth: Option<BasicAuth>,
+    #[cfg(target_os = "macos")]
+    context_menu: Option<Box<dyn FnMut(ContextMenuContext) -> Vec<MenuItem>>>,
+    /// Handlers for the custom items in the open context menu, indexed by each item's tag.
+    #[cfg(target_os = "macos")]
+    context_menu_actions: Vec<Box<dyn FnOnce()>>,
+    /// Shortcuts forwarded to the host view instead of the webview.
+    #[cfg(target_os = "macos")]
+    key_passthrough: Vec<Keystroke>,
+    #[cfg(target_os = "macos")]
+    keep_open_on_cmd_w: bool,
+    /// WebKit has no public mute API, so the mute script is applied again on every page load.
+    #[cfg(target_os = "macos")]
+    muted: bool,
+    #[cfg(target_os = "macos")]
+    fullscreen_restore: FullscreenRestore<NSRect>,
+    /// Set again in every page that finishes loading, since the flag lives in the page.
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    context_menu_disabled: bool,
+    /// Bumped by every show or hide, so a fade-out that finishes after the window was shown again
+    /// doesn't order it out.
+    #[cfg(target_os = "macos")]
+    fade_generation: u64,
+    /// Handlers registered with `WebViewManagerBuilder::register_scheme_handler`, by scheme.
+    #[cfg(target_os = "macos")]
+    scheme_handlers: HashMap<String, Box<dyn FnMut(SchemeRequest) -> SchemeResponse>>,
+    crash: Option<Box<dyn FnMut()>>,
+    last_reported_bounds: Option<Bounds<Pixels>>,
+    load: LoadState<DeferredScript>,
+    /// The URL the page last finished loading, when it hasn't been picked up by the manager yet.
+    /// Differs from the requested URL after redirects and links followed in the page.
+    page_url: Option<String>,
+    /// Resolves the future returned by `navigate_async` when the next main-frame load ends.
+    pending_navigation: Option<oneshot::Sender<Result<()>>>,
+    /// The URL `navigate_async` waits for. WebKitGTK doesn't say whether a navigation is for the
+    /// main frame, so only a navigation policy cancelling this URL fails it.
+    #[cfg(target_os = "linux")]
+    pending_navigation_url: String,
+    /// Counts the loads requested through the manager, so that events the GTK thread reports for
+    /// earlier loads can be told apart.
+    #[cfg(target_os = "linux")]
+    requested_load: u64,
+    /// Destinations of in-flight downloads, keyed by the `WKDownload` pointer.
+    #[cfg(target_os = "macos")]
+    downloads: HashMap<usize, PathBuf>,
+    /// WebView2 doesn't report intermediate progress, so this only tracks whether the last
+    /// navigation has completed.
+    #[cfg(target_os = "windows")]
+    estimated_progress: f64,
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+impl WebViewState {
+    /// Calls the handler in `slot`, if any. The handler is taken out of the state for the
+    /// duration of the call so that re-entering the manager doesn't find the state borrowed.
+    fn invoke<H: ?Sized, R>(
+        this: &RefCell<Self>,
+        slot: fn(&mut Self) -> &mut Option<Box<H>>,
+        call: impl FnOnce(&mut H) -> R,
+    ) -> Option<R> {
+        let mut handler = slot(&mut this.borrow_mut()).take()?;
+        let result = call(&mut handler);
+        // A handler registered during the call replaces the one that was running.
+        slot(&mut this.borrow_mut()).get_or_insert(handler);
+        Some(result)
+    }
+
+    /// Records `title` for `WebViewManager::title` and calls the title change handler.
+    fn update_title(this: &RefCell<Self>, title: String) {
+        this.borrow_mut().new_title = Some(title.clone());
+        Self::invoke(
+            this,
+            |state| &mut state.title_changed,
+            |handler| handler(title),
+        );
+    }
+
+    /// Navigations are allowed when no policy is set.
+    fn decide_navigation(this: &RefCell<Self>, url: &str) -> NavigationDecision {
+        Self::invoke(
+            this,
+            |state| &mut state.navigation_policy,
+            |policy| policy(url),
+        )
+        .unwrap_or(NavigationDecision::Allow)
+    }
+
+    /// Asks the download handler where to save `suggested_filename`. Returns `None` to cancel.
+    fn download_destination(this: &RefCell<Self>, suggested_filename: String) -> Option<PathBuf> {
+        Self::invoke(
+            this,
+            |state| &mut state.download,
+            |handler| handler(suggested_filename),
+        )
+        .flatten()
+    }
+
+    fn finish_download(this: &RefCell<Self>, result: Result<PathBuf>) {
+        if let Err(error) = &result {
+            log::error!("WebView download failed: {error}");
+        }
+        Self::invoke(
+            this,
+            |state| &mut state.download_finished,
+            |handler| handler(result),
+        );
+    }
+
+    /// Skips bounds identical to the last ones reported, since moves and resizes often arrive in
+    /// pairs.
+    fn report_bounds(this: &RefCell<Self>, bounds: Bounds<Pixels>) {
+        if this.borrow_mut().last_reported_bounds.replace(bounds) == Some(bounds) {
+            return;
+        }
+        Self::invoke(
+            this,
+            |state| &mut state.bounds_changed,
+            |handler| handler(bounds),
+        );
+    }
+
+    fn run_js_alert(this: &RefCell<Self>, message: String) {
+        Self::invoke(
+            this,
+            |state| &mut state.js_alert,
+            |handler| handler(message),
+        );
+    }
+
+    /// Confirms when no handler is registered.
+    fn run_js_confirm(this: &RefCell<Self>, message: String) -> bool {
+        Self::invoke(
+            this,
+            |state| &mut state.js_confirm,
+            |handler| handler(message),
+        )
+        .unwrap_or(true)
+    }
+
+    /// Answers with the default text when no handler is registered. `None` cancels the prompt.
+    fn run_js_prompt(
+        this: &RefCell<Self>,
+        message: String,
+        default_text: String,
+    ) -> Option<String> {
+        Self::invoke(
+            this,
+            |state| &mut state.js_prompt,
+            |handler| handler(message, default_text.clone()),
+        )
+        .unwrap_or(Some(default_text))
+    }
+
+    /// Permissions are denied when no handler is registered.
+    fn decide_permission(this: &RefCell<Self>, kind: PermissionKind, origin: String) -> bool {
+        Self::invoke(
+            this,
+            |state| &mut state.permission_request,
+            |handler| handler(kind, origin),
+        )
+        .unwrap_or(false)
+    }
+
+    /// Challenges get the platform's default handling when no handler is registered.
+    fn respond_to_auth_challenge(this: &RefCell<Self>, challenge: AuthChallenge) -> AuthResponse {
+        Self::invoke(
+            this,
+            |state| &mut state.auth_challenge,
+            |handler| handler(challenge),
+        )
+        .unwrap_or_default()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn context_menu_items(this: &RefCell<Self>, context: ContextMenuContext) -> Vec<MenuItem> {
+        Self::invoke(
+            this,
+            |state| &mut state.context_menu,
+            |handler| handler(context),
+        )
+        .unwrap_or_default()
+    }
+
+    /// Called whenever a main-frame load ends. Returns the scripts deferred until the first load
+    /// ended, for the caller to evaluate.
+    fn finish_pending_navigation(this: &RefCell<Self>, result: Result<()>) -> Vec<DeferredScript> {
+        let (sender, deferred_scripts) = {
+            let mut state = this.borrow_mut();
+            let deferred_scripts = state.load.end_load(result.is_ok());
+            (state.pending_navigation.take(), deferred_scripts)
+        };
+        if let Some(sender) = sender {
+            sender.send(result).ok();
+        }
+        deferred_scripts
+    }
+
+    /// Called when a navigation policy cancels a main-frame navigation. Returns the scripts
+    /// deferred until the first load ended, for the caller to evaluate, when the cancelled
+    /// navigation was that load.
+    fn cancel_pending_navigation(this: &RefCell<Self>, url: &str) -> Vec<DeferredScript> {
+        let (sender, deferred_scripts) = {
+            let mut state = this.borrow_mut();
+            let deferred_scripts = state.load.cancel_load();
+            (state.pending_navigation.take(), deferred_scripts)
+        };
+        if let Some(sender) = sender {
+            sender
+                .send(Err(anyhow::anyhow!("navigation to {url} was cancelled")))
+                .ok();
+        }
+        deferred_scripts
+    }
+
+    /// Calls the crash handler, or returns whether the page should be reloaded when none is set.
+    /// When no reload will end the first load, the scripts waiting for it fail, and the user
+    /// scripts among them are returned to be installed for later pages.
+    fn handle_crash(this: &RefCell<Self>) -> (bool, Vec<DeferredScript>) {
+        log::error!("WebView content process terminated");
+        let handled = Self::invoke(this, |state| &mut state.crash, |handler| handler()).is_some();
+        let (reload, abandoned, sender) = {
+            let mut state = this.borrow_mut();
+            let (reload, abandoned) = state.load.crash(handled);
+            let sender = (!reload).then(|| state.pending_navigation.take()).flatten();
+            (reload, abandoned, sender)
+        };
+        if let Some(sender) = sender {
+            sender
+                .send(Err(anyhow::anyhow!(
+                    "the page's content process terminated"
+                )))
+                .ok();
+        }
+        let mut user_scripts = Vec::new();
+        for script in abandoned {
+            match script {
+                DeferredScript::Evaluate(_, Some(callback)) => callback(Err(anyhow::anyhow!(
+                    "the page's content process terminated before it loaded"
+                ))),
+                DeferredScript::Evaluate(_, None) => {}
+                user_script @ DeferredScript::UserScript { .. } => user_scripts.push(user_script),
+            }
+        }
+        (reload, user_scripts)
+    }
+
+    fn notify_closed(this: &RefCell<Self>) {
+        let close = this.borrow_mut().close.take();
+        if let Some(close) = close {
+            close();
+        }
+    }
+
+    fn invoke_message_handler(this: &RefCell<Self>, name: String, body: String) {
+        let handler = this.borrow_mut().message_handlers.remove(&name);
+        if let Some(mut handler) = handler {
+            handler(body);
+            this.borrow_mut()
+                .message_handlers
+                .entry(name)
+                .or_insert(handler);
+        } else {
+            log::warn!("WebView received a message for unregistered handler {name:?}");
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn handle_scheme_request(
+        this: &RefCell<Self>,
+        scheme: &str,
+        request: SchemeRequest,
+    ) -> SchemeResponse {
+        let handler = this.borrow_mut().scheme_handlers.remove(scheme);
+        let Some(mut handler) = handler else {
+            log::warn!(
+                "WebView requested {} with no handler for its scheme",
+                request.url
+            );
+            return SchemeResponse::not_found();
+        };
+        let response = handler(request);
+        this.borrow_mut()
+            .scheme_handlers
+            .entry(scheme.to_string())
+            .or_insert(handler);
+        response
+    }
+}
+
+/// Lazily declares the `WKWebView` subclass that opens dropped files when file drops are
+/// enabled, leaving every other drag to WebKit. Fails when another class already took its name.
+#[cfg(target_os = "macos")]
+fn webview_class() -> Result<&'static Class> {
+    static WEBVIEW_CLASS: OnceLock<Option<&'static Class>> = OnceLock::new();
+    let class = match WEBVIEW_CLASS.get() {
+        Some(class) => *class,
+        None => {
+            let superclass = webkit_class("WKWebView")?;
+            *WEBVIEW_CLASS.get_or_init(|| declare_webview_class(superclass))
+        }
+    };
+    class.ok_or_else(|| anyhow::anyhow!("failed to declare the ZedWKWebView class"))
+}
+
+#[cfg(target_os = "macos")]
+fn declare_webview_class(superclass: &Class) -> Option<&'static Class> {
+    unsafe {
+        let mut decl = ClassDecl::new("ZedWKWebView", superclass)?;
+        decl.add_ivar::<BOOL>(ACCEPTS_FILE_DROPS_IVAR);
+        decl.add_ivar::<id>(PARENT_VIEW_IVAR);
+        decl.add_method(
+            sel!(performKeyEquivalent:),
+            perform_key_equivalent as extern "C" fn(&Object, Sel, id) -> BOOL,
+        );
+        decl.add_method(
+            sel!(draggingEntered:),
+            dragging_entered as extern "C" fn(&Object, Sel, id) -> usize,
+        );
+        decl.add_method(
+            sel!(draggingUpdated:),
+            dragging_updated as extern "C" fn(&Object, Sel, id) -> usize,
+        );
+        decl.add_method(
+            sel!(performDragOperation:),
+            perform_drag_operation as extern "C" fn(&Object, Sel, id) -> BOOL,
+        );
+        decl.add_method(
+            sel!(willOpenMenu:withEvent:),
+            will_open_menu as extern "C" fn(&Object, Sel, id, id),
+        );
+        Some(decl.register())
+    }
+}
+
+/// The dropped file, if file drops are enabled and it's one the webview can display.
+#[cfg(target_os = "macos")]
+unsafe fn accepted_dropped_file(this: &Object, dragging_info: id) -> Option<PathBuf> {
+    unsafe {
+        let accepts: BOOL = *this.get_ivar(ACCEPTS_FILE_DROPS_IVAR);
+        if accepts == NO {
+            return None;
+        }
+        let pasteboard: id = msg_send![dragging_info, draggingPasteboard];
+        let classes: id = msg_send![class!(NSArray), arrayWithObject: class!(NSURL)];
+        let file_urls_only: id = msg_send![class!(NSNumber), numberWithBool: YES];
+        let options: id = msg_send![
+            class!(NSDictionary),
+            dictionaryWithObject: file_urls_only
+            forKey: ns_string("NSPasteboardURLReadingFileURLsOnlyKey")
+        ];
+        let urls: id = msg_send![pasteboard, readObjectsForClasses: classes options: options];
+        if urls == nil {
+            return None;
+        }
+        let url: id = msg_send![urls, firstObject];
+        if url == nil {
+            return None;
+        }
+        let path: id = msg_send![url, path];
+        let path = PathBuf::from(string_from_ns(path));
+        (path.is_file() && has_droppable_extension(&path)).then_some(path)
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn dragging_entered(this: &Object, _: Sel, dragging_info: id) -> usize {
+    const NS_DRAG_OPERATION_COPY: usize = 1;
+    unsafe {
+        if accepted_dropped_file(this, dragging_info).is_some() {
+            return NS_DRAG_OPERATION_COPY;
+        }
+        msg_send![super(this, class!(WKWebView)), draggingEntered: dragging_info]
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn dragging_updated(this: &Object, _: Sel, dragging_info: id) -> usize {
+    const NS_DRAG_OPERATION_COPY: usize = 1;
+    unsafe {
+        if accepted_dropped_file(this, dragging_info).is_some() {
+            return NS_DRAG_OPERATION_COPY;
+        }
+        msg_send![super(this, class!(WKWebView)), draggingUpdated: dragging_info]
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn perform_drag_operation(this: &Object, _: Sel, dragging_info: id) -> BOOL {
+    unsafe {
+        let Some(path) = accepted_dropped_file(this, dragging_info) else {
+            return msg_send![super(this, class!(WKWebView)), performDragOperation: dragging_info];
+        };
+        let file_url: id = msg_send![
+            class!(NSURL),
+            fileURLWithPath: ns_string(&path.to_string_lossy())
+        ];
+        let directory_url: id = msg_send![file_url, URLByDeletingLastPathComponent];
+        let _: id = msg_send![
+            this,
+            loadFileURL: file_url
+            allowingReadAccessToURL: directory_url
+        ];
+        webview_info!("WebView opened dropped file: {}", path.display());
+        YES
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn perform_key_equivalent(this: &Object, _: Sel, event: id) -> BOOL {
+    unsafe {
+        let delegate: id = msg_send![this, UIDelegate];
+        if delegate != nil {
+            let state = get_webview_state(&*delegate);
+            let modifier_flags: u64 = msg_send![event, modifierFlags];
+            let characters = string_from_ns(msg_send![event, charactersIgnoringModifiers]);
+            let unshifted_characters =
+                string_from_ns(msg_send![event, charactersByApplyingModifiers: 0u64]);
+            let mut modifiers = modifiers_from_ns(modifier_flags);
+            // Arrows and other function keys come with the function modifier, which gpui leaves
+            // out of their keystrokes.
+            if characters
+                .chars()
+                .next()
+                .is_some_and(|ch| ('\u{F700}'..='\u{F747}').contains(&ch))
+            {
+                modifiers.function = false;
+            }
+            let (passthrough, keep_open_on_cmd_w) = {
+                let state = state.borrow();
+                let passthrough = state.key_passthrough.iter().any(|keystroke| {
+                    keystroke_matches(keystroke, modifiers, &characters, &unshifted_characters)
+                });
+                (passthrough, state.keep_open_on_cmd_w)
+            };
+            let parent_view: id = *this.get_ivar(PARENT_VIEW_IVAR);
+            if passthrough && parent_view != nil {
+                return msg_send![parent_view, performKeyEquivalent: event];
+            }
+            if modifiers == Modifiers::command() && characters.eq_ignore_ascii_case("w") {
+                if !keep_open_on_cmd_w {
+                    let window: id = msg_send![this, window];
+                    let _: () = msg_send![window, performClose: nil];
+                }
+                return YES;
+            }
+        }
+        msg_send![super(this, class!(WKWebView)), performKeyEquivalent: event]
+    }
+}
+
+/// Whether `keystroke` is a key event with `modifiers` that macOS reports as `characters`
+/// ignoring modifiers, or as `unshifted_characters` ignoring shift too. Key names go through
+/// gpui's table, so that `cmd-up` and `cmd-shift-/` match like they do in keymaps.
+#[cfg(target_os = "macos")]
+fn keystroke_matches(
+    keystroke: &Keystroke,
+    modifiers: Modifiers,
+    characters: &str,
+    unshifted_characters: &str,
+) -> bool {
+    let native_key = match keystroke.key.as_str() {
+        "enter" => "\r".into(),
+        "tab" => "\t".into(),
+        key => gpui::key_to_native(key),
+    };
+    keystroke.modifiers == modifiers
+        && (native_key.eq_ignore_ascii_case(characters)
+            || native_key.eq_ignore_ascii_case(unshifted_characters))
+}
+
+/// Converts `NSEventModifierFlags` into gpui's modifiers.
+#[cfg(any(test, target_os = "macos"))]
+fn modifiers_from_ns(modifier_flags: u64) -> Modifiers {
+    Modifiers {
+        shift: modifier_flags & (1 << 17) != 0,
+        control: modifier_flags & (1 << 18) != 0,
+        alt: modifier_flags & (1 << 19) != 0,
+        platform: modifier_flags & (1 << 20) != 0,
+        function: modifier_flags & (1 << 23) != 0,
+    }
+}
+
+/// Replaces the native context menu with the items from the `on_context_menu` handler, unless it
+/// returns none.
+#[cfg(target_os = "macos")]
+extern "C" fn will_open_menu(this: &Object, _: Sel, menu: id, event: id) {
+    unsafe {
+        let _: () = msg_send![super(this, class!(WKWebView)), willOpenMenu: menu withEvent: event];
+        let delegate: id = msg_send![this, UIDelegate];
+        if delegate == nil {
+            return;
+        }
+        let state = get_webview_state(&*delegate);
+        let location: NSPoint = msg_send![event, locationInWindow];
+        // WKWebView is flipped, so this is relative to its top-left corner.
+        let location: NSPoint = msg_send![this, convertPoint: location fromView: nil];
+        let url: id = msg_send![this, URL];
+        let page_url = if url == nil {
+            String::new()
+        } else {
+            let absolute_string: id = msg_send![url, absoluteString];
+            string_from_ns(absolute_string)
+        };
+        let context = ContextMenuContext {
+            page_url,
+            position: point(px(location.x as f32), px(location.y as f32)),
+        };
+        let items = WebViewState::context_menu_items(&state, context);
+        if items.is_empty() {
+            return;
+        }
+
+        let _: () = msg_send![menu, removeAllItems];
+        let mut actions = Vec::new();
+        for item in items {
+            match item {
+                MenuItem::Action { title, handler } => {
+                    let menu_item: id = msg_send![class!(NSMenuItem), alloc];
+                    let menu_item: id = msg_send![
+                        menu_item,
+                        initWithTitle: ns_string(&title)
+                        action: sel!(contextMenuItemSelected:)
+                        keyEquivalent: ns_string("")
+                    ];
+                    let _: () = msg_send![menu_item, setTarget: delegate];
+                    let _: () = msg_send![menu_item, setTag: actions.len() as isize];
+                    let _: () = msg_send![menu, addItem: menu_item];
+                    let _: () = msg_send![menu_item, release];
+                    actions.push(handler);
+                }
+                MenuItem::Separator => {
+                    let separator: id = msg_send![class!(NSMenuItem), separatorItem];
+                    let _: () = msg_send![menu, addItem: separator];
+                }
+            }
+        }
+        state.borrow_mut().context_menu_actions = actions;
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn context_menu_item_selected(this: &Object, _: Sel, menu_item: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        let tag: isize = msg_send![menu_item, tag];
+        let actions = std::mem::take(&mut state.borrow_mut().context_menu_actions);
+        if let Some(action) = usize::try_from(tag)
+            .ok()
+            .and_then(|tag| actions.into_iter().nth(tag))
+        {
+            action();
+        }
+    }
+}
+
+/// Lazily declares the Objective-C class that receives WebKit delegate callbacks. Fails when
+/// another class already took its name.
+#[cfg(target_os = "macos")]
+fn delegate_class() -> Result<&'static Class> {
+    static DELEGATE_CLASS: OnceLock<Option<&'static Class>> = OnceLock::new();
+    DELEGATE_CLASS
+        .get_or_init(declare_delegate_class)
+        .ok_or_else(|| anyhow::anyhow!("failed to declare the ZedWebViewDelegate class"))
+}
+
+#[cfg(target_os = "macos")]
+fn declare_delegate_class() -> Option<&'static Class> {
+    unsafe {
+        let mut decl = ClassDecl::new("ZedWebViewDelegate", class!(NSObject))?;
+        decl.add_ivar::<*mut c_void>(WEBVIEW_STATE_IVAR);
+        for protocol in [
+            "WKNavigationDelegate",
+            "WKUIDelegate",
+            "WKDownloadDelegate",
+            "NSWindowDelegate",
+            "WKScriptMessageHandler",
+            "WKURLSchemeHandler",
+        ] {
+            if let Some(protocol) = Protocol::get(protocol) {
+                decl.add_protocol(protocol);
+            }
+        }
+        decl.add_method(
+            sel!(dealloc),
+            dealloc_delegate as extern "C" fn(&Object, Sel),
+        );
+        decl.add_method(
+            sel!(contextMenuItemSelected:),
+            context_menu_item_selected as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(webView:didFinishNavigation:),
+            did_finish_navigation as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(webViewWebContentProcessDidTerminate:),
+            web_content_process_did_terminate as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(observeValueForKeyPath:ofObject:change:context:),
+            observe_value as extern "C" fn(&Object, Sel, id, id, id, *mut c_void),
+        );
+        decl.add_method(
+            sel!(userContentController:didReceiveScriptMessage:),
+            did_receive_script_message as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(webView:startURLSchemeTask:),
+            start_url_scheme_task as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(webView:stopURLSchemeTask:),
+            stop_url_scheme_task as extern "C" fn(&Object, Sel, id, id),
+        );
+        decl.add_method(
+            sel!(webView:decidePolicyForNavigationAction:decisionHandler:),
+            decide_policy_for_navigation_action
+                as extern "C" fn(&Object, Sel, id, id, *mut Block<(isize,), ()>),
+        );
+        decl.add_method(
+            sel!(webView:createWebViewWithConfiguration:forNavigationAction:windowFeatures:),
+            create_webview as extern "C" fn(&Object, Sel, id, id, id, id) -> id,
+        );
+        decl.add_method(
+            sel!(webView:runJavaScriptAlertPanelWithMessage:initiatedByFrame:completionHandler:),
+            run_javascript_alert_panel
+                as extern "C" fn(&Object, Sel, id, id, id, *mut Block<(), ()>),
+        );
+        decl.add_method(
+            sel!(webView:runJavaScriptConfirmPanelWithMessage:initiatedByFrame:completionHandler:),
+            run_javascript_confirm_panel
+                as extern "C" fn(&Object, Sel, id, id, id, *mut Block<(BOOL,), ()>),
+        );
+        decl.add_method(
+            sel!(webView:runJavaScriptTextInputPanelWithPrompt:defaultText:initiatedByFrame:completionHandler:),
+            run_javascript_text_input_panel
+                as extern "C" fn(&Object, Sel, id, id, id, id, *mut Block<(id,), ()>),
+        );
+        decl.add_method(
+            sel!(webView:requestMediaCapturePermissionForOrigin:initiatedByFrame:type:decisionHandler:),
+            request_media_capture_permission
+                as extern "C" fn(&Object, Sel, id, id, id, isize, *mut Block<(isize,), ()>),
+        );
+        decl.add_method(
+            sel!(webView:didReceiveAuthenticationChallenge:completionHandler:),
+            did_receive_authentication_challenge
+                as extern "C" fn(&Object, Sel, id, id, *mut Block<(isize, id), ()>),
+        );
+        decl.add_method(
+            sel!(webView:decidePolicyForNavigationResponse:decisionHandler:),
+            decide_policy_for_navigation_response
+                as extern "C" fn(&Object, Sel, id, id, *mut Block<(isize,), ()>),
+        );
+        decl.add_method(
+            sel!(webView:navigationAction:didBecomeDownload:),
+            did_become_download as extern "C" fn(&Object, Sel, id, id, id),
+        );
+        decl.add_method(
+            sel!(webView:navigationResponse:didBecomeDownload:),
+            did_become_download as extern "C" fn(&Object, Sel, id, id, id),
+        );
+        decl.add_method(
+            sel!(download:decideDestinationUsingResponse:suggestedFilename:completionHandler:),
+            decide_download_destination
+                as extern "C" fn(&Object, Sel, id, id, id, *mut Block<(id,), ()>),
+        );
+        decl.add_method(
+            sel!(downloadDidFinish:),
+            download_did_finish as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(download:didFailWithError:resumeData:),
+            download_did_fail as extern "C" fn(&Object, Sel, id, id, id),
+        );
+        for selector in [
+            sel!(windowDidMove:),
+            sel!(windowDidResize:),
+            sel!(windowDidEndLiveResize:),
+        ] {
+            decl.add_method(
+                selector,
+                window_did_change_frame as extern "C" fn(&Object, Sel, id),
+            );
+        }
+        decl.add_method(
+            sel!(windowWillClose:),
+            window_will_close as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(webView:didFailNavigation:withError:),
+            did_fail_navigation as extern "C" fn(&Object, Sel, id, id, id),
+        );
+        decl.add_method(
+            sel!(webView:didFailProvisionalNavigation:withError:),
+            did_fail_navigation as extern "C" fn(&Object, Sel, id, id, id),
+        );
+        Some(decl.register())
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn get_webview_state(object: &Object) -> Rc<RefCell<WebViewState>> {
+    unsafe {
+        let raw: *mut c_void = *object.get_ivar(WEBVIEW_STATE_IVAR);
+        let raw = raw as *const RefCell<WebViewState>;
+        Rc::increment_strong_count(raw);
+        Rc::from_raw(raw)
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn dealloc_delegate(this: &Object, _: Sel) {
+    unsafe {
+        let raw: *mut c_void = *this.get_ivar(WEBVIEW_STATE_IVAR);
+        if !raw.is_null() {
+            drop(Rc::from_raw(raw as *const RefCell<WebViewState>));
+        }
+        let _: () = msg_send![super(this, class!(NSObject)), dealloc];
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn webview_url(webview: id) -> String {
+    unsafe {
+        let url: id = msg_send![webview, URL];
+        if url == nil {
+            return String::new();
+        }
+        let absolute_string: id = msg_send![url, absoluteString];
+        string_from_ns(absolute_string)
+    }
+}
+
+/// Evaluates `script` in `webview`, passing its result to `callback` if there is one.
+#[cfg(target_os = "macos")]
+unsafe fn evaluate_script(
+    webview: id,
+    script: &str,
+    callback: Option<Box<dyn FnOnce(Result<String>)>>,
+) {
+    unsafe {
+        let Some(callback) = callback else {
+            let _: () = msg_send![
+                webview,
+                evaluateJavaScript: ns_string(script)
+                completionHandler: nil
+            ];
+            return;
+        };
+        let callback = Cell::new(Some(callback));
+        let completion_handler = ConcreteBlock::new(move |result: id, error: id| {
+            if let Some(callback) = callback.take() {
+                if error == nil {
+                    callback(Ok(string_from_ns(result)));
+                } else {
+                    callback(Err(error_from_ns(error)));
+                }
+            }
+        });
+        let completion_handler = completion_handler.copy();
+        let _: () = msg_send![
+            webview,
+            evaluateJavaScript: ns_string(script)
+            completionHandler: completion_handler
+        ];
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn install_user_script(user_content_controller: id, source: &str, at_document_start: bool) {
+    // WKUserScriptInjectionTimeAtDocumentStart = 0, WKUserScriptInjectionTimeAtDocumentEnd = 1
+    let injection_time: i64 = if at_document_start { 0 } else { 1 };
+    unsafe {
+        let script: id = msg_send![class!(WKUserScript), alloc];
+        let script: id = msg_send![
+            script,
+            initWithSource: ns_string(source)
+            injectionTime: injection_time
+            forMainFrameOnly: YES
+        ];
+        let _: () = msg_send![user_content_controller, addUserScript: script];
+        let _: () = msg_send![script, release];
+    }
+}
+
+/// Runs the scripts queued until the first load ended, in call order. User scripts are only
+/// installed, without running, when `run_user_scripts` is false.
+#[cfg(target_os = "macos")]
+unsafe fn run_deferred_scripts(webview: id, scripts: Vec<DeferredScript>, run_user_scripts: bool) {
+    unsafe {
+        for script in scripts {
+            match script {
+                DeferredScript::Evaluate(script, callback) => {
+                    evaluate_script(webview, &script, callback)
+                }
+                DeferredScript::UserScript {
+                    source,
+                    at_document_start,
+                } => {
+                    let configuration: id = msg_send![webview, configuration];
+                    let user_content_controller: id =
+                        msg_send![configuration, userContentController];
+                    install_user_script(user_content_controller, &source, at_document_start);
+                    if run_user_scripts {
+                        evaluate_script(webview, &source, None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn did_finish_navigation(this: &Object, _: Sel, webview: id, _navigation: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        let url = webview_url(webview);
+        if !url.is_empty() {
+            state.borrow_mut().page_url = Some(url.clone());
+        }
+        if state.borrow().muted {
+            let _: () = msg_send![
+                webview,
+                evaluateJavaScript: ns_string(&mute_script(true))
+                completionHandler: nil
+            ];
+        }
+        if state.borrow().context_menu_disabled {
+            let _: () = msg_send![
+                webview,
+                evaluateJavaScript: ns_string(&context_menu_flag_script(true))
+                completionHandler: nil
+            ];
+        }
+        let deferred_scripts = WebViewState::finish_pending_navigation(&state, Ok(()));
+        run_deferred_scripts(webview, deferred_scripts, true);
+        WebViewState::invoke(
+            &state,
+            |state| &mut state.did_finish_navigation,
+            |handler| handler(url),
+        );
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn web_content_process_did_terminate(this: &Object, _: Sel, webview: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        let (reload, user_scripts) = WebViewState::handle_crash(&state);
+        run_deferred_scripts(webview, user_scripts, false);
+        if reload {
+            let _: id = msg_send![webview, reload];
+        }
+    }
+}
+
+/// Key-value observation callback for [`OBSERVED_KEY_PATHS`].
+#[cfg(target_os = "macos")]
+extern "C" fn observe_value(
+    this: &Object,
+    _: Sel,
+    key_path: id,
+    webview: id,
+    _change: id,
+    _context: *mut c_void,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        match string_from_ns(key_path).as_str() {
+            ESTIMATED_PROGRESS_KEY_PATH => {
+                let progress: f64 = msg_send![webview, estimatedProgress];
+                WebViewState::invoke(
+                    &state,
+                    |state| &mut state.progress,
+                    |handler| handler(progress),
+                );
+            }
+            TITLE_KEY_PATH => {
+                WebViewState::update_title(&state, webview_title(webview));
+            }
+            FULLSCREEN_STATE_KEY_PATH => {
+                let fullscreen_state: isize = msg_send![webview, fullscreenState];
+                let window: id = msg_send![webview, window];
+                let frame: NSRect = msg_send![window, frame];
+                let screen: id = msg_send![window, screen];
+                let screen_frame = (screen != nil).then(|| -> NSRect { msg_send![screen, frame] });
+                let new_frame = state.borrow_mut().fullscreen_restore.update(
+                    fullscreen_state,
+                    frame,
+                    screen_frame,
+                );
+                if let Some(new_frame) = new_frame {
+                    let _: () = msg_send![window, setFrame: new_frame display: YES];
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Strings are delivered as-is; other JSON-compatible values are serialized to JSON.
+#[cfg(target_os = "macos")]
+unsafe fn script_message_body_to_string(body: id) -> String {
+    unsafe {
+        let is_string: bool = msg_send![body, isKindOfClass: class!(NSString)];
+        let is_json: bool = msg_send![class!(NSJSONSerialization), isValidJSONObject: body];
+        if is_string || !is_json {
+            return string_from_ns(body);
+        }
+        let data: id = msg_send![
+            class!(NSJSONSerialization),
+            dataWithJSONObject: body
+            options: 0u64
+            error: nil
+        ];
+        if data == nil {
+            return string_from_ns(body);
+        }
+        String::from_utf8_lossy(&vec_from_ns_data(data)).into_owned()
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn did_receive_script_message(this: &Object, _: Sel, _controller: id, message: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        let name: id = msg_send![message, name];
+        let body: id = msg_send![message, body];
+        WebViewState::invoke_message_handler(
+            &state,
+            string_from_ns(name),
+            script_message_body_to_string(body),
+        );
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn start_url_scheme_task(this: &Object, _: Sel, _webview: id, task: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        let request: id = msg_send![task, request];
+        let url: id = msg_send![request, URL];
+        let scheme: id = msg_send![url, scheme];
+        let url_string: id = msg_send![url, absoluteString];
+        let response = WebViewState::handle_scheme_request(
+            &state,
+            &string_from_ns(scheme).to_ascii_lowercase(),
+            SchemeRequest {
+                url: string_from_ns(url_string),
+            },
+        );
+
+        let header_fields: id = msg_send![
+            class!(NSDictionary),
+            dictionaryWithObject: ns_string(&response.mime_type)
+            forKey: ns_string("Content-Type")
+        ];
+        let url_response: id = msg_send![class!(NSHTTPURLResponse), alloc];
+        let url_response: id = msg_send![
+            url_response,
+            initWithURL: url
+            statusCode: response.status as isize
+            HTTPVersion: ns_string("HTTP/1.1")
+            headerFields: header_fields
+        ];
+        let _: () = msg_send![task, didReceiveResponse: url_response];
+        let _: () = msg_send![url_response, release];
+        let data: id = msg_send![
+            class!(NSData),
+            dataWithBytes: response.body.as_ptr() as *const c_void
+            length: response.body.len()
+        ];
+        let _: () = msg_send![task, didReceiveData: data];
+        let _: () = msg_send![task, didFinish];
+    }
+}
+
+/// Tasks are answered before `webView:startURLSchemeTask:` returns, so there's never one in
+/// flight to stop.
+#[cfg(target_os = "macos")]
+extern "C" fn stop_url_scheme_task(_: &Object, _: Sel, _webview: id, _task: id) {}
+
+#[cfg(target_os = "macos")]
+extern "C" fn decide_policy_for_navigation_action(
+    this: &Object,
+    _: Sel,
+    webview: id,
+    navigation_action: id,
+    decision_handler: *mut Block<(isize,), ()>,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        let request: id = msg_send![navigation_action, request];
+        let url: id = msg_send![request, URL];
+        let url_string: id = msg_send![url, absoluteString];
+        let decision = WebViewState::decide_navigation(&state, &string_from_ns(url_string));
+        if decision == NavigationDecision::OpenExternally {
+            open_in_default_browser(&string_from_ns(url_string)).log_err();
+        }
+        if decision != NavigationDecision::Allow {
+            // A nil target frame means a new window, which never loads here.
+            let target_frame: id = msg_send![navigation_action, targetFrame];
+            let is_main_frame: bool = target_frame != nil && {
+                let is_main_frame: bool = msg_send![target_frame, isMainFrame];
+                is_main_frame
+            };
+            if is_main_frame {
+                let deferred_scripts =
+                    WebViewState::cancel_pending_navigation(&state, &string_from_ns(url_string));
+                run_deferred_scripts(webview, deferred_scripts, true);
+            }
+        }
+        let mut policy = wk_navigation_action_policy(decision);
+        if policy == WK_NAVIGATION_ACTION_POLICY_ALLOW && downloads_enabled(&state) {
+            // `shouldPerformDownload` is set for links with a `download` attribute.
+            let responds: bool =
+                msg_send![navigation_action, respondsToSelector: sel!(shouldPerformDownload)];
+            if responds {
+                let should_download: bool = msg_send![navigation_action, shouldPerformDownload];
+                if should_download {
+                    policy = WK_POLICY_DOWNLOAD;
+                }
+            }
+        }
+        (*decision_handler).call((policy,));
+    }
+}
+
+/// Downloads require `WKDownload`, available since macOS 11.3, and a registered download handler.
+#[cfg(target_os = "macos")]
+fn downloads_enabled(state: &RefCell<WebViewState>) -> bool {
+    state.borrow().download.is_some() && Class::get("WKDownload").is_some()
+}
+
+/// Turns responses the webview can't display, such as archives, into downloads.
+#[cfg(target_os = "macos")]
+extern "C" fn decide_policy_for_navigation_response(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    navigation_response: id,
+    decision_handler: *mut Block<(isize,), ()>,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        let can_show: bool = msg_send![navigation_response, canShowMIMEType];
+        let policy = if !can_show && downloads_enabled(&state) {
+            WK_POLICY_DOWNLOAD
+        } else {
+            WK_NAVIGATION_ACTION_POLICY_ALLOW
+        };
+        (*decision_handler).call((policy,));
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn did_become_download(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    _navigation: id,
+    download: id,
+) {
+    unsafe {
+        let _: () = msg_send![download, setDelegate: this];
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn decide_download_destination(
+    this: &Object,
+    _: Sel,
+    download: id,
+    _response: id,
+    suggested_filename: id,
+    completion_handler: *mut Block<(id,), ()>,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        let destination =
+            WebViewState::download_destination(&state, string_from_ns(suggested_filename));
+        let url: id = match destination.as_ref().and_then(|path| path.to_str()) {
+            Some(path) => msg_send![class!(NSURL), fileURLWithPath: ns_string(path)],
+            // A nil destination cancels the download.
+            None => nil,
+        };
+        if let Some(destination) = destination.filter(|_| url != nil) {
+            state
+                .borrow_mut()
+                .downloads
+                .insert(download as usize, destination);
+        }
+        (*completion_handler).call((url,));
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn download_did_finish(this: &Object, _: Sel, download: id) {
+    let state = unsafe { get_webview_state(this) };
+    let destination = state.borrow_mut().downloads.remove(&(download as usize));
+    if let Some(destination) = destination {
+        WebViewState::finish_download(&state, Ok(destination));
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn download_did_fail(this: &Object, _: Sel, download: id, error: id, _resume_data: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        state.borrow_mut().downloads.remove(&(download as usize));
+        WebViewState::finish_download(&state, Err(error_from_ns(error)));
+    }
+}
+
+/// Called for `target="_blank"` links and `window.open`. Returning nil would drop the request, so
+/// the URL is loaded in place unless a new-window handler takes it.
+#[cfg(target_os = "macos")]
+extern "C" fn create_webview(
+    this: &Object,
+    _: Sel,
+    webview: id,
+    _configuration: id,
+    navigation_action: id,
+    _window_features: id,
+) -> id {
+    unsafe {
+        let state = get_webview_state(this);
+        let request: id = msg_send![navigation_action, request];
+        let url: id = msg_send![request, URL];
+        let url_string: id = msg_send![url, absoluteString];
+        let url = string_from_ns(url_string);
+        let handled = WebViewState::invoke(
+            &state,
+            |state| &mut state.new_window,
+            |handler| handler(url),
+        );
+        if handled.is_none() {
+            let _: id = msg_send![webview, loadRequest: request];
+        }
+        nil
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn run_javascript_alert_panel(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    message: id,
+    _frame: id,
+    completion_handler: *mut Block<(), ()>,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        WebViewState::run_js_alert(&state, string_from_ns(message));
+        (*completion_handler).call(());
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn run_javascript_confirm_panel(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    message: id,
+    _frame: id,
+    completion_handler: *mut Block<(BOOL,), ()>,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        let confirmed = WebViewState::run_js_confirm(&state, string_from_ns(message));
+        (*completion_handler).call((if confirmed { YES } else { NO },));
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn run_javascript_text_input_panel(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    prompt: id,
+    default_text: id,
+    _frame: id,
+    completion_handler: *mut Block<(id,), ()>,
+) {
+    unsafe {
+        let state = get_webview_state(this);
+        let text = WebViewState::run_js_prompt(
+            &state,
+            string_from_ns(prompt),
+            string_from_ns(default_text),
+        );
+        let result = match text {
+            Some(text) => ns_string(&text),
+            None => nil,
+        };
+        (*completion_handler).call((result,));
+    }
+}
+
+/// Requires macOS 12. Earlier versions, and geolocation, which WebKit only exposes through
+/// private API on macOS, fall back to WebKit's default of prompting the user.
+#[cfg(target_os = "macos")]
+extern "C" fn request_media_capture_permission(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    origin: id,
+    _frame: id,
+    media_type: isize,
+    decision_handler: *mut Block<(isize,), ()>,
+) {
+    const WK_PERMISSION_DECISION_GRANT: isize = 1;
+    const WK_PERMISSION_DECISION_DENY: isize = 2;
+    unsafe {
+        let state = get_webview_state(this);
+        let kind = match media_type {
+            0 => PermissionKind::Camera,
+            1 => PermissionKind::Microphone,
+            _ => PermissionKind::CameraAndMicrophone,
+        };
+        let protocol: id = msg_send![origin, protocol];
+        let host: id = msg_send![origin, host];
+        let port: isize = msg_send![origin, port];
+        let origin = origin_string(&string_from_ns(protocol), &string_from_ns(host), port);
+        let decision = if WebViewState::decide_permission(&state, kind, origin) {
+            WK_PERMISSION_DECISION_GRANT
+        } else {
+            WK_PERMISSION_DECISION_DENY
+        };
+        (*decision_handler).call((decision,));
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" fn did_receive_authentication_challenge(
+    this: &Object,
+    _: Sel,
+    _webview: id,
+    challenge: id,
+    completion_handler: *mut Block<(isize, id), ()>,
+) {
+    const NS_URL_SESSION_AUTH_CHALLENGE_USE_CREDENTIAL: isize = 0;
+    const NS_URL_SESSION_AUTH_CHALLENGE_PERFORM_DEFAULT_HANDLING: isize = 1;
+    const NS_URL_SESSION_AUTH_CHALLENGE_CANCEL_AUTHENTICATION_CHALLENGE: isize = 2;
+    unsafe {
+        let state = get_webview_state(this);
+        let protection_space: id = msg_send![challenge, protectionSpace];
+        let host: id = msg_send![protection_space, host];
+        let port: isize = msg_send![protection_space, port];
+        let method: id = msg_send![protection_space, authenticationMethod];
+        let method = auth_method_from_name(&string_from_ns(method));
+        // Stored credentials are only offered once, so wrong ones fall through to the handler
+        // instead of being retried forever.
+        let previous_failure_count: isize = msg_send![challenge, previousFailureCount];
+        let host = string_from_ns(host);
+        let port = u16::try_from(port).unwrap_or(0);
+        let basic_auth = state.borrow().basic_auth.clone();
+        if method == AuthMethod::HttpBasic
+            && previous_failure_count == 0
+            && let Some(basic_auth) =
+                basic_auth.filter(|basic_auth| basic_auth.matches(&host, port))
+        {
+            // Not persisted, so clearing or replacing the credentials takes effect right away.
+            const NS_URL_CREDENTIAL_PERSISTENCE_NONE: usize = 0;
+            let credential: id = msg_send![
+                class!(NSURLCredential),
+                credentialWithUser: ns_string(&basic_auth.username)
+                password: ns_string(&basic_auth.password)
+                persistence: NS_URL_CREDENTIAL_PERSISTENCE_NONE
+            ];
+            (*completion_handler).call((NS_URL_SESSION_AUTH_CHALLENGE_USE_CREDENTIAL, credential));
+            return;
+        }
+        let challenge = AuthChallenge { host, port, method };
+        match WebViewState::respond_to_auth_challenge(&state, challenge) {
+            AuthResponse::UseCredential if method == AuthMethod::ServerTrust => {
+                let trust: *mut c_void = msg_send![protection_space, serverTrust];
+                let credential: id = msg_send![class!(NSURLCredential), credentialForTrust: trust];
+                (*completion_handler)
+                    .call((NS_URL_SESSION_AUTH_CHALLENGE_USE_CREDENTIAL, credential));
+            }
+            AuthResponse::UseCredential | AuthResponse::PerformDefault => {
+                (*completion_handler)
+                    .call((NS_URL_SESSION_AUTH_CHALLENGE_PERFORM_DEFAULT_HANDLING, nil));
+            }
+            AuthResponse::Cancel => {
+                (*completion_handler).call((
+                    NS_URL_SESSION_AUTH_CHALLENGE_CANCEL_AUTHENTICATION_CHALLENGE,
+                    nil,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn cookie_from_ns(ns_cookie: id) -> Cookie {
+    unsafe {
+        let name: id = msg_send![ns_cookie, name];
+        let value: id = msg_send![ns_cookie, value];
+        let domain: id = msg_send![ns_cookie, domain];
+        let path: id = msg_send![ns_cookie, path];
+        let secure: bool = msg_send![ns_cookie, isSecure];
+        let http_only: bool = msg_send![ns_cookie, isHTTPOnly];
+        let expires_date: id = msg_send![ns_cookie, expiresDate];
+        let expires = if expires_date == nil {
+            None
+        } else {
+            let seconds: f64 = msg_send![expires_date, timeIntervalSince1970];
+            from_unix_seconds(seconds)
+        };
+        Cookie {
+            name: string_from_ns(name),
+            value: string_from_ns(value),
+            domain: string_from_ns(domain),
+            path: string_from_ns(path),
+            secure,
+            http_only,
+            expires,
+        }
+    }
+}
+
+/// Resizes are reported once the user releases the mouse rather than for every intermediate size.
+#[cfg(target_os = "macos")]
+extern "C" fn window_did_change_frame(this: &Object, _: Sel, notification: id) {
+    unsafe {
+        let window: id = msg_send![notification, object];
+        let in_live_resize: bool = msg_send![window, inLiveResize];
+        if in_live_resize {
+            return;
+        }
+        let state = get_webview_state(this);
+        WebViewState::report_bounds(&state, window_bounds(window));
+    }
+}
+
+/// Only the user closes the window while the manager is alive: `Drop` detaches the delegate
+/// before closing it.
+#[cfg(target_os = "macos")]
+extern "C" fn window_will_close(this: &Object, _: Sel, _notification: id) {
+    let state = unsafe { get_webview_state(this) };
+    WebViewState::notify_closed(&state);
+}
+
+/// Handles both committed and provisional failures. The failing URL comes from the error when
+/// available, since a provisional failure leaves the webview on the previous page.
+#[cfg(target_os = "macos")]
+extern "C" fn did_fail_navigation(this: &Object, _: Sel, webview: id, _navigation: id, error: id) {
+    unsafe {
+        let state = get_webview_state(this);
+        let code: i64 = msg_send![error, code];
+        let user_info: id = msg_send![error, userInfo];
+        let failing_url: id =
+            msg_send![user_info, objectForKey: ns_string("NSErrorFailingURLStringKey")];
+        let url = if failing_url == nil {
+            webview_url(webview)
+        } else {
+            string_from_ns(failing_url)
+        };
+        log::warn!("WebView navigation to {} failed with code {}", url, code);
+
+        let deferred_scripts = WebViewState::finish_pending_navigation(
+            &state,
+            Err(anyhow::anyhow!(
+                "navigation to {url} failed with code {code}"
+            )),
+        );
+        run_deferred_scripts(webview, deferred_scripts, true);
+        WebViewState::invoke(
+            &state,
+            |state| &mut state.navigation_failed,
+            |handler| handler(url, code),
+        );
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn ensure_main_thread(method: &str) -> Result<()> {
+    let is_main_thread: bool = unsafe { msg_send![class!(NSThread), isMainThread] };
+    anyhow::ensure!(
+        is_main_thread,
+        "WebViewManager::{method} must be called on the main thread"
+    );
+    Ok(())
+}
+
+/// A capability a page asked for, passed to [`WebViewManager::on_permission_request`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermissionKind {
+    Camera,
+    Microphone,
+    CameraAndMicrophone,
+    Geolocation,
+}
+
+/// Formats a security origin like `https://example.com:8443`, omitting the port when it is the
+/// scheme's default, which WebKit reports as 0.
+#[cfg(any(test, target_os = "macos"))]
+fn origin_string(protocol: &str, host: &str, port: isize) -> String {
+    if port == 0 {
+        format!("{protocol}://{host}")
+    } else {
+        format!("{protocol}://{host}:{port}")
+    }
+}
+
+/// How a server asked the webview to authenticate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Deciding whether to trust the server's TLS certificate.
+    ServerTrust,
+    HttpBasic,
+    HttpDigest,
+    ClientCertificate,
+    Other,
+}
+
+/// Maps an `NSURLAuthenticationMethod` constant, whose value is its own name, to an [`AuthMethod`].
+#[cfg(any(test, target_os = "macos"))]
+fn auth_method_from_name(name: &str) -> AuthMethod {
+    match name {
+        "NSURLAuthenticationMethodServerTrust" => AuthMethod::ServerTrust,
+        "NSURLAuthenticationMethodHTTPBasic" => AuthMethod::HttpBasic,
+        "NSURLAuthenticationMethodHTTPDigest" => AuthMethod::HttpDigest,
+        "NSURLAuthenticationMethodClientCertificate" => AuthMethod::ClientCertificate,
+        _ => AuthMethod::Other,
+    }
+}
+
+/// An authentication challenge, passed to [`WebViewManager::on_auth_challenge`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthChallenge {
+    pub host: String,
+    /// 0 when the platform doesn't report a port.
+    pub port: u16,
+    pub method: AuthMethod,
+}
+
+/// How to answer an [`AuthChallenge`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthResponse {
+    /// Trusts the server's certificate for [`AuthMethod::ServerTrust`] challenges, even when it
+    /// failed validation. Behaves like `PerformDefault` for other methods.
+    UseCredential,
+    #[default]
+    PerformDefault,
+    Cancel,
+}
+
+/// A request for a URL in a scheme registered with
+/// [`WebViewManagerBuilder::register_scheme_handler`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemeRequest {
+    pub url: String,
+}
+
+/// What to serve for a [`SchemeRequest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemeResponse {
+    pub body: Vec<u8>,
+    pub mime_type: String,
+    pub status: u16,
+}
+
+impl SchemeResponse {
+    /// A `200 OK` response.
+    pub fn new(mime_type: impl Into<String>, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            body: body.into(),
+            mime_type: mime_type.into(),
+            status: 200,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            body: Vec::new(),
+            mime_type: "text/plain".to_string(),
+            status: 404,
+        }
+    }
+}
+
+/// An HTTP proxy for a webview's traffic, set with [`WebViewManagerBuilder::proxy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub host: String,
+    pub port: u16,
+    /// Username and password, if the proxy requires them.
+    pub credentials: Option<(String, String)>,
+}
+
+/// A cookie in a webview's data store, read with [`WebViewManager::get_cookies`] and written with
+/// [`WebViewManager::set_cookie`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// A leading `.` also matches subdomains.
+    pub domain: String,
+    pub path: String,
+    pub secure: bool,
+    pub http_only: bool,
+    /// `None` for session cookies.
+    pub expires: Option<SystemTime>,
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0.0, |duration| duration.as_secs_f64())
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn from_unix_seconds(seconds: f64) -> Option<SystemTime> {
+    let duration = std::time::Duration::try_from_secs_f64(seconds).ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(duration)
+}
+
+/// Whether a browser would send `cookie` with a request to `url`.
+#[cfg(any(test, target_os = "macos"))]
+fn cookie_matches_url(cookie: &Cookie, url: &url::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let domain_matches = match cookie.domain.strip_prefix('.') {
+        Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+        None => host == cookie.domain,
+    };
+    let path = url.path();
+    let path_matches = cookie.path.is_empty()
+        || path == cookie.path
+        || path.starts_with(&cookie.path)
+            && (cookie.path.ends_with('/') || path[cookie.path.len()..].starts_with('/'));
+    domain_matches && path_matches && (!cookie.secure || url.scheme() == "https")
+}
+
+/// File types opened when dropped onto a webview with
+/// [`WebViewManager::set_accepts_file_drops`] enabled.
+#[cfg(any(test, target_os = "macos"))]
+const DROPPABLE_FILE_EXTENSIONS: &[&str] = &["html", "htm", "xhtml", "pdf", "svg", "txt"];
+
+#[cfg(any(test, target_os = "macos"))]
+fn has_droppable_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            DROPPABLE_FILE_EXTENSIONS
+                .iter()
+                .any(|droppable| extension.eq_ignore_ascii_case(droppable))
+        })
+}
+
+/// Cancels `contextmenu` events, and with them the native menu, while the page-level flag set by
+/// [`context_menu_flag_script`] is on.
+const CONTEXT_MENU_LISTENER_SCRIPT: &str = r#"
+window.addEventListener('contextmenu', (event) => {
+    if (window.__zedContextMenuDisabled) event.preventDefault();
+}, true);
+"#;
+
+/// Sets the flag checked by [`CONTEXT_MENU_LISTENER_SCRIPT`] in the current page.
+fn context_menu_flag_script(disabled: bool) -> String {
+    format!("window.__zedContextMenuDisabled = {disabled};")
+}
+
+/// Where a context menu was opened, passed to [`WebViewManager::on_context_menu`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextMenuContext {
+    pub page_url: String,
+    /// Relative to the webview's top-left corner.
+    pub position: Point<Pixels>,
+}
+
+/// An entry in a custom context menu.
+pub enum MenuItem {
+    Action {
+        title: String,
+        handler: Box<dyn FnOnce()>,
+    },
+    Separator,
+}
+
+impl MenuItem {
+    pub fn action(title: impl Into<String>, handler: impl FnOnce() + 'static) -> Self {
+        Self::Action {
+            title: title.into(),
+            handler: Box::new(handler),
+        }
+    }
+}
+
+/// Remembers a window's frame while its content is fullscreen, so that exiting restores it.
+#[cfg(any(test, target_os = "macos"))]
+struct FullscreenRestore<T>(Option<T>);
+
+#[cfg(any(test, target_os = "macos"))]
+impl<T> Default for FullscreenRestore<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+/// `WKFullscreenState` values.
+#[cfg(any(test, target_os = "macos"))]
+const WK_FULLSCREEN_STATE_NOT_IN_FULLSCREEN: isize = 0;
+#[cfg(any(test, target_os = "macos"))]
+const WK_FULLSCREEN_STATE_ENTERING_FULLSCREEN: isize = 1;
+
+#[cfg(any(test, target_os = "macos"))]
+impl<T> FullscreenRestore<T> {
+    /// Returns the frame to give the window, currently at `frame`, once the webview's
+    /// `fullscreenState` changes to `fullscreen_state`: `screen_frame` when entering, and the
+    /// frame from before entering when exiting. Entering again before exiting keeps the first
+    /// frame.
+    fn update(&mut self, fullscreen_state: isize, frame: T, screen_frame: Option<T>) -> Option<T> {
+        match fullscreen_state {
+            WK_FULLSCREEN_STATE_ENTERING_FULLSCREEN => {
+                self.0.get_or_insert(frame);
+                screen_frame
+            }
+            WK_FULLSCREEN_STATE_NOT_IN_FULLSCREEN => self.0.take(),
+            _ => None,
+        }
+    }
+}
+
+/// How navigations use the HTTP cache, set with [`WebViewManagerBuilder::cache_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Follows the caching headers the server sent.
+    #[default]
+    Default,
+    /// Always fetches the page from the server.
+    ReloadIgnoringCache,
+    /// Uses a cached copy, even a stale one, and only loads from the server without one.
+    ReturnCacheDataElseLoad,
+}
+
+impl CachePolicy {
+    #[cfg(target_os = "macos")]
+    fn ns_url_request_cache_policy(self) -> usize {
+        match self {
+            CachePolicy::Default => 0, // NSURLRequestUseProtocolCachePolicy
+            CachePolicy::ReloadIgnoringCache => 1, // NSURLRequestReloadIgnoringLocalCacheData
+            CachePolicy::ReturnCacheDataElseLoad => 2, // NSURLRequestReturnCacheDataElseLoad
+        }
+    }
+}
+
+/// Which screen a new window opens on, set with [`WebViewManagerBuilder::screen`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScreenSelector {
+    /// The screen showing the parent window, or the main screen without one.
+    #[default]
+    Parent,
+    Main,
+    /// The screen containing a point in gpui's screen coordinates, or the main screen when no
+    /// screen does.
+    ContainingPoint(Point<Pixels>),
+}
+
+/// What to do with a navigation, as decided by a policy registered with
+/// [`WebViewManager::set_navigation_policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NavigationDecision {
+    Allow,
+    Cancel,
+    /// Cancels the navigation and opens the URL in the system browser instead.
+    OpenExternally,
+}
+
+#[cfg(any(test, target_os = "macos"))]
+const WK_NAVIGATION_ACTION_POLICY_CANCEL: isize = 0;
+#[cfg(any(test, target_os = "macos"))]
+const WK_NAVIGATION_ACTION_POLICY_ALLOW: isize = 1;
+/// Shared by `WKNavigationActionPolicy` and `WKNavigationResponsePolicy`.
+#[cfg(target_os = "macos")]
+const WK_POLICY_DOWNLOAD: isize = 2;
+
+/// Maps a decision to the `WKNavigationActionPolicy` WebKit applies. URLs opened externally are
+/// cancelled in the webview.
+#[cfg(any(test, target_os = "macos"))]
+fn wk_navigation_action_policy(decision: NavigationDecision) -> isize {
+    match decision {
+        NavigationDecision::Allow => WK_NAVIGATION_ACTION_POLICY_ALLOW,
+        NavigationDecision::Cancel | NavigationDecision::OpenExternally => {
+            WK_NAVIGATION_ACTION_POLICY_CANCEL
+        }
+    }
+}
+
+/// Which media pages may start playing on their own, without the user interacting first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AutoplayPolicy {
+    Allow,
+    /// Only muted media may autoplay.
+    #[default]
+    RequireUserGesture,
+    /// No media autoplays, muted or not.
+    Block,
+}
+
+impl AutoplayPolicy {
+    /// The `WKAudiovisualMediaTypes` that require a user action before playing.
+    #[cfg(target_os = "macos")]
+    fn wk_media_types(self) -> usize {
+        match self {
+            AutoplayPolicy::Allow => 0,
+            AutoplayPolicy::RequireUserGesture => 1, // WKAudiovisualMediaTypeAudio
+            AutoplayPolicy::Block => usize::MAX,     // WKAudiovisualMediaTypeAll
+        }
+    }
+}
+
+/// How the webview window is stacked relative to other windows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowLevel {
+    /// Stacked with ordinary application windows.
+    Normal,
+    /// Kept above ordinary windows, like a palette.
+    #[default]
+    Floating,
+    /// Kept above floating windows, like a modal panel.
+    ModalPanel,
+    /// Kept above everything else, including the menu bar and the Dock.
+    ScreenSaver,
+}
+
+impl WindowLevel {
+    /// The corresponding `NSWindowLevel` constant.
+    #[cfg(target_os = "macos")]
+    fn ns_window_level(self) -> i64 {
+        match self {
+            WindowLevel::Normal => 0,
+            WindowLevel::Floating => 3,
+            WindowLevel::ModalPanel => 8,
+            WindowLevel::ScreenSaver => 1000,
+        }
+    }
+}
+
+/// How the webview window's titlebar is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TitlebarStyle {
+    /// No titlebar or window buttons; the page fills the whole window, like an overlay.
+    Hidden,
+    /// The page extends under a transparent titlebar that only shows the window buttons.
+    #[default]
+    Transparent,
+    /// A standard titlebar above the page, showing the title.
+    Visible,
+}
+
+impl TitlebarStyle {
+    #[cfg(target_os = "macos")]
+    fn ns_style_mask(self) -> NSWindowStyleMask {
+        let style_mask = NSWindowStyleMask::NSTitledWindowMask
+            | NSWindowStyleMask::NSClosableWindowMask
+            | NSWindowStyleMask::NSMiniaturizableWindowMask
+            | NSWindowStyleMask::NSResizableWindowMask;
+        match self {
+            // Hidden keeps the titled mask, since a borderless window can't become key.
+            TitlebarStyle::Hidden | TitlebarStyle::Transparent => {
+                style_mask | NSWindowStyleMask::NSFullSizeContentViewWindowMask
+            }
+            TitlebarStyle::Visible => style_mask,
+        }
+    }
+}
+
+/// The color scheme a webview's pages see through `prefers-color-scheme`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Appearance {
+    Light,
+    Dark,
+    /// Follows the system setting.
+    #[default]
+    System,
+}
+
+impl Appearance {
+    #[cfg(target_os = "macos")]
+    fn ns_appearance_name(self) -> Option<&'static str> {
+        match self {
+            Appearance::Light => Some("NSAppearanceNameAqua"),
+            Appearance::Dark => Some("NSAppearanceNameDarkAqua"),
+            Appearance::System => None,
+        }
+    }
+}
+
+/// The website data store a webview keeps cookies, cache, and storage in. Webviews built with
+/// clones of the same handle share a login session; see [`WebViewManagerBuilder::data_store`].
+///
+/// Only macOS shares ephemeral stores. Elsewhere, each ephemeral webview still gets its own.
+#[derive(Clone, Debug, Default)]
+pub struct DataStoreHandle {
+    kind: DataStoreKind,
+    /// The `WKWebsiteDataStore`, created by the first webview built with this handle.
+    #[cfg(target_os = "macos")]
+    store: Rc<NativeDataStore>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+enum DataStoreKind {
+    #[default]
+    Default,
+    Ephemeral,
+    Named(Uuid),
+}
+
+impl DataStoreHandle {
+    /// A store kept in memory and discarded once every webview using it is dropped.
+    pub fn ephemeral() -> Self {
+        Self::with_kind(DataStoreKind::Ephemeral)
+    }
+
+    /// A persistent store kept apart from the default one. Reusing `identifier` on later launches
+    /// reopens the same store. Requires macOS 14 or later, and isn't supported on Linux.
+    pub fn named(identifier: Uuid) -> Self {
+        Self::with_kind(DataStoreKind::Named(identifier))
+    }
+
+    fn with_kind(kind: DataStoreKind) -> Self {
+        Self {
+            kind,
+            #[cfg(target_os = "macos")]
+            store: Rc::default(),
+        }
+    }
+
+    /// See [`WebViewManager::is_persistent`].
+    pub fn is_persistent(&self) -> bool {
+        self.kind != DataStoreKind::Ephemeral
+    }
+
+    /// Whether this is the store webviews use unless they are given another one.
+    #[cfg(target_os = "macos")]
+    fn is_default(&self) -> bool {
+        self.kind == DataStoreKind::Default
+    }
+
+    /// The identifier passed to [`Self::named`].
+    pub fn identifier(&self) -> Option<Uuid> {
+        match self.kind {
+            DataStoreKind::Named(identifier) => Some(identifier),
+            DataStoreKind::Default | DataStoreKind::Ephemeral => None,
+        }
+    }
+
+    /// Returns the `WKWebsiteDataStore`, creating it on first use so that later webviews built
+    /// with this handle get the same one.
+    #[cfg(target_os = "macos")]
+    unsafe fn native_store(&self) -> Result<id> {
+        if self.store.0.get() != nil {
+            return Ok(self.store.0.get());
+        }
+        let data_store_class = webkit_class("WKWebsiteDataStore")?;
+        let store: id = unsafe {
+            match self.kind {
+                DataStoreKind::Default => msg_send![data_store_class, defaultDataStore],
+                DataStoreKind::Ephemeral => msg_send![data_store_class, nonPersistentDataStore],
+                DataStoreKind::Named(identifier) => {
+                    let responds: bool = msg_send![
+                        data_store_class,
+                        respondsToSelector: sel!(dataStoreForIdentifier:)
+                    ];
+                    anyhow::ensure!(
+                        responds,
+                        "named data stores require macOS 14 or later (running macOS {})",
+                        macos_version()
+                    );
+                    let uuid: id = msg_send![class!(NSUUID), alloc];
+                    let uuid: id =
+                        msg_send![uuid, initWithUUIDString: ns_string(&identifier.to_string())];
+                    let uuid: id = msg_send![uuid, autorelease];
+                    msg_send![data_store_class, dataStoreForIdentifier: uuid]
+                }
+            }
+        };
+        anyhow::ensure!(store != nil, "failed to create the webview data store");
+        let store: id = unsafe { msg_send![store, retain] };
+        self.store.0.set(store);
+        Ok(store)
+    }
+}
+
+/// The retained `WKWebsiteDataStore` behind a [`DataStoreHandle`], nil until it is created.
+#[cfg(target_os = "macos")]
+#[derive(Debug)]
+struct NativeDataStore(Cell<id>);
+
+#[cfg(target_os = "macos")]
+impl Default for NativeDataStore {
+    fn default() -> Self {
+        Self(Cell::new(nil))
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for NativeDataStore {
+    fn drop(&mut self) {
+        let store = self.0.get();
+        if store != nil {
+            unsafe {
+                let _: () = msg_send![store, release];
+            }
+        }
+    }
+}
+
+/// A floating webview window for embedding web content.
+///
+/// The native window and webview may only be used from the main thread. A `WebViewManager` is
+/// neither `Send` nor `Sync` on any platform, so every method runs on the thread that created it,
+/// and on macOS `new` fails when called off the main thread.
+///
+/// Every backend finishes creating its native webview before `new` and
+/// [`WebViewManagerBuilder::build`] return, so calls made right afterwards aren't dropped.
+/// Navigations take effect in call order, with each one superseding the last. On every backend,
+/// scripts evaluated and user scripts added before the first load ends are queued and run in call
+/// order once it does; see [`Self::has_loaded`].
+pub struct WebViewManager {
+    #[cfg(target_os = "macos")]
+    floating_window: id,
+    #[cfg(target_os = "macos")]
+    ns_webview: id,
+    /// Covers the strip reserved by `set_chrome_height`. Owned by the content view.
+    #[cfg(target_os = "macos")]
+    chrome_view: id,
+    #[cfg(target_os = "macos")]
+    delegate: id,
+    #[cfg(target_os = "macos")]
+    user_content_controller: id,
+    #[cfg(target_os = "macos")]
+    cache_policy: CachePolicy,
+    #[cfg(target_os = "macos")]
+    request_timeout: Duration,
+    /// The editor window the floating window is attached to as a child, retained so that it can
+    /// be attached again after the editor closes it. Nil when the webview is independent.
+    #[cfg(target_os = "macos")]
+    parent_window: id,
+    #[cfg(target_os = "windows")]
+    hwnd: ::windows::Win32::Foundation::HWND,
+    /// The window that `blur` hands focus back to. Null when created without a parent.
+    #[cfg(target_os = "windows")]
+    parent_hwnd: ::windows::Win32::Foundation::HWND,
+    #[cfg(target_os = "windows")]
+    controller: webview2_com::Microsoft::Web::WebView2::Win32::ICoreWebView2Controller,
+    #[cfg(target_os = "windows")]
+    webview: webview2_com::Microsoft::Web::WebView2::Win32::ICoreWebView2,
+    #[cfg(target_os = "windows")]
+    navigation_starting_token: i64,
+    #[cfg(target_os = "windows")]
+    navigation_completed_token: i64,
+    #[cfg(target_os = "windows")]
+    web_message_received_token: i64,
+    #[cfg(target_os = "windows")]
+    document_title_changed_token: i64,
+    #[cfg(target_os = "windows")]
+    new_window_requested_token: i64,
+    #[cfg(target_os = "windows")]
+    script_dialog_opening_token: i64,
+    #[cfg(target_os = "windows")]
+    permission_requested_token: i64,
+    #[cfg(target_os = "windows")]
+    process_failed_token: i64,
+    /// `None` when the WebView2 runtime predates certificate error events.
+    #[cfg(target_os = "windows")]
+    server_certificate_error_token: Option<i64>,
+    /// `None` when the WebView2 runtime predates basic authentication events.
+    #[cfg(target_os = "windows")]
+    basic_authentication_token: Option<i64>,
+    /// `None` when the WebView2 runtime predates download events.
+    #[cfg(target_os = "windows")]
+    download_starting_token: Option<i64>,
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    state: Rc<RefCell<WebViewState>>,
+    #[cfg(target_os = "linux")]
+    id: u64,
+    /// Whether the GTK thread holds navigations until the navigation policy answers.
+    #[cfg(target_os = "linux")]
+    has_navigation_policy: std::sync::Arc<AtomicBool>,
+    /// The GTK thread's copy of `WebViewState::requested_load`, updated as it starts each load.
+    #[cfg(target_os = "linux")]
+    requested_load: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    /// Drains the events reported by the GTK thread.
+    #[cfg(target_os = "linux")]
+    _gtk_events: gpui::Task<()>,
+    /// The directories of the data manager, which only the GTK thread can ask for.
+    #[cfg(target_os = "linux")]
+    data_store_directories: Vec<PathBuf>,
+    #[cfg(target_os = "linux")]
+    muted: std::cell::Cell<bool>,
+    /// Whether the GTK thread sets the context menu flag again in every page that finishes
+    /// loading.
+    #[cfg(target_os = "linux")]
+    context_menu_disabled: std::sync::Arc<AtomicBool>,
+    current_url: String,
+    history: NavigationHistory,
+    context_menu_listener_installed: std::cell::Cell<bool>,
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pending_bounds: PendingBounds,
+    window_title: String,
+    /// The document title as of the last title change that [`WebViewManager::title`] saw.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    title: String,
+    data_store: DataStoreHandle,
+    /// Awaits platform replies and work moved off this thread without blocking it.
+    foreground_executor: gpui::ForegroundExecutor,
+    background_executor: gpui::BackgroundExecutor,
+    /// The last zoom factor applied where the platform can't report it back.
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    zoom: std::cell::Cell<f64>,
+    /// The alpha set by `set_opacity`, which fades return to.
+    #[cfg(target_os = "macos")]
+    opacity: std::cell::Cell<f64>,
+    /// Where the webview sits within the window, applied together by `layout_webview`. On
+    /// Windows they live in the window procedure's data instead.
+    #[cfg(target_os = "macos")]
+    content_insets: std::cell::Cell<Edges<Pixels>>,
+    #[cfg(target_os = "macos")]
+    viewport_size: std::cell::Cell<Option<Size<Pixels>>>,
+    #[cfg(target_os = "macos")]
+    chrome_height: std::cell::Cell<Pixels>,
+    /// The size limits set so far, since they are applied together. On Windows they live in the
+    /// window procedure's data instead.
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    min_size: std::cell::Cell<Option<Size<Pixels>>>,
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    max_size: std::cell::Cell<Option<Size<Pixels>>>,
+    /// Width divided by height, applied together with the size limits.
+    #[cfg(target_os = "linux")]
+    aspect_ratio: std::cell::Cell<Option<f64>>,
+    /// `None` only while [`WebViewManagerBuilder::build`] is creating the webview.
+    _slot: Option<WebViewSlot>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl WebViewManager {
+    pub fn window_title(&self) -> &str {
+        &self.window_title
+    }
+
+    /// Whether cookies, cache, and other website data outlive this webview. Non-persistent
+    /// webviews get an isolated data store that is discarded when they are dropped.
+    pub fn is_persistent(&self) -> bool {
+        self.data_store.is_persistent()
+    }
+
+    /// The store this webview was built with, for building others that share it.
+    pub fn data_store(&self) -> &DataStoreHandle {
+        &self.data_store
+    }
+
+    /// The `WKWebView`, for calling Cocoa APIs this wrapper doesn't cover. The pointer is only
+    /// valid while this manager is alive, and must only be used on the main thread.
+    #[cfg(target_os = "macos")]
+    pub fn native_webview_handle(&self) -> *mut std::ffi::c_void {
+        self.ns_webview.cast()
+    }
+
+    /// The `NSWindow` hosting the webview. The pointer is only valid while this manager is alive,
+    /// and must only be used on the main thread.
+    #[cfg(target_os = "macos")]
+    pub fn native_window_handle(&self) -> *mut std::ffi::c_void {
+        self.floating_window.cast()
+    }
+
+    /// The `NSView` filling the strip reserved by [`Self::set_chrome_height`], for adding native
+    /// controls such as a URL field or back and forward buttons. It spans the window's width and
+    /// resizes with it, so its subviews should set autoresizing masks to follow. The pointer is
+    /// only valid while this manager is alive, and must only be used on the main thread.
+    #[cfg(target_os = "macos")]
+    pub fn native_chrome_view_handle(&self) -> *mut std::ffi::c_void {
+        self.chrome_view.cast()
+    }
+
+    /// Fades are only supported on macOS, so this shows or hides the window immediately.
+    #[cfg(not(target_os = "macos"))]
+    pub fn set_hidden_animated(&self, hidden: bool, _duration: Duration) {
+        self.set_hidden(hidden);
+    }
+
+    /// Null outside macOS.
+    #[cfg(not(target_os = "macos"))]
+    pub fn native_webview_handle(&self) -> *mut std::ffi::c_void {
+        std::ptr::null_mut()
+    }
+
+    /// Null outside macOS.
+    #[cfg(not(target_os = "macos"))]
+    pub fn native_window_handle(&self) -> *mut std::ffi::c_void {
+        std::ptr::null_mut()
+    }
+
+    /// Null outside macOS.
+    #[cfg(not(target_os = "macos"))]
+    pub fn native_chrome_view_handle(&self) -> *mut std::ffi::c_void {
+        std::ptr::null_mut()
+    }
+
+    /// Not every platform applies frame changes to a hidden window, so bounds set while hidden
+    /// are remembered and applied again by [`Self::apply_pending_bounds`].
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn remember_bounds_if_hidden(&self, bounds: Bounds<Pixels>) {
+        self.pending_bounds
+            .remember_if_hidden(bounds, !self.is_visible());
+    }
+
+    /// Called after showing the window.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn apply_pending_bounds(&self) {
+        if let Some(bounds) = self.pending_bounds.take() {
+            self.set_bounds(bounds);
+        }
+    }
+
+    /// Suppresses the right-click menu when disabled. The menu is enabled by default.
+    ///
+    /// User scripts can't be removed individually, so the first call installs a listener for every
+    /// future page and later calls only flip the flag it checks, which is set again in every page
+    /// that finishes loading.
+    pub fn set_context_menu_enabled(&self, enabled: bool) {
+        if enabled && !self.context_menu_listener_installed.get() {
+            return;
+        }
+        if !self.context_menu_listener_installed.replace(true) {
+            self.inject_script(CONTEXT_MENU_LISTENER_SCRIPT);
+        }
+        self.remember_context_menu_disabled(!enabled);
+        self.evaluate_javascript_fire_and_forget(&context_menu_flag_script(!enabled));
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn remember_context_menu_disabled(&self, disabled: bool) {
+        self.state.borrow_mut().context_menu_disabled = disabled;
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    fn remember_context_menu_disabled(&self, _disabled: bool) {}
+
+    /// Runs `source` in the current page and at document start in every page loaded from now on.
+    fn inject_script(&self, source: &str) {
+        self.add_user_script(source, true);
+        // Until the first load ends, the queued user script runs in the page that loads.
+        if self.has_loaded() {
+            self.evaluate_javascript_fire_and_forget(source);
+        }
+    }
+
+    /// Switches between [`WindowLevel::Floating`] and [`WindowLevel::Normal`].
+    pub fn set_always_on_top(&self, on_top: bool) {
+        self.set_window_level(if on_top {
+            WindowLevel::Floating
+        } else {
+            WindowLevel::Normal
+        });
+    }
+
+    /// Creates and shows a webview. See [`Self::builder`] for more options.
+    pub fn new(
+        parent_window_ptr: *mut std::ffi::c_void,
+        bounds: Bounds<Pixels>,
+        url: &str,
+        cx: &App,
+    ) -> Result<Self> {
+        Self::builder()
+            .url(url)
+            .bounds(bounds)
+            .build(parent_window_ptr, cx)
+    }
+
+    pub fn builder() -> WebViewManagerBuilder {
+        WebViewManagerBuilder::default()
+    }
+
+    /// URLs loaded through [`Self::navigate`] and its variants, oldest first, starting with the
+    /// initial URL. Links followed within the page are not included.
+    pub fn history(&self) -> &[String] {
+        &self.history.entries
+    }
+
+    /// Clears [`Self::history`]. The native back-forward list can't be cleared, so going back
+    /// still works afterwards.
+    pub fn clear_history(&mut self) {
+        self.history.entries.clear();
+    }
+
+    /// Captures what's needed to put the webview back where the user left it.
+    pub fn session_state(&self) -> WebViewSession {
+        WebViewSession {
+            url: self.page_url().unwrap_or_else(|| self.current_url.clone()),
+            history: self.history.entries.clone(),
+            bounds: self.tracked_bounds(),
+            visible: self.is_visible(),
+            interaction_state: self.interaction_state(),
+        }
+    }
+
+    /// Loads the session's page, preferring its interaction state where available so that the
+    /// scroll position and back-forward list come back too.
+    pub fn restore_session(&mut self, session: WebViewSession) {
+        if let Some(bounds) = session.bounds {
+            self.set_bounds(bounds);
+        }
+        let restored = session
+            .interaction_state
+            .as_deref()
+            .is_some_and(|interaction_state| self.restore_interaction_state(interaction_state));
+        if restored {
+            self.take_page_url();
+            self.current_url = session.url;
+        } else {
+            self.navigate(&session.url).log_err();
+        }
+        self.history.replace(session.history);
+        self.set_hidden(!session.visible);
+    }
+
+    /// Moves the window below `anchor`, e.g. a button's bounds in gpui's screen coordinates,
+    /// leaving `gap` between them. The window keeps its size, opens above the anchor when there's
+    /// no room below, and stays within the screen's visible area.
+    pub fn position_below_anchor(&self, anchor: Bounds<Pixels>, gap: Pixels) {
+        self.position_beside_anchor(anchor, gap, AnchorSide::Below);
+    }
+
+    /// Like [`Self::position_below_anchor`], but to the right of `anchor`, or its left when
+    /// there's no room.
+    pub fn position_right_of_anchor(&self, anchor: Bounds<Pixels>, gap: Pixels) {
+        self.position_beside_anchor(anchor, gap, AnchorSide::Right);
+    }
+
+    fn position_beside_anchor(&self, anchor: Bounds<Pixels>, gap: Pixels, side: AnchorSide) {
+        let size = self.get_bounds().size;
+        let origin = anchored_origin(anchor, size, gap, side, self.work_area());
+        self.set_bounds(Bounds { origin, size });
+    }
+
+    /// The URL of the page being shown. Unlike the URL last passed to [`Self::navigate`], this
+    /// follows redirects and links the user clicked once their loads finish.
+    pub fn current_url(&mut self) -> &str {
+        if let Some(page_url) = self.take_page_url() {
+            self.current_url = page_url;
+        }
+        &self.current_url
+    }
+
+    /// Starts loading [`navigation_target`] with `load`, returning the URL loaded, if any. First
+    /// catches up with where the page navigated on its own, so that deduplication compares against
+    /// the page actually shown. Nothing is recorded when `load` fails.
+    fn start_navigation(
+        &mut self,
+        url: &str,
+        load: impl FnOnce(&Self, &str) -> Result<()>,
+    ) -> Result<Option<String>> {
+        self.current_url();
+        let Some(url) = navigation_target(&self.current_url, url, self.last_load_succeeded())?
+        else {
+            return Ok(None);
+        };
+        self.start_load(&url, load).map(Some)
+    }
+
+    /// Like [`Self::start_navigation`], but loads `url` even when it is already current, for loads
+    /// such as POSTs that must never be skipped. Returns the normalized URL loaded.
+    fn start_load(
+        &mut self,
+        url: &str,
+        load: impl FnOnce(&Self, &str) -> Result<()>,
+    ) -> Result<String> {
+        self.current_url();
+        let url = normalize_url(url)?;
+        load(self, &url)?;
+        record_navigation(&mut self.current_url, &mut self.history, &url);
+        self.forget_replaced_page();
+        Ok(url)
+    }
+
+    /// The document title as of the last title change, without asking the platform, so it's
+    /// cheap enough to call while rendering. Empty until the page reports a title, and again from
+    /// the moment [`Self::navigate`] starts loading another page.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub fn title(&mut self) -> &str {
+        if let Some(title) = self.state.borrow_mut().new_title.take() {
+            self.title = title;
+        }
+        &self.title
+    }
+
+    /// Called once a load started through the manager replaces the page. The new page reports its
+    /// own title once it commits, but the previous page's title shouldn't be shown until then, and
+    /// the previous load's failure no longer makes its URL worth retrying.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn forget_replaced_page(&mut self) {
+        self.title.clear();
+        let mut state = self.state.borrow_mut();
+        state.new_title = None;
+        state.load.last_load_failed = false;
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    fn forget_replaced_page(&mut self) {}
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn set_basic_auth_state(
+        &mut self,
+        host: &str,
+        port: Option<u16>,
+        username: &str,
+        password: &str,
+    ) {
+        self.state.borrow_mut().basic_auth = Some(BasicAuth {
+            host: host.to_string(),
+            port,
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn page_url(&self) -> Option<String> {
+        self.state.borrow().page_url.clone()
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn take_page_url(&mut self) -> Option<String> {
+        self.state.borrow_mut().page_url.take()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    fn page_url(&self) -> Option<String> {
+        None
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    fn take_page_url(&mut self) -> Option<String> {
+        None
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn last_load_succeeded(&self) -> bool {
+        !self.state.borrow().load.last_load_failed
+    }
+
+    /// Load failures aren't observed here, so the current URL is never retried.
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    fn last_load_succeeded(&self) -> bool {
+        true
+    }
+
+    /// Whether the initial navigation has ended, successfully or not. Until then,
+    /// [`Self::evaluate_javascript`], [`Self::evaluate_javascript_fire_and_forget`], and
+    /// [`Self::add_user_script`] are queued and run in call order once it does, so setup scripts
+    /// don't run in the blank page being replaced, and user scripts also run once in the page
+    /// that loaded. Cancelling the initial navigation through [`Self::set_navigation_policy`]
+    /// ends it too. If the page's content process terminates first and isn't reloaded, queued
+    /// evaluations fail instead.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub fn has_loaded(&self) -> bool {
+        self.state.borrow().load.has_loaded
+    }
+
+    /// Load events aren't observed here, so scripts are never deferred.
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    pub fn has_loaded(&self) -> bool {
+        true
+    }
+
+    /// Makes `sender` resolve when the next main-frame load ends.
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn await_load(&self, sender: oneshot::Sender<Result<()>>, _url: &str) {
+        self.state.borrow_mut().pending_navigation = Some(sender);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn defer_script(&self, script: DeferredScript) {
+        self.state.borrow_mut().load.deferred_scripts.push(script);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn tracked_bounds(&self) -> Option<Bounds<Pixels>> {
+        self.state.borrow().last_reported_bounds
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn tracked_bounds(&self) -> Option<Bounds<Pixels>> {
+        None
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn interaction_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn restore_interaction_state(&self, _interaction_state: &[u8]) -> bool {
+        false
+    }
+
+    /// Caps [`Self::history`] at `limit` entries, dropping the oldest ones. Defaults to 100.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    /// Scrolls the page so that `(x, y)` is at the top left, in CSS pixels.
+    pub fn scroll_to(&self, x: f64, y: f64) {
+        if x.is_finite() && y.is_finite() {
+            self.evaluate_javascript_fire_and_forget(&format!("window.scrollTo({x}, {y})"));
+        }
+    }
+
+    pub fn scroll_by(&self, dx: f64, dy: f64) {
+        if dx.is_finite() && dy.is_finite() {
+            self.evaluate_javascript_fire_and_forget(&format!("window.scrollBy({dx}, {dy})"));
+        }
+    }
+
+    /// Scrolls the first element matching the CSS `selector` into view, if there is one.
+    pub fn scroll_to_element(&self, selector: &str) {
+        self.evaluate_javascript_fire_and_forget(&format!(
+            "document.querySelector({})?.scrollIntoView()",
+            serde_json::Value::from(selector)
+        ));
+    }
+
+    /// Passes the page's scroll offset in CSS pixels to `callback`, or `(0.0, 0.0)` when it can't
+    /// be read.
+    pub fn scroll_position(&self, callback: impl FnOnce((f64, f64)) + 'static) {
+        self.evaluate_javascript(SCROLL_POSITION_SCRIPT, move |result| {
+            callback(
+                result
+                    .ok()
+                    .and_then(|result| parse_scroll_position(&result))
+                    .unwrap_or_default(),
+            )
+        });
+    }
+
+    /// Passes the page's visible text to `callback`, e.g. to use the page as context for a
+    /// prompt. Fails when no page has loaded yet.
+    pub fn extract_text(&self, callback: impl FnOnce(Result<String>) + 'static) {
+        self.evaluate_javascript(EXTRACT_TEXT_SCRIPT, move |result| {
+            callback(result.and_then(|result| parse_extracted_text(&result)))
+        });
+    }
+
+    /// Like [`Self::extract_text`], but leaves out navigation, headers, footers, and sidebars,
+    /// preferring the page's `<main>` or `<article>` element when it has one. Relies on the page
+    /// marking these up semantically.
+    pub fn extract_readable_text(&self, callback: impl FnOnce(Result<String>) + 'static) {
+        self.evaluate_javascript(EXTRACT_READABLE_TEXT_SCRIPT, move |result| {
+            callback(result.and_then(|result| parse_extracted_text(&result)))
+        });
+    }
+
+    /// Evaluates `scripts` in a single round trip and passes their results to `callback`, in order.
+    /// Each script must be an expression; its result is converted to a string, with non-string
+    /// values encoded as JSON. A script that throws only fails its own result, but a syntax error
+    /// in any of them fails them all.
+    pub fn evaluate_batch(
+        &self,
+        scripts: &[&str],
+        callback: impl FnOnce(Vec<Result<String>>) + 'static,
+    ) {
+        if scripts.is_empty() {
+            callback(Vec::new());
+            return;
+        }
+        let count = scripts.len();
+        self.evaluate_javascript(&batch_script(scripts), move |result| {
+            callback(parse_batch_results(result, count))
+        });
+    }
+
+    /// Calls `handler` once per page load, after the page has finished loading and no `fetch` or
+    /// `XMLHttpRequest` has been in flight for `idle_ms`. Unlike the native finish event, this
+    /// waits for content the page loads from script. Calling this again replaces the handler and
+    /// the delay. Never fires on Linux, which doesn't deliver page messages yet.
+    pub fn on_network_idle(&mut self, idle_ms: u64, mut handler: impl FnMut() + 'static) {
+        self.register_message_handler(NETWORK_IDLE_MESSAGE_HANDLER, move |_| handler());
+        self.inject_script(&network_idle_script(idle_ms));
+    }
+
+    /// Passes the disk space used by this webview's data store, in bytes, to `callback`, e.g. to
+    /// offer [`Self::clear_website_data`]. Webviews sharing the store report the same size, and
+    /// non-persistent webviews report 0. The store's directories are walked on a background
+    /// thread.
+    pub fn data_store_size(&self, callback: impl FnOnce(u64) + 'static) {
+        if !self.is_persistent() {
+            callback(0);
+            return;
+        }
+        let directories = self.data_store_directories();
+        let size = self.background_executor.spawn(async move {
+            directories
+                .iter()
+                .map(|directory| directory_size(directory))
+                .sum::<u64>()
+        });
+        self.foreground_executor
+            .spawn(async move { callback(size.await) })
+            .detach();
+    }
+
+    /// Like [`Self::navigate`], but resolves once the load finishes and fails if it does. If the
+    /// webview is already on `url`, waits for any load in progress instead of reloading. Starting
+    /// another `navigate_async` before this one resolves makes it fail.
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    pub async fn navigate_async(&mut self, url: &str) -> Result<()> {
+        let normalized_url = normalize_url(url)?;
+        if self.current_url() == normalized_url && !self.is_loading() && self.last_load_succeeded()
+        {
+            return Ok(());
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.navigate(&normalized_url)?;
+        self.await_load(sender, &normalized_url);
+        receiver
+            .await
+            .map_err(|_| anyhow::anyhow!("navigation to {normalized_url} was superseded"))?
+    }
+}
+
+/// Configures a [`WebViewManager`] before creating it. Options left unset keep the platform
+/// defaults.
+pub struct WebViewManagerBuilder {
+    url: String,
+    bounds: Bounds<Pixels>,
+    title: Option<String>,
+    user_agent: Option<String>,
+    data_store: DataStoreHandle,
+    inspectable: bool,
+    window_level: WindowLevel,
+    activate: bool,
+    initially_hidden: bool,
+    titlebar_style: TitlebarStyle,
+    resizable: bool,
+    min_size: Option<Size<Pixels>>,
+    max_size: Option<Size<Pixels>>,
+    history_limit: Option<usize>,
+    scheme_handlers: Vec<(String, Box<dyn FnMut(SchemeRequest) -> SchemeResponse>)>,
+    media_autoplay: AutoplayPolicy,
+    proxy: Option<ProxyConfig>,
+    cache_policy: CachePolicy,
+    timeout: Duration,
+    attach_to_parent: bool,
+    screen: ScreenSelector,
+    javascript_enabled: bool,
+    load_images: bool,
+    fraudulent_website_warning: bool,
+}
+
+impl Default for WebViewManagerBuilder {
+    fn default() -> Self {
+        Self {
+            url: "about:blank".to_string(),
+            bounds: Bounds {
+                origin: point(px(0.0), px(0.0)),
+                size: size(px(400.0), px(600.0)),
+            },
+            title: None,
+            user_agent: None,
+            data_store: DataStoreHandle::default(),
+            inspectable: false,
+            window_level: WindowLevel::default(),
+            activate: true,
+            initially_hidden: false,
+            titlebar_style: TitlebarStyle::default(),
+            resizable: true,
+            min_size: None,
+            max_size: None,
+            history_limit: None,
+            scheme_handlers: Vec::new(),
+            media_autoplay: AutoplayPolicy::default(),
+            proxy: None,
+            cache_policy: CachePolicy::default(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            attach_to_parent: true,
+            screen: ScreenSelector::default(),
+            javascript_enabled: true,
+            load_images: true,
+            fraudulent_website_warning: true,
+        }
+    }
+}
+
+impl WebViewManagerBuilder {
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    pub fn bounds(mut self, bounds: Bounds<Pixels>) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// See [`WebViewManager::is_persistent`]. Defaults to `true`. Non-persistent webviews each get
+    /// their own ephemeral store. Replaces any [`Self::data_store`].
+    pub fn persistent(mut self, persistent: bool) -> Self {
+        self.data_store = if persistent {
+            DataStoreHandle::default()
+        } else {
+            DataStoreHandle::ephemeral()
+        };
+        self
+    }
+
+    /// Builds the webview on `data_store`, so that it shares cookies, cache, and storage with
+    /// other webviews built with the same handle. Replaces any [`Self::persistent`].
+    pub fn data_store(mut self, data_store: DataStoreHandle) -> Self {
+        self.data_store = data_store;
+        self
+    }
+
+    pub fn inspectable(mut self, inspectable: bool) -> Self {
+        self.inspectable = inspectable;
+        self
+    }
+
+    /// Defaults to [`WindowLevel::Floating`].
+    pub fn window_level(mut self, window_level: WindowLevel) -> Self {
+        self.window_level = window_level;
+        self
+    }
+
+    /// Whether showing the new window takes keyboard focus. Defaults to `true`.
+    pub fn activate(mut self, activate: bool) -> Self {
+        self.activate = activate;
+        self
+    }
+
+    /// Creates the window without showing it, so the caller can finish setting it up and
+    /// navigate before calling [`WebViewManager::set_hidden`] or
+    /// [`WebViewManager::show_without_activating`]. Defaults to `false`.
+    pub fn initially_hidden(mut self, hidden: bool) -> Self {
+        self.initially_hidden = hidden;
+        self
+    }
+
+    /// Defaults to [`TitlebarStyle::Transparent`]. Windows and Linux only distinguish
+    /// [`TitlebarStyle::Hidden`], which removes the window's frame.
+    pub fn titlebar_style(mut self, style: TitlebarStyle) -> Self {
+        self.titlebar_style = style;
+        self
+    }
+
+    /// See [`WebViewManager::set_resizable`]. Defaults to `true`.
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    /// See [`WebViewManager::set_min_size`].
+    pub fn min_size(mut self, size: Size<Pixels>) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// See [`WebViewManager::set_max_size`].
+    pub fn max_size(mut self, size: Size<Pixels>) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// See [`WebViewManager::set_history_limit`].
+    pub fn history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = Some(limit);
+        self
+    }
+
+    /// Serves every request for a `scheme:` URL from `handler`, so pages bundled with the app can
+    /// be loaded without a server. Schemes are case-insensitive and can't be ones WebKit handles
+    /// itself, such as `https` or `file`. Only supported on macOS; building fails elsewhere.
+    pub fn register_scheme_handler(
+        mut self,
+        scheme: impl Into<String>,
+        handler: impl FnMut(SchemeRequest) -> SchemeResponse + 'static,
+    ) -> Self {
+        self.scheme_handlers
+            .push((scheme.into().to_ascii_lowercase(), Box::new(handler)));
+        self
+    }
+
+    /// Defaults to [`AutoplayPolicy::RequireUserGesture`].
+    pub fn media_autoplay(mut self, policy: AutoplayPolicy) -> Self {
+        self.media_autoplay = policy;
+        self
+    }
+
+    /// Routes the webview's traffic through an HTTP proxy. Creating the webview fails if the
+    /// platform can't apply it, rather than silently connecting directly.
+    ///
+    /// On macOS this requires macOS 14 and a data store other than the default one, such as
+    /// [`DataStoreHandle::ephemeral`] or [`DataStoreHandle::named`], since WebKit only applies
+    /// proxies to those. On Windows the proxy applies to every
+    /// webview in the app and credentials aren't supported.
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Applies to every page this webview loads. Only supported on macOS; defaults to
+    /// [`CachePolicy::Default`].
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// How long a navigation may wait for the server before failing with
+    /// [`NAVIGATION_TIMED_OUT`]. Only supported on macOS; defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether the window moves, minimizes, and closes along with the parent window passed to
+    /// [`Self::build`]. Turn this off for webviews that should outlive the editor window. Not
+    /// supported on Linux; defaults to `true`.
+    pub fn attach_to_parent(mut self, attach: bool) -> Self {
+        self.attach_to_parent = attach;
+        self
+    }
+
+    /// The window opens near the top-left of the selected screen's visible area, shrunk to fit
+    /// it. On Linux it opens at the bounds' origin instead, moved onto the selected screen's work
+    /// area. Defaults to [`ScreenSelector::Parent`].
+    pub fn screen(mut self, screen: ScreenSelector) -> Self {
+        self.screen = screen;
+        self
+    }
+
+    /// See [`WebViewManager::set_javascript_enabled`]. Defaults to `true`.
+    pub fn javascript_enabled(mut self, enabled: bool) -> Self {
+        self.javascript_enabled = enabled;
+        self
+    }
+
+    /// See [`WebViewManager::set_load_images`]. Defaults to `true`.
+    pub fn load_images(mut self, load: bool) -> Self {
+        self.load_images = load;
+        self
+    }
+
+    /// See [`WebViewManager::set_fraudulent_website_warning_enabled`]. Defaults to `true`.
+    pub fn fraudulent_website_warning(mut self, enabled: bool) -> Self {
+        self.fraudulent_website_warning = enabled;
+        self
+    }
+
+    /// Fails without creating anything while [`set_max_webviews`]'s limit is reached.
+    pub fn build(
+        self,
+        parent_window_ptr: *mut std::ffi::c_void,
+        cx: &App,
+    ) -> Result<WebViewManager> {
+        #[cfg(not(target_os = "macos"))]
+        anyhow::ensure!(
+            self.scheme_handlers.is_empty(),
+            "custom URL scheme handlers are only supported on macOS"
+        );
+        #[cfg(target_os = "linux")]
+        anyhow::ensure!(
+            self.data_store.identifier().is_none(),
+            "named data stores are not supported on Linux"
+        );
+        let slot = WEBVIEW_SLOTS.acquire()?;
+        let mut manager = WebViewManager::create(parent_window_ptr, &self, cx)?;
+        manager._slot = Some(slot);
+        if let Some(title) = &self.title {
+            manager.set_title(title);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            manager.set_user_agent(user_agent);
+        }
+        if self.inspectable {
+            manager.set_inspectable(true);
+        }
+        if self.window_level != WindowLevel::default() {
+            manager.set_window_level(self.window_level);
+        }
+        if let Some(size) = self.min_size {
+            manager.set_min_size(size);
+        }
+        if let Some(size) = self.max_size {
+            manager.set_max_size(size);
+        }
+        if !self.resizable {
+            manager.set_resizable(false);
+        }
+        if let Some(limit) = self.history_limit {
+            manager.set_history_limit(limit);
+        }
+        #[cfg(target_os = "macos")]
+        manager
+            .state
+            .borrow_mut()
+            .scheme_handlers
+            .extend(self.scheme_handlers);
+        Ok(manager)
+    }
+}
+
+impl WebViewManager {
+    /// Whether webviews can be created in this environment, checked without creating one. When
+    /// this is false, callers should fall back to opening URLs in the system browser.
+    #[cfg(target_os = "macos")]
+    pub fn is_supported() -> bool {
+        ["WKWebView", "WKWebViewConfiguration", "WKWebsiteDataStore"]
+            .iter()
+            .all(|name| Class::get(name).is_some())
+            && unsafe {
+                let screen: id = msg_send![class!(NSScreen), mainScreen];
+                screen != nil
+            }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn create(
+        parent_window_ptr: *mut std::ffi::c_void,
+        options: &WebViewManagerBuilder,
+        cx: &App,
+    ) -> Result<Self> {
+        ensure_main_thread("new")?;
+        let bounds = options.bounds;
+        let activate = options.activate;
+        let url = &normalize_url(&options.url)?;
+        unsafe {
+            let request = mutable_request(url, options.cache_policy, options.timeout)?;
+            let wk_config_class = webkit_class("WKWebViewConfiguration")?;
+            let wk_webview_class = webview_class()?;
+            let delegate_class = delegate_class()?;
+
+            // Everything that can fail happens before the first object is allocated, so that errors
+            // leave nothing behind.
+            let screen = select_screen(options.screen, parent_window_ptr as id);
+            anyhow::ensure!(
+                screen != nil,
+                "no screen to show the webview on (macOS {})",
+                macos_version()
+            );
+            for (scheme, _) in &options.scheme_handlers {
+                let handled: bool =
+                    msg_send![wk_webview_class, handlesURLScheme: ns_string(scheme)];
+                anyhow::ensure!(!handled, "WebKit already handles the {scheme:?} URL scheme");
+            }
+            let data_store = options.data_store.native_store()?;
+            let proxy_config = match &options.proxy {
+                Some(proxy) => {
+                    anyhow::ensure!(
+                        !options.data_store.is_default(),
+                        "webview proxies require a data store other than the default one on macOS"
+                    );
+                    Some(nw_proxy_config(proxy)?)
+                }
+                None => None,
+            };
+            let visible_frame: NSRect = msg_send![screen, visibleFrame];
+            let min_x = visible_frame.origin.x;
+            let min_y = visible_frame.origin.y;
+            let max_x = min_x + visible_frame.size.width;
+            let max_y = min_y + visible_frame.size.height;
+
+            let width = f64::from(bounds.size.width).min(visible_frame.size.width);
+            let height = f64::from(bounds.size.height).min(visible_frame.size.height);
+            let x = (min_x + 100.0).min(max_x - width);
+            let y = (max_y - height - 100.0).max(min_y);
+
+            let window_rect = NSRect {
+                origin: NSPoint::new(x, y),
+                size: NSSize::new(width, height),
+            };
+
+            let style_mask = options.titlebar_style.ns_style_mask();
+
+            let floating_window: id = msg_send![class!(NSWindow), alloc];
+            let floating_window: id = msg_send![
+                floating_window,
+                initWithContentRect:window_rect
+                styleMask:style_mask
+                backing:NSBackingStoreType::NSBackingStoreBuffered
+                defer:NO
+            ];
+
+            if options.titlebar_style != TitlebarStyle::Visible {
+                let _: () = msg_send![floating_window, setTitlebarAppearsTransparent:YES];
+                let _: () = msg_send![floating_window, setTitleVisibility:1i64]; // NSWindowTitleHidden
+            }
+            if options.titlebar_style == TitlebarStyle::Hidden {
+                // NSWindowCloseButton, NSWindowMiniaturizeButton, NSWindowZoomButton
+                for button_kind in 0..3u64 {
+                    let button: id = msg_send![floating_window, standardWindowButton: button_kind];
+                    let _: () = msg_send![button, setHidden: YES];
+                }
+            }
+            let _: () = msg_send![
+                floating_window,
+                setLevel: WindowLevel::default().ns_window_level()
+            ];
+            let _: () = msg_send![floating_window, setOpaque:YES];
+            let _: () = msg_send![floating_window, setHasShadow:YES];
+            // The manager owns the window, so closing it must not release it out from under `Drop`.
+            let _: () = msg_send![floating_window, setReleasedWhenClosed:NO];
+
+            let state = Rc::new(RefCell::new(WebViewState::default()));
+            let delegate: id = msg_send![delegate_class, new];
+            (*delegate).set_ivar::<*mut c_void>(
+                WEBVIEW_STATE_IVAR,
+                Rc::into_raw(state.clone()) as *mut c_void,
+            );
+
+            let config: id = msg_send![wk_config_class, new];
+            // Handlers must be registered before the webview is created, and the manager installs
+            // them in `state` before the initial request starts.
+            for (scheme, _) in &options.scheme_handlers {
+                let _: () = msg_send![
+                    config,
+                    setURLSchemeHandler: delegate
+                    forURLScheme: ns_string(scheme)
+                ];
+            }
+            let user_content_controller: id = msg_send![config, userContentController];
+            let user_content_controller: id = msg_send![user_content_controller, retain];
+
+            let _: () = msg_send![config, setWebsiteDataStore:data_store];
+            if let Some(proxy_config) = proxy_config {
+                let proxy_configs: id = msg_send![class!(NSArray), arrayWithObject: proxy_config];
+                let _: () = msg_send![data_store, setProxyConfigurations: proxy_configs];
+            }
+            let media_types = options.media_autoplay.wk_media_types();
+            let _: () = msg_send![config, setMediaTypesRequiringUserActionForPlayback: media_types];
+            configure_javascript(config, options.javascript_enabled);
+            configure_fraudulent_website_warning(config, options.fraudulent_website_warning);
+            if !options.load_images {
+                log::warn!("Disabling image loading is not supported on macOS");
+            }
+
+            let webview_frame = NSRect {
+                origin: NSPoint::new(0.0, 0.0),
+                size: NSSize::new(width, height),
+            };
+
+            let webview: id = msg_send![wk_webview_class, alloc];
+            let webview: id = msg_send![webview, initWithFrame:webview_frame configuration:config];
+            // The webview keeps its own copy of the configuration.
+            let _: () = msg_send![config, release];
+            // Released on drop, so that key equivalents never reach a freed view.
+            let parent_view = parent_window_ptr as id;
+            if parent_view != nil {
+                let _: id = msg_send![parent_view, retain];
+            }
+            (*webview).set_ivar::<id>(PARENT_VIEW_IVAR, parent_view);
+
+            let _: () = msg_send![webview, setNavigationDelegate: delegate];
+            let _: () = msg_send![webview, setUIDelegate: delegate];
+            let _: () = msg_send![floating_window, setDelegate: delegate];
+            for key_path in OBSERVED_KEY_PATHS {
+                let _: () = msg_send![
+                    webview,
+                    addObserver: delegate
+                    forKeyPath: ns_string(key_path)
+                    options: 1u64 // NSKeyValueObservingOptionNew
+                    context: std::ptr::null_mut::<c_void>()
+                ];
+            }
+
+            let autoresizing_mask: u64 = 2 | 16;
+            let _: () = msg_send![webview, setAutoresizingMask: autoresizing_mask];
+
+            let content_view: id = msg_send![floating_window, contentView];
+            let _: () = msg_send![content_view, addSubview:webview];
+
+            // Empty until `set_chrome_height` reserves space for it above the webview.
+            let chrome_view: id = msg_send![class!(NSView), alloc];
+            let chrome_view: id = msg_send![
+                chrome_view,
+                initWithFrame: NSRect {
+                    origin: NSPoint::new(0.0, height),
+                    size: NSSize::new(width, 0.0),
+                }
+            ];
+            let chrome_autoresizing_mask: u64 = 2 | 8; // NSViewWidthSizable | NSViewMinYMargin
+            let _: () = msg_send![chrome_view, setAutoresizingMask: chrome_autoresizing_mask];
+            let _: () = msg_send![content_view, addSubview: chrome_view];
+            let _: () = msg_send![chrome_view, release];
+
+            let _: () = msg_send![webview, loadRequest:request];
+
+            if !options.initially_hidden {
+                if activate {
+                    let _: () = msg_send![floating_window, makeKeyAndOrderFront:nil];
+                } else {
+                    let _: () = msg_send![floating_window, orderFront:nil];
+                }
+            }
+            let parent_window: id = if options.attach_to_parent && !parent_window_ptr.is_null() {
+                msg_send![parent_window_ptr as id, window]
+            } else {
+                nil
+            };
+            if parent_window != nil {
+                let _: id = msg_send![parent_window, retain];
+            }
+            // A hidden window is attached once it's shown.
+            if parent_window != nil && !options.initially_hidden {
+                let _: () = msg_send![
+                    parent_window,
+                    addChildWindow: floating_window
+                    ordered: 1i64 // NSWindowAbove
+                ];
+            }
+
+            webview_info!("Created floating webview window ({}x{})", width, height);
+
+            Ok(Self {
+                floating_window,
+                ns_webview: webview,
+                chrome_view,
+                user_content_controller,
+                delegate,
+                state,
+                current_url: url.to_string(),
+                history: NavigationHistory::new(url),
+                context_menu_listener_installed: Cell::new(false),
+                pending_bounds: PendingBounds::default(),
+                window_title: String::new(),
+                title: String::new(),
+                data_store: options.data_store.clone(),
+                zoom: Cell::new(1.0),
+                opacity: Cell::new(1.0),
+                content_insets: Cell::new(Edges::default()),
+                viewport_size: Cell::new(None),
+                chrome_height: Cell::new(px(0.0)),
+                min_size: Cell::new(None),
+                max_size: Cell::new(None),
+                cache_policy: options.cache_policy,
+                request_timeout: options.timeout,
+                parent_window,
+                foreground_executor: cx.foreground_executor().clone(),
+                background_executor: cx.background_executor().clone(),
+                _slot: None,
+                _not_send: PhantomData,
+            })
+        }
+    }
+
+    /// Loads `url`, adding `https://` when it has no scheme. Does nothing when the webview is
+    /// already showing `url`, unless its last load failed. Fails when `url` is invalid.
+    #[cfg(target_os = "macos")]
+    pub fn navigate(&mut self, url: &str) -> Result<()> {
+        let url = self.start_navigation(url, |this, url| unsafe {
+            let request = mutable_request(url, this.cache_policy, this.request_timeout)?;
+            let _: () = msg_send![this.ns_webview, loadRequest: request];
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!("WebView navigated to: {}", url);
+        }
+        Ok(())
+    }
+
+    /// Navigates like [`Self::navigate`], sending `headers` with the request, e.g. for
+    /// `Authorization`. Headers only apply to this request, not to subresources or later
+    /// navigations.
+    #[cfg(target_os = "macos")]
+    pub fn navigate_with_headers(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<()> {
+        let url = self.start_navigation(url, |this, url| unsafe {
+            let request = mutable_request(url, this.cache_policy, this.request_timeout)?;
+            for (name, value) in headers {
+                let _: () = msg_send![
+                    request,
+                    setValue: ns_string(value)
+                    forHTTPHeaderField: ns_string(name)
+                ];
+            }
+            let _: id = msg_send![this.ns_webview, loadRequest: request];
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!(
+                "WebView navigated to: {} with {} headers",
+                url,
+                headers.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Loads `url` with a POST request carrying `body`. Unlike [`Self::navigate`], repeated loads of
+    /// the same URL are not skipped, since each one submits data. Fails when `url` is invalid.
+    ///
+    /// Older WebKit releases silently dropped the body of POST requests passed to `loadRequest:`.
+    /// Where that matters, render an auto-submitting `<form>` with [`Self::load_html`] instead.
+    #[cfg(target_os = "macos")]
+    pub fn load_post(&mut self, url: &str, body: &[u8], content_type: &str) -> Result<()> {
+        let url = self.start_load(url, |this, url| unsafe {
+            let request = mutable_request(url, this.cache_policy, this.request_timeout)?;
+            let _: () = msg_send![request, setHTTPMethod: ns_string("POST")];
+            let data: id = msg_send![
+                class!(NSData),
+                dataWithBytes: body.as_ptr() as *const c_void
+                length: body.len()
+            ];
+            let _: () = msg_send![request, setHTTPBody: data];
+            let _: () = msg_send![
+                request,
+                setValue: ns_string(content_type)
+                forHTTPHeaderField: ns_string("Content-Type")
+            ];
+            let _: id = msg_send![this.ns_webview, loadRequest: request];
+            Ok(())
+        })?;
+        webview_info!("WebView posted {} bytes to: {}", body.len(), url);
+        Ok(())
+    }
+
+    /// Loads the local file at `path`, letting the page read the other files in its directory.
+    /// Like [`Self::navigate`], does nothing when the file is already shown. Fails when `path`
+    /// doesn't exist or isn't a file.
+    #[cfg(target_os = "macos")]
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let (path, url) = file_url(path)?;
+        let url = self.start_navigation(&url, |this, _| unsafe {
+            let file_url: id = msg_send![
+                class!(NSURL),
+                fileURLWithPath: ns_string(&path.to_string_lossy())
+            ];
+            anyhow::ensure!(
+                file_url != nil,
+                "can't load {}: invalid file path",
+                path.display()
+            );
+            let directory_url: id = msg_send![file_url, URLByDeletingLastPathComponent];
+            let _: id = msg_send![
+                this.ns_webview,
+                loadFileURL: file_url
+                allowingReadAccessToURL: directory_url
+            ];
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!("WebView loaded file: {}", url);
+        }
+        Ok(())
+    }
+
+    /// Renders `html` directly. Relative references in the document resolve against `base_url`.
+    #[cfg(target_os = "macos")]
+    pub fn load_html(&mut self, html: &str, base_url: Option<&str>) {
+        self.current_url = base_url.unwrap_or("about:blank").to_string();
+
+        unsafe {
+            let base_nsurl: id = match base_url {
+                Some(base_url) => msg_send![class!(NSURL), URLWithString: ns_string(base_url)],
+                None => nil,
+            };
+            let _: () = msg_send![
+                self.ns_webview,
+                loadHTMLString: ns_string(html)
+                baseURL: base_nsurl
+            ];
+            webview_info!("WebView loaded HTML string ({} bytes)", html.len());
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn reload(&self) {
+        unsafe {
+            let _: () = msg_send![self.ns_webview, reload];
+            webview_info!("WebView reloaded: {}", self.current_url);
+        }
+    }
+
+    /// Reloads the current page, revalidating cached resources with the origin server.
+    #[cfg(target_os = "macos")]
+    pub fn reload_ignoring_cache(&self) {
+        unsafe {
+            let _: () = msg_send![self.ns_webview, reloadFromOrigin];
+            webview_info!("WebView reloaded from origin: {}", self.current_url);
+        }
+    }
+
+    /// Cancels any in-flight load. Safe to call when nothing is loading.
+    #[cfg(target_os = "macos")]
+    pub fn stop_loading(&self) {
+        unsafe {
+            let _: () = msg_send![self.ns_webview, stopLoading];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn go_back(&self) {
+        unsafe {
+            let _: () = msg_send![self.ns_webview, goBack];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn go_forward(&self) {
+        unsafe {
+            let _: () = msg_send![self.ns_webview, goForward];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn can_go_back(&self) -> bool {
+        unsafe {
+            let can_go_back: bool = msg_send![self.ns_webview, canGoBack];
+            can_go_back
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn can_go_forward(&self) -> bool {
+        unsafe {
+            let can_go_forward: bool = msg_send![self.ns_webview, canGoForward];
+            can_go_forward
+        }
+    }
+
+    /// Moves and resizes the window. `bounds` is in gpui's top-left-origin screen coordinates.
+    #[cfg(target_os = "macos")]
+    pub fn set_bounds(&self, bounds: Bounds<Pixels>) {
+        self.remember_bounds_if_hidden(bounds);
+        unsafe {
+            let screen: id = msg_send![class!(NSScreen), mainScreen];
+            let screen_frame: NSRect = msg_send![screen, frame];
+
+            let width: f64 = bounds.size.width.into();
+            let height: f64 = bounds.size.height.into();
+            let x: f64 = bounds.origin.x.into();
+            let y = flip_y(bounds.origin.y.into(), height, screen_frame.size.height);
+
+            let frame = NSRect {
+                origin: NSPoint::new(x, y),
+                size: NSSize::new(width, height),
+            };
+            // The webview follows via its autoresizing mask.
+            let _: () = msg_send![self.floating_window, setFrame:frame display:YES];
+        }
+    }
+
+    /// Returns the window frame in gpui's top-left-origin screen coordinates.
+    #[cfg(target_os = "macos")]
+    pub fn get_bounds(&self) -> Bounds<Pixels> {
+        unsafe { window_bounds(self.floating_window) }
+    }
+
+    /// The visible frame of the window's screen, excluding the menu bar and Dock.
+    #[cfg(target_os = "macos")]
+    fn work_area(&self) -> Option<Bounds<Pixels>> {
+        unsafe {
+            let main_screen: id = msg_send![class!(NSScreen), mainScreen];
+            let window_screen: id = msg_send![self.floating_window, screen];
+            let screen = if window_screen != nil {
+                window_screen
+            } else {
+                main_screen
+            };
+            if screen == nil || main_screen == nil {
+                return None;
+            }
+            let main_frame: NSRect = msg_send![main_screen, frame];
+            let frame: NSRect = msg_send![screen, visibleFrame];
+            let y = flip_y(frame.origin.y, frame.size.height, main_frame.size.height);
+            Some(Bounds {
+                origin: point(px(frame.origin.x as f32), px(y as f32)),
+                size: size(px(frame.size.width as f32), px(frame.size.height as f32)),
+            })
+        }
+    }
+
+    /// Registers a handler called with the window frame, in the coordinates used by
+    /// [`Self::get_bounds`], after the user moves or resizes the window.
+    #[cfg(target_os = "macos")]
+    pub fn on_bounds_changed(&mut self, handler: impl FnMut(Bounds<Pixels>) + 'static) {
+        self.state.borrow_mut().bounds_changed = Some(Box::new(handler));
+    }
+
+    /// Evaluates `script` in the page and passes its result, converted to a string, to `callback`.
+    ///
+    /// WebKit invokes the completion handler on the main thread, so `callback` runs there too.
+    #[cfg(target_os = "macos")]
+    pub fn evaluate_javascript(
+        &self,
+        script: &str,
+        callback: impl FnOnce(Result<String>) + 'static,
+    ) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::Evaluate(
+                script.to_string(),
+                Some(Box::new(callback)),
+            ));
+            return;
+        }
+        unsafe { evaluate_script(self.ns_webview, script, Some(Box::new(callback))) }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn evaluate_javascript_fire_and_forget(&self, script: &str) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::Evaluate(script.to_string(), None));
+            return;
+        }
+        unsafe { evaluate_script(self.ns_webview, script, None) }
+    }
+
+    /// Registers a handler invoked with the final URL whenever a main-frame navigation finishes.
+    #[cfg(target_os = "macos")]
+    pub fn on_did_finish_navigation(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().did_finish_navigation = Some(Box::new(handler));
+    }
+
+    /// Registers a handler invoked with the failing URL and `NSError` code when a navigation
+    /// fails, whether before or after the response was committed. Timeouts report
+    /// [`NAVIGATION_TIMED_OUT`].
+    #[cfg(target_os = "macos")]
+    pub fn on_navigation_failed(&mut self, handler: impl FnMut(String, i64) + 'static) {
+        self.state.borrow_mut().navigation_failed = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called when the page's content process crashes, leaving it blank.
+    /// Without a handler, the page is reloaded, unless it crashed again before that reload
+    /// finished.
+    #[cfg(target_os = "macos")]
+    pub fn on_crash(&mut self, handler: impl FnMut() + 'static) {
+        self.state.borrow_mut().crash = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called with the page's load progress, from 0.0 to 1.0, as it changes.
+    #[cfg(target_os = "macos")]
+    pub fn on_progress(&mut self, handler: impl FnMut(f64) + 'static) {
+        self.state.borrow_mut().progress = Some(Box::new(handler));
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn estimated_progress(&self) -> f64 {
+        unsafe { msg_send![self.ns_webview, estimatedProgress] }
+    }
+
+    /// Consults `policy` with the URL of every navigation before it starts, including those
+    /// triggered by the page itself.
+    #[cfg(target_os = "macos")]
+    pub fn set_navigation_policy(
+        &mut self,
+        policy: impl FnMut(&str) -> NavigationDecision + 'static,
+    ) {
+        self.state.borrow_mut().navigation_policy = Some(Box::new(policy));
+    }
+
+    /// Routes downloads to `handler`, which receives the suggested file name and returns where to
+    /// save the file, or `None` to cancel. Requires macOS 11.3 or later; downloads are ignored on
+    /// older systems.
+    #[cfg(target_os = "macos")]
+    pub fn on_download(&mut self, handler: impl FnMut(String) -> Option<PathBuf> + 'static) {
+        if Class::get("WKDownload").is_none() {
+            log::warn!("WebView downloads require macOS 11.3 or later");
+        }
+        self.state.borrow_mut().download = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called with the destination of each completed download, or the error
+    /// that interrupted it.
+    #[cfg(target_os = "macos")]
+    pub fn on_download_finished(&mut self, handler: impl FnMut(Result<PathBuf>) + 'static) {
+        self.state.borrow_mut().download_finished = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called once when the user closes the window. Closing only hides it, so
+    /// the manager stays usable until it is dropped.
+    #[cfg(target_os = "macos")]
+    pub fn on_close(&mut self, handler: impl FnOnce() + 'static) {
+        self.state.borrow_mut().close = Some(Box::new(handler));
+    }
+
+    /// Registers a handler for URLs the page asks to open in a new window, such as
+    /// `target="_blank"` links. Without a handler they load in this webview.
+    #[cfg(target_os = "macos")]
+    pub fn on_new_window(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().new_window = Some(Box::new(handler));
+    }
+
+    /// Registers a handler for `alert()` dialogs, called with the message. Without one, alerts are
+    /// dismissed immediately.
+    #[cfg(target_os = "macos")]
+    pub fn on_js_alert(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().js_alert = Some(Box::new(handler));
+    }
+
+    /// Registers a handler for `confirm()` dialogs, returning whether the user confirmed. Without
+    /// one, every dialog is confirmed.
+    #[cfg(target_os = "macos")]
+    pub fn on_js_confirm(&mut self, handler: impl FnMut(String) -> bool + 'static) {
+        self.state.borrow_mut().js_confirm = Some(Box::new(handler));
+    }
+
+    /// Registers a handler for `prompt()` dialogs, called with the message and default text and
+    /// returning the answer, or `None` to cancel. Without one, the default text is returned.
+    #[cfg(target_os = "macos")]
+    pub fn on_js_prompt(
+        &mut self,
+        handler: impl FnMut(String, String) -> Option<String> + 'static,
+    ) {
+        self.state.borrow_mut().js_prompt = Some(Box::new(handler));
+    }
+
+    /// Registers a handler deciding whether a page may use the camera or microphone, called with
+    /// the kind of access and the requesting origin. Requests are denied without a handler.
+    #[cfg(target_os = "macos")]
+    pub fn on_permission_request(
+        &mut self,
+        handler: impl FnMut(PermissionKind, String) -> bool + 'static,
+    ) {
+        self.state.borrow_mut().permission_request = Some(Box::new(handler));
+    }
+
+    /// Registers a handler answering authentication challenges, including the TLS server trust
+    /// check made for every HTTPS connection. Returning [`AuthResponse::UseCredential`] for a
+    /// server trust challenge accepts the certificate without validation, so only do so for hosts
+    /// you control, such as a local development server.
+    #[cfg(target_os = "macos")]
+    pub fn on_auth_challenge(
+        &mut self,
+        handler: impl FnMut(AuthChallenge) -> AuthResponse + 'static,
+    ) {
+        self.state.borrow_mut().auth_challenge = Some(Box::new(handler));
+    }
+
+    /// Answers HTTP basic auth challenges from `host`, on `port` or any port when `None`, with
+    /// these credentials, ahead of any handler registered with [`Self::on_auth_challenge`].
+    /// Challenges from other servers, and one that rejects the credentials, go to the handler.
+    #[cfg(target_os = "macos")]
+    pub fn set_basic_auth(
+        &mut self,
+        host: &str,
+        port: Option<u16>,
+        username: &str,
+        password: &str,
+    ) {
+        self.set_basic_auth_state(host, port, username, password);
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn clear_basic_auth(&mut self) {
+        self.state.borrow_mut().basic_auth = None;
+    }
+
+    /// Registers a handler building the context menu when it opens. Returned items replace the
+    /// native menu; returning none keeps it.
+    #[cfg(target_os = "macos")]
+    pub fn on_context_menu(
+        &mut self,
+        handler: impl FnMut(ContextMenuContext) -> Vec<MenuItem> + 'static,
+    ) {
+        self.state.borrow_mut().context_menu = Some(Box::new(handler));
+    }
+
+    /// Forwards these shortcuts, such as `"cmd-shift-p"`, to the host window instead of the
+    /// webview, replacing any set before. Invalid shortcuts are logged and skipped.
+    #[cfg(target_os = "macos")]
+    pub fn set_key_passthrough(&self, keys: &[&str]) {
+        self.state.borrow_mut().key_passthrough = keys
+            .iter()
+            .filter_map(|key| {
+                Keystroke::parse(key)
+                    .inspect_err(|error| log::error!("Invalid passthrough key {key:?}: {error}"))
+                    .ok()
+            })
+            .collect();
+    }
+
+    /// Whether Cmd-W closes the webview window. When disabled, Cmd-W does nothing unless it's
+    /// passed through with [`Self::set_key_passthrough`]. Enabled by default.
+    #[cfg(target_os = "macos")]
+    pub fn set_close_on_cmd_w(&self, close: bool) {
+        self.state.borrow_mut().keep_open_on_cmd_w = !close;
+    }
+
+    /// Registers a handler called with the document title whenever it changes.
+    #[cfg(target_os = "macos")]
+    pub fn on_title_changed(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().title_changed = Some(Box::new(handler));
+    }
+
+    /// Allows attaching Safari's Web Inspector. Once enabled, right-clicking the page offers an
+    /// "Inspect Element" entry. Requires macOS 13.3 or later; a no-op on older systems.
+    #[cfg(target_os = "macos")]
+    pub fn set_inspectable(&self, inspectable: bool) {
+        unsafe {
+            let responds: bool =
+                msg_send![self.ns_webview, respondsToSelector: sel!(setInspectable:)];
+            if responds {
+                let inspectable = if inspectable { YES } else { NO };
+                let _: () = msg_send![self.ns_webview, setInspectable: inspectable];
+            } else {
+                log::warn!("WebView inspection requires macOS 13.3 or later");
+            }
+        }
+    }
+
+    /// Compiles `json_rules`, in WebKit's content blocker format, and applies them to every page
+    /// loaded afterwards, replacing rules set earlier. `callback` receives compile errors.
+    #[cfg(target_os = "macos")]
+    pub fn set_content_rules(&self, json_rules: &str, callback: impl FnOnce(Result<()>) + 'static) {
+        unsafe {
+            let Some(store_class) = Class::get("WKContentRuleListStore") else {
+                callback(Err(anyhow::anyhow!(
+                    "content rules require macOS 10.13 or later"
+                )));
+                return;
+            };
+            let store: id = msg_send![store_class, defaultStore];
+            // Keep the controller alive until compilation finishes, even if `self` is dropped.
+            let user_content_controller: id = msg_send![self.user_content_controller, retain];
+            let callback = Cell::new(Some(callback));
+            let completion_handler = ConcreteBlock::new(move |rule_list: id, error: id| {
+                if let Some(callback) = callback.take() {
+                    if rule_list == nil {
+                        callback(Err(if error == nil {
+                            anyhow::anyhow!("content rule compilation returned no rule list")
+                        } else {
+                            error_from_ns(error)
+                        }));
+                    } else {
+                        let _: () = msg_send![user_content_controller, removeAllContentRuleLists];
+                        let _: () =
+                            msg_send![user_content_controller, addContentRuleList: rule_list];
+                        callback(Ok(()));
+                    }
+                }
+                let _: () = msg_send![user_content_controller, release];
+            });
+            let completion_handler = completion_handler.copy();
+            let _: () = msg_send![
+                store,
+                compileContentRuleListForIdentifier: ns_string(CONTENT_RULE_LIST_IDENTIFIER)
+                encodedContentRuleList: ns_string(json_rules)
+                completionHandler: completion_handler
+            ];
+        }
+    }
+
+    /// Captures the visible page as PNG bytes.
+    #[cfg(target_os = "macos")]
+    pub fn take_snapshot(&self, callback: impl FnOnce(Result<Vec<u8>>) + 'static) {
+        unsafe { snapshot_webview(self.ns_webview, nil, callback) }
+    }
+
+    /// Captures the first element matching the CSS `selector` as PNG bytes, clipped to the
+    /// visible part of the page. Fails if nothing matches.
+    #[cfg(target_os = "macos")]
+    pub fn snapshot_element(
+        &self,
+        selector: &str,
+        callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+    ) {
+        let zoom = self.zoom();
+        let selector = selector.to_string();
+        // Keep the webview alive until the rect is known, even if `self` is dropped.
+        let webview: id = unsafe { msg_send![self.ns_webview, retain] };
+        self.evaluate_javascript(&element_rect_script(&selector), move |result| unsafe {
+            let rect = result.and_then(|result| {
+                parse_element_rect(&result)
+                    .ok_or_else(|| anyhow::anyhow!("no visible element matches {selector:?}"))
+            });
+            match rect {
+                Ok(rect) => {
+                    // Page zoom scales CSS pixels into the webview's points.
+                    let configuration: id = msg_send![class!(WKSnapshotConfiguration), new];
+                    let frame = NSRect {
+                        origin: NSPoint::new(rect.x * zoom, rect.y * zoom),
+                        size: NSSize::new(rect.width * zoom, rect.height * zoom),
+                    };
+                    let _: () = msg_send![configuration, setRect: frame];
+                    snapshot_webview(webview, configuration, callback);
+                    let _: () = msg_send![configuration, release];
+                }
+                Err(error) => callback(Err(error)),
+            }
+            let _: () = msg_send![webview, release];
+        });
+    }
+
+    /// Searches the page for `query`, selecting and scrolling to the next match after the current
+    /// one. `callback` receives whether a match was found.
+    #[cfg(target_os = "macos")]
+    pub fn find(&self, query: &str, forward: bool, callback: impl FnOnce(bool) + 'static) {
+        unsafe {
+            let responds: bool = msg_send![
+                self.ns_webview,
+                respondsToSelector: sel!(findString:withConfiguration:completionHandler:)
+            ];
+            if !responds {
+                // Native find requires macOS 11.
+                self.evaluate_javascript(&find_script(query, forward), move |result| {
+                    callback(result.is_ok_and(|result| find_script_matched(&result)))
+                });
+                return;
+            }
+
+            let configuration: id = msg_send![class!(WKFindConfiguration), new];
+            let configuration: id = msg_send![configuration, autorelease];
+            let backwards = if forward { NO } else { YES };
+            let _: () = msg_send![configuration, setBackwards: backwards];
+            let _: () = msg_send![configuration, setWraps: YES];
+
+            let callback = Cell::new(Some(callback));
+            let completion_handler = ConcreteBlock::new(move |result: id| {
+                if let Some(callback) = callback.take() {
+                    let found: bool = msg_send![result, matchFound];
+                    callback(found);
+                }
+            });
+            let completion_handler = completion_handler.copy();
+            let _: () = msg_send![
+                self.ns_webview,
+                findString: ns_string(query)
+                withConfiguration: configuration
+                completionHandler: completion_handler
+            ];
+        }
+    }
+
+    /// Removes the selection left by [`Self::find`].
+    #[cfg(target_os = "macos")]
+    pub fn clear_find(&self) {
+        self.evaluate_javascript_fire_and_forget(CLEAR_FIND_SCRIPT);
+    }
+
+    /// Renders the current page as a PDF and writes it to `path`.
+    #[cfg(target_os = "macos")]
+    pub fn print_to_pdf(&self, path: &Path, callback: impl FnOnce(Result<()>) + 'static) {
+        unsafe {
+            let responds: bool = msg_send![
+                self.ns_webview,
+                respondsToSelector: sel!(createPDFWithConfiguration:completionHandler:)
+            ];
+            if !responds {
+                // `createPDF` requires macOS 11; older systems save through the print system.
+                callback(self.print_to_pdf_with_print_operation(path));
+                return;
+            }
+
+            let path = path.to_path_buf();
+            let callback = Cell::new(Some(callback));
+            let completion_handler = ConcreteBlock::new(move |data: id, error: id| {
+                if let Some(callback) = callback.take() {
+                    if data == nil {
+                        callback(Err(if error == nil {
+                            anyhow::anyhow!("PDF creation returned no data")
+                        } else {
+                            error_from_ns(error)
+                        }));
+                    } else {
+                        callback(
+                            std::fs::write(&path, vec_from_ns_data(data)).map_err(|error| {
+                                anyhow::anyhow!(
+                                    "failed to write PDF to {}: {error}",
+                                    path.display()
+                                )
+                            }),
+                        );
+                    }
+                }
+            });
+            let completion_handler = completion_handler.copy();
+            let _: () = msg_send![
+                self.ns_webview,
+                createPDFWithConfiguration: nil
+                completionHandler: completion_handler
+            ];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn print_to_pdf_with_print_operation(&self, path: &Path) -> Result<()> {
+        unsafe {
+            let path_string = path.to_str().ok_or_else(|| {
+                anyhow::anyhow!("PDF path is not valid UTF-8: {}", path.display())
+            })?;
+            let url: id = msg_send![class!(NSURL), fileURLWithPath: ns_string(path_string)];
+
+            let shared_print_info: id = msg_send![class!(NSPrintInfo), sharedPrintInfo];
+            let print_info: id = msg_send![shared_print_info, copy];
+            let print_info: id = msg_send![print_info, autorelease];
+            let _: () = msg_send![print_info, setJobDisposition: ns_string("NSPrintSaveJob")];
+            let dictionary: id = msg_send![print_info, dictionary];
+            let _: () =
+                msg_send![dictionary, setObject: url forKey: ns_string("NSPrintJobSavingURL")];
+
+            let operation: id = msg_send![
+                class!(NSPrintOperation),
+                printOperationWithView: self.ns_webview
+                printInfo: print_info
+            ];
+            let _: () = msg_send![operation, setShowsPrintPanel: NO];
+            let _: () = msg_send![operation, setShowsProgressPanel: NO];
+            let succeeded: bool = msg_send![operation, runOperation];
+            if succeeded {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("failed to print PDF to {}", path.display()))
+            }
+        }
+    }
+
+    /// Where WebKit keeps this webview's data store: named after the app's bundle identifier, or
+    /// its process name when it isn't bundled. Named stores live under `WebsiteDataStore`, which
+    /// the default store's size leaves out. WebKit's data records don't report their size, so
+    /// the size is measured on disk.
+    #[cfg(target_os = "macos")]
+    fn data_store_directories(&self) -> Vec<PathBuf> {
+        let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else {
+            return Vec::new();
+        };
+        let name = unsafe {
+            let bundle: id = msg_send![class!(NSBundle), mainBundle];
+            let identifier: id = msg_send![bundle, bundleIdentifier];
+            if identifier == nil {
+                let process_info: id = msg_send![class!(NSProcessInfo), processInfo];
+                string_from_ns(msg_send![process_info, processName])
+            } else {
+                string_from_ns(identifier)
+            }
+        };
+        let root = home.join("Library/WebKit").join(&name);
+        let named_stores = root.join("WebsiteDataStore");
+        if let Some(identifier) = self.data_store.identifier() {
+            return vec![named_stores.join(identifier.hyphenated().to_string().to_uppercase())];
+        }
+        let mut directories: Vec<PathBuf> = std::fs::read_dir(&root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| *path != named_stores)
+            .collect();
+        directories.push(home.join("Library/Caches").join(&name).join("WebKit"));
+        directories
+    }
+
+    /// Removes all cookies, caches, and storage from this webview's data store, then invokes
+    /// `callback`.
+    #[cfg(target_os = "macos")]
+    pub fn clear_website_data(&self, callback: impl FnOnce() + 'static) {
+        unsafe {
+            let types: id = msg_send![class!(WKWebsiteDataStore), allWebsiteDataTypes];
+            self.remove_website_data(types, callback);
+        }
+    }
+
+    /// Removes only cookies from this webview's data store, then invokes `callback`.
+    #[cfg(target_os = "macos")]
+    pub fn clear_cookies_only(&self, callback: impl FnOnce() + 'static) {
+        unsafe {
+            let types: id =
+                msg_send![class!(NSSet), setWithObject: ns_string("WKWebsiteDataTypeCookies")];
+            self.remove_website_data(types, callback);
+        }
+    }
+
+    /// Adds or replaces a cookie in this webview's data store, then invokes `callback`. Unlike
+    /// `document.cookie`, this can set `HttpOnly` cookies.
+    #[cfg(target_os = "macos")]
+    pub fn set_cookie(&self, cookie: Cookie, callback: impl FnOnce() + 'static) {
+        unsafe {
+            let properties: id = msg_send![class!(NSMutableDictionary), dictionary];
+            let set = |key: &str, value: id| {
+                let _: () = msg_send![properties, setObject: value forKey: ns_string(key)];
+            };
+            // The keys are the values of the `NSHTTPCookie*` property constants.
+            set("Name", ns_string(&cookie.name));
+            set("Value", ns_string(&cookie.value));
+            set("Domain", ns_string(&cookie.domain));
+            set("Path", ns_string(&cookie.path));
+            if cookie.secure {
+                set("Secure", ns_string("TRUE"));
+            }
+            if cookie.http_only {
+                set("HttpOnly", ns_string("TRUE"));
+            }
+            if let Some(expires) = cookie.expires {
+                let date: id = msg_send![
+                    class!(NSDate),
+                    dateWithTimeIntervalSince1970: unix_seconds(expires)
+                ];
+                set("Expires", date);
+            }
+            let ns_cookie: id = msg_send![class!(NSHTTPCookie), cookieWithProperties: properties];
+            if ns_cookie == nil {
+                log::error!("Invalid cookie {} for {}", cookie.name, cookie.domain);
+                callback();
+                return;
+            }
+            let callback = Cell::new(Some(callback));
+            let completion_handler = ConcreteBlock::new(move || {
+                if let Some(callback) = callback.take() {
+                    callback();
+                }
+            });
+            let completion_handler = completion_handler.copy();
+            let _: () = msg_send![
+                self.http_cookie_store(),
+                setCookie: ns_cookie
+                completionHandler: completion_handler
+            ];
+        }
+    }
+
+    /// Invokes `callback` with the cookies in this webview's data store that would be sent with a
+    /// request to `url`, including `HttpOnly` cookies.
+    #[cfg(target_os = "macos")]
+    pub fn get_cookies(&self, url: &str, callback: impl FnOnce(Vec<Cookie>) + 'static) {
+        let url = match normalize_url(url).and_then(|url| Ok(url::Url::parse(&url)?)) {
+            Ok(url) => url,
+            Err(error) => {
+                log::error!("Failed to get cookies: {error}");
+                callback(Vec::new());
+                return;
+            }
+        };
+        unsafe {
+            let callback = Cell::new(Some(callback));
+            let completion_handler = ConcreteBlock::new(move |ns_cookies: id| {
+                let Some(callback) = callback.take() else {
+                    return;
+                };
+                let count: usize = msg_send![ns_cookies, count];
+                let cookies = (0..count)
+                    .map(|index| {
+                        let ns_cookie: id = msg_send![ns_cookies, objectAtIndex: index];
+                        cookie_from_ns(ns_cookie)
+                    })
+                    .filter(|cookie| cookie_matches_url(cookie, &url))
+                    .collect();
+                callback(cookies);
+            });
+            let completion_handler = completion_handler.copy();
+            let _: () = msg_send![
+                self.http_cookie_store(),
+                getAllCookies: completion_handler
+            ];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn http_cookie_store(&self) -> id {
+        unsafe {
+            let configuration: id = msg_send![self.ns_webview, configuration];
+            let data_store: id = msg_send![configuration, websiteDataStore];
+            msg_send![data_store, httpCookieStore]
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn remove_website_data(&self, types: id, callback: impl FnOnce() + 'static) {
+        unsafe {
+            let configuration: id = msg_send![self.ns_webview, configuration];
+            let data_store: id = msg_send![configuration, websiteDataStore];
+            let distant_past: id = msg_send![class!(NSDate), distantPast];
+            let callback = Cell::new(Some(callback));
+            // WebKit copies the block, keeping it alive until the completion handler runs.
+            let completion_handler = ConcreteBlock::new(move || {
+                if let Some(callback) = callback.take() {
+                    callback();
+                }
+            });
+            let completion_handler = completion_handler.copy();
+            let _: () = msg_send![
+                data_store,
+                removeDataOfTypes: types
+                modifiedSince: distant_past
+                completionHandler: completion_handler
+            ];
+        }
+    }
+
+    /// Requires macOS 12.
+    #[cfg(target_os = "macos")]
+    fn interaction_state(&self) -> Option<Vec<u8>> {
+        unsafe {
+            let responds: bool =
+                msg_send![self.ns_webview, respondsToSelector: sel!(interactionState)];
+            if !responds {
+                return None;
+            }
+            let interaction_state: id = msg_send![self.ns_webview, interactionState];
+            let is_data: bool = msg_send![interaction_state, isKindOfClass: class!(NSData)];
+            is_data.then(|| vec_from_ns_data(interaction_state))
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn restore_interaction_state(&self, interaction_state: &[u8]) -> bool {
+        unsafe {
+            let responds: bool =
+                msg_send![self.ns_webview, respondsToSelector: sel!(setInteractionState:)];
+            if !responds {
+                return false;
+            }
+            let data: id = msg_send![
+                class!(NSData),
+                dataWithBytes: interaction_state.as_ptr() as *const c_void
+                length: interaction_state.len()
+            ];
+            let _: () = msg_send![self.ns_webview, setInteractionState: data];
+            true
+        }
+    }
+
+    /// Whether pages may run scripts, e.g. to safely preview untrusted HTML. Disabling it doesn't
+    /// stop [`Self::evaluate_javascript`] on macOS 11 and later or on Windows. Applies to pages
+    /// loaded afterwards.
+    #[cfg(target_os = "macos")]
+    pub fn set_javascript_enabled(&self, enabled: bool) {
+        unsafe {
+            let configuration: id = msg_send![self.ns_webview, configuration];
+            configure_javascript(configuration, enabled);
+        }
+    }
+
+    /// Whether pages load images. Not supported on macOS or Windows, where WebKit and WebView2
+    /// have no such setting.
+    #[cfg(target_os = "macos")]
+    pub fn set_load_images(&self, load: bool) {
+        if !load {
+            log::warn!("Disabling image loading is not supported on macOS");
+        }
+    }
+
+    /// Whether to warn before showing sites suspected of phishing or malware, using Safe
+    /// Browsing on macOS and SmartScreen on Windows. WebKitGTK has no such warnings.
+    #[cfg(target_os = "macos")]
+    pub fn set_fraudulent_website_warning_enabled(&self, enabled: bool) {
+        unsafe {
+            let configuration: id = msg_send![self.ns_webview, configuration];
+            configure_fraudulent_website_warning(configuration, enabled);
+        }
+    }
+
+    /// Lets page elements such as video players use the Fullscreen API, expanding the window to
+    /// fill the screen until they exit. Requires macOS 13, which reports the webview's fullscreen
+    /// state; disabled by default.
+    #[cfg(target_os = "macos")]
+    pub fn set_allow_element_fullscreen(&self, allow: bool) {
+        unsafe {
+            let configuration: id = msg_send![self.ns_webview, configuration];
+            let preferences: id = msg_send![configuration, preferences];
+            let reports_fullscreen_state: bool =
+                msg_send![self.ns_webview, respondsToSelector: sel!(fullscreenState)];
+            let responds: bool = msg_send![
+                preferences,
+                respondsToSelector: sel!(setElementFullscreenEnabled:)
+            ];
+            if responds && reports_fullscreen_state {
+                let enabled = if allow { YES } else { NO };
+                let _: () = msg_send![preferences, setElementFullscreenEnabled: enabled];
+            } else {
+                log::warn!(
+                    "Element fullscreen requires macOS 13 (running macOS {})",
+                    macos_version()
+                );
+            }
+        }
+    }
+
+    /// Opens HTML, PDF, SVG, and text files dropped onto the webview. Other drops, and all drops
+    /// while disabled, keep WebKit's default handling. Disabled by default.
+    #[cfg(target_os = "macos")]
+    pub fn set_accepts_file_drops(&self, accept: bool) {
+        unsafe {
+            (*self.ns_webview)
+                .set_ivar::<BOOL>(ACCEPTS_FILE_DROPS_IVAR, if accept { YES } else { NO });
+            if accept {
+                let types: id = msg_send![
+                    class!(NSArray),
+                    arrayWithObject: ns_string("public.file-url") // NSPasteboardTypeFileURL
+                ];
+                let _: () = msg_send![self.ns_webview, registerForDraggedTypes: types];
+            }
+        }
+    }
+
+    /// Scales the page content. `factor` is clamped to 0.25..=5.0, where 1.0 is the default.
+    #[cfg(target_os = "macos")]
+    pub fn set_zoom(&self, factor: f64) {
+        let factor = clamp_zoom(factor);
+        self.zoom.set(factor);
+        unsafe {
+            let responds: bool = msg_send![self.ns_webview, respondsToSelector: sel!(setPageZoom:)];
+            if responds {
+                let _: () = msg_send![self.ns_webview, setPageZoom: factor];
+                return;
+            }
+        }
+        // `pageZoom` requires macOS 11, so older systems fall back to CSS zoom, which only affects
+        // the current document.
+        self.evaluate_javascript_fire_and_forget(&format!(
+            "document.body && (document.body.style.zoom = '{factor}');"
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn zoom(&self) -> f64 {
+        unsafe {
+            let responds: bool = msg_send![self.ns_webview, respondsToSelector: sel!(pageZoom)];
+            if responds {
+                let factor: f64 = msg_send![self.ns_webview, pageZoom];
+                return factor;
+            }
+        }
+        self.zoom.get()
+    }
+
+    /// Overrides the user agent sent with requests. An empty string restores WebKit's default.
+    #[cfg(target_os = "macos")]
+    pub fn set_user_agent(&self, user_agent: &str) {
+        unsafe {
+            let user_agent = if user_agent.is_empty() {
+                nil
+            } else {
+                ns_string(user_agent)
+            };
+            let _: () = msg_send![self.ns_webview, setCustomUserAgent: user_agent];
+        }
+    }
+
+    /// Injects `source` into the main frame of every page loaded from now on, either before any
+    /// page script runs or once the document has finished parsing. Before the first load ends,
+    /// the script is queued and also runs once in the page that loaded; see
+    /// [`Self::has_loaded`].
+    #[cfg(target_os = "macos")]
+    pub fn add_user_script(&self, source: &str, at_document_start: bool) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::UserScript {
+                source: source.to_string(),
+                at_document_start,
+            });
+            return;
+        }
+        unsafe { install_user_script(self.user_content_controller, source, at_document_start) }
+    }
+
+    /// Registers a handler for messages posted by the page through
+    /// `window.webkit.messageHandlers.<name>.postMessage(body)`. Registering the same name again
+    /// replaces the previous handler.
+    #[cfg(target_os = "macos")]
+    pub fn register_message_handler(&mut self, name: &str, handler: impl FnMut(String) + 'static) {
+        let previous = self
+            .state
+            .borrow_mut()
+            .message_handlers
+            .insert(name.to_string(), Box::new(handler));
+        if previous.is_none() {
+            unsafe {
+                let _: () = msg_send![
+                    self.user_content_controller,
+                    addScriptMessageHandler: self.delegate
+                    name: ns_string(name)
+                ];
+            }
+        }
+    }
+
+    /// Sets the opacity of the whole window, from 0.0 (invisible) to 1.0 (opaque).
+    #[cfg(target_os = "macos")]
+    pub fn set_opacity(&self, alpha: f64) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        self.opacity.set(alpha);
+        unsafe {
+            let _: () = msg_send![self.floating_window, setAlphaValue: alpha];
+        }
+    }
+
+    /// Lets content behind the window show through wherever the page doesn't paint a background.
+    #[cfg(target_os = "macos")]
+    pub fn set_background_transparent(&self, transparent: bool) {
+        unsafe {
+            let background: id = if transparent {
+                msg_send![class!(NSColor), clearColor]
+            } else {
+                msg_send![class!(NSColor), windowBackgroundColor]
+            };
+            let opaque = if transparent { NO } else { YES };
+            let _: () = msg_send![self.floating_window, setOpaque: opaque];
+            let _: () = msg_send![self.floating_window, setBackgroundColor: background];
+
+            // WKWebView has no public API to stop drawing its own background.
+            let draws_background: id = msg_send![class!(NSNumber), numberWithBool: opaque];
+            let _: () = msg_send![
+                self.ns_webview,
+                setValue: draws_background
+                forKey: ns_string("drawsBackground")
+            ];
+            // Matching the area shown while loading or overscrolling avoids a white flash.
+            let responds: bool = msg_send![
+                self.ns_webview,
+                respondsToSelector: sel!(setUnderPageBackgroundColor:)
+            ];
+            if responds {
+                let _: () = msg_send![self.ns_webview, setUnderPageBackgroundColor: background];
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn set_window_level(&self, level: WindowLevel) {
+        unsafe {
+            let _: () = msg_send![self.floating_window, setLevel: level.ns_window_level()];
+        }
+    }
+
+    /// Sets the window title, which labels the webview in the window switcher even while the
+    /// title itself is hidden from the titlebar.
+    #[cfg(target_os = "macos")]
+    pub fn set_title(&mut self, title: &str) {
+        self.window_title = title.to_string();
+        unsafe {
+            let _: () = msg_send![self.floating_window, setTitle: ns_string(title)];
+        }
+    }
+
+    /// Windows have a shadow by default.
+    #[cfg(target_os = "macos")]
+    pub fn set_has_shadow(&self, shadow: bool) {
+        unsafe {
+            let _: () =
+                msg_send![self.floating_window, setHasShadow: if shadow { YES } else { NO }];
+        }
+    }
+
+    /// Overrides the appearance of the window and the page inside it. Defaults to
+    /// [`Appearance::System`].
+    #[cfg(target_os = "macos")]
+    pub fn set_appearance(&self, appearance: Appearance) {
+        unsafe {
+            let ns_appearance: id = match appearance.ns_appearance_name() {
+                Some(name) => msg_send![class!(NSAppearance), appearanceNamed: ns_string(name)],
+                None => nil,
+            };
+            let _: () = msg_send![self.floating_window, setAppearance: ns_appearance];
+        }
+    }
+
+    /// Whether the user can resize the window. A non-resizable window keeps its current content
+    /// size, though [`Self::set_bounds`] can still change it.
+    #[cfg(target_os = "macos")]
+    pub fn set_resizable(&self, resizable: bool) {
+        unsafe {
+            let resizable_mask = NSWindowStyleMask::NSResizableWindowMask.bits();
+            let style_mask: u64 = msg_send![self.floating_window, styleMask];
+            let style_mask = if resizable {
+                style_mask | resizable_mask
+            } else {
+                style_mask & !resizable_mask
+            };
+            let _: () = msg_send![self.floating_window, setStyleMask: style_mask];
+            self.apply_content_size_limits();
+        }
+    }
+
+    /// Keeps the user from resizing the window's content below `size`.
+    #[cfg(target_os = "macos")]
+    pub fn set_min_size(&self, size: Size<Pixels>) {
+        self.min_size.set(Some(size));
+        unsafe { self.apply_content_size_limits() }
+    }
+
+    /// Keeps the user from resizing the window's content above `size`.
+    #[cfg(target_os = "macos")]
+    pub fn set_max_size(&self, size: Size<Pixels>) {
+        self.max_size.set(Some(size));
+        unsafe { self.apply_content_size_limits() }
+    }
+
+    /// Keeps the window's content at `ratio` (width, height) while the user resizes it, e.g. for
+    /// video. `None` lets it resize freely again.
+    #[cfg(target_os = "macos")]
+    pub fn set_aspect_ratio(&self, ratio: Option<(f64, f64)>) {
+        unsafe {
+            match ratio {
+                Some((width, height)) => {
+                    let _: () = msg_send![
+                        self.floating_window,
+                        setContentAspectRatio: NSSize::new(width, height)
+                    ];
+                }
+                // Setting resize increments clears the aspect ratio.
+                None => {
+                    let _: () = msg_send![
+                        self.floating_window,
+                        setContentResizeIncrements: NSSize::new(1.0, 1.0)
+                    ];
+                }
+            }
+        }
+    }
+
+    /// Lays the page out at `size` instead of the window's content size, e.g. to preview a
+    /// mobile layout in a desktop window. The webview stays pinned to the top-left corner of the
+    /// [content insets](Self::set_content_insets): it's clipped when larger than the window, and
+    /// the window background shows around it when smaller. Only the CSS viewport changes; pages
+    /// still see a desktop user agent and device pixel ratio, and macOS WebKit ignores
+    /// `<meta name="viewport">`.
+    #[cfg(target_os = "macos")]
+    pub fn set_viewport_size(&self, size: Size<Pixels>) {
+        self.viewport_size.set(Some(size));
+        unsafe { self.layout_webview() }
+    }
+
+    /// Makes the webview fill the window again after [`Self::set_viewport_size`].
+    #[cfg(target_os = "macos")]
+    pub fn clear_viewport_size(&self) {
+        self.viewport_size.set(None);
+        unsafe { self.layout_webview() }
+    }
+
+    /// Keeps the webview `insets` away from the edges of the window's content area, e.g. to leave
+    /// room for a toolbar drawn above the page. The margins stay fixed as the window resizes.
+    /// Defaults to no insets.
+    #[cfg(target_os = "macos")]
+    pub fn set_content_insets(&self, insets: Edges<Pixels>) {
+        self.content_insets.set(insets);
+        unsafe { self.layout_webview() }
+    }
+
+    /// Reserves a `height` strip across the top of the window for toolbar controls, moving the
+    /// webview below it. Add the controls to [`Self::native_chrome_view_handle`]. The content
+    /// insets apply to the area below the strip. Defaults to no strip.
+    #[cfg(target_os = "macos")]
+    pub fn set_chrome_height(&self, height: Pixels) {
+        self.chrome_height.set(height.max(px(0.0)));
+        unsafe { self.layout_webview() }
+    }
+
+    /// Positions the chrome strip, then the webview within the content insets below it, filling
+    /// them unless a viewport size is set, and picks the autoresizing mask that keeps it there as
+    /// the window resizes.
+    #[cfg(target_os = "macos")]
+    unsafe fn layout_webview(&self) {
+        let insets = self.content_insets.get();
+        let chrome_height: f64 = self.chrome_height.get().into();
+        let (top, right, bottom, left): (f64, f64, f64, f64) = (
+            chrome_height + f64::from(insets.top),
+            insets.right.into(),
+            insets.bottom.into(),
+            insets.left.into(),
+        );
+        unsafe {
+            let content_view: id = msg_send![self.floating_window, contentView];
+            let content_bounds: NSRect = msg_send![content_view, bounds];
+            let chrome_frame = NSRect {
+                origin: NSPoint::new(0.0, content_bounds.size.height - chrome_height),
+                size: NSSize::new(content_bounds.size.width, chrome_height),
+            };
+            let _: () = msg_send![self.chrome_view, setFrame: chrome_frame];
+            let (size, autoresizing_mask) = match self.viewport_size.get() {
+                // NSViewMinYMargin
+                Some(size) => (NSSize::new(size.width.into(), size.height.into()), 8u64),
+                // NSViewWidthSizable | NSViewHeightSizable
+                None => (
+                    NSSize::new(
+                        (content_bounds.size.width - left - right).max(0.0),
+                        (content_bounds.size.height - top - bottom).max(0.0),
+                    ),
+                    2 | 16,
+                ),
+            };
+            // The content view isn't flipped, so the frame's origin is its bottom-left corner.
+            let frame = NSRect {
+                origin: NSPoint::new(left, content_bounds.size.height - top - size.height),
+                size,
+            };
+            let _: () = msg_send![self.ns_webview, setAutoresizingMask: autoresizing_mask];
+            let _: () = msg_send![self.ns_webview, setFrame: frame];
+        }
+    }
+
+    /// Applies the configured size limits, or pins the current content size if the window isn't
+    /// resizable.
+    #[cfg(target_os = "macos")]
+    unsafe fn apply_content_size_limits(&self) {
+        let ns_size = |size: Size<Pixels>| NSSize::new(size.width.into(), size.height.into());
+        unsafe {
+            let style_mask: u64 = msg_send![self.floating_window, styleMask];
+            let resizable = style_mask & NSWindowStyleMask::NSResizableWindowMask.bits() != 0;
+            let (min_size, max_size) = if resizable {
+                (
+                    self.min_size.get().map_or(NSSize::new(0.0, 0.0), ns_size),
+                    self.max_size
+                        .get()
+                        .map_or(NSSize::new(f64::MAX, f64::MAX), ns_size),
+                )
+            } else {
+                let content_view: id = msg_send![self.floating_window, contentView];
+                let content_frame: NSRect = msg_send![content_view, frame];
+                (content_frame.size, content_frame.size)
+            };
+            let _: () = msg_send![self.floating_window, setContentMinSize: min_size];
+            let _: () = msg_send![self.floating_window, setContentMaxSize: max_size];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn set_title_visible(&self, visible: bool) {
+        // NSWindowTitleVisible = 0, NSWindowTitleHidden = 1
+        let visibility: i64 = if visible { 0 } else { 1 };
+        unsafe {
+            let _: () = msg_send![self.floating_window, setTitleVisibility: visibility];
+        }
+    }
+
+    /// Hiding only orders the window out: the page stays loaded, keeping its scroll position and
+    /// session, so a hidden manager can be shown again instead of recreated.
+    #[cfg(target_os = "macos")]
+    pub fn set_hidden(&self, hidden: bool) {
+        self.state.borrow_mut().fade_generation += 1;
+        unsafe {
+            // Cancels any fade in progress.
+            let _: () = msg_send![self.floating_window, setAlphaValue: self.opacity.get()];
+            if hidden {
+                // A child window is ordered back in with its parent, so detach it while hidden.
+                self.detach_from_parent();
+                let _: () = msg_send![self.floating_window, orderOut:nil];
+            } else {
+                let _: () = msg_send![self.floating_window, makeKeyAndOrderFront:nil];
+                self.attach_to_parent();
+                self.apply_pending_bounds();
+            }
+        }
+    }
+
+    /// Like [`Self::set_hidden`], but fades the window out or in over `duration`. A zero
+    /// duration behaves exactly like `set_hidden`.
+    #[cfg(target_os = "macos")]
+    pub fn set_hidden_animated(&self, hidden: bool, duration: Duration) {
+        if duration.is_zero() {
+            self.set_hidden(hidden);
+            return;
+        }
+        unsafe {
+            let opacity = self.opacity.get();
+            if hidden {
+                let generation = {
+                    let mut state = self.state.borrow_mut();
+                    state.fade_generation += 1;
+                    state.fade_generation
+                };
+                let state = self.state.clone();
+                let window: id = msg_send![self.floating_window, retain];
+                let completion_handler = ConcreteBlock::new(move || {
+                    if state.borrow().fade_generation == generation {
+                        let parent_window: id = msg_send![window, parentWindow];
+                        if parent_window != nil {
+                            let _: () = msg_send![parent_window, removeChildWindow: window];
+                        }
+                        let _: () = msg_send![window, orderOut: nil];
+                        let _: () = msg_send![window, setAlphaValue: opacity];
+                    }
+                    let _: () = msg_send![window, release];
+                });
+                let completion_handler = completion_handler.copy();
+                self.animate_alpha(0.0, duration, Some(&completion_handler));
+            } else {
+                let visible = self.is_visible();
+                self.set_hidden(false);
+                if !visible {
+                    let _: () = msg_send![self.floating_window, setAlphaValue: 0.0f64];
+                }
+                self.animate_alpha(opacity, duration, None);
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn animate_alpha(
+        &self,
+        alpha: f64,
+        duration: Duration,
+        completion_handler: Option<&Block<(), ()>>,
+    ) {
+        unsafe {
+            let _: () = msg_send![class!(NSAnimationContext), beginGrouping];
+            let context: id = msg_send![class!(NSAnimationContext), currentContext];
+            let _: () = msg_send![context, setDuration: duration.as_secs_f64()];
+            if let Some(completion_handler) = completion_handler {
+                let _: () = msg_send![context, setCompletionHandler: completion_handler];
+            }
+            let animator: id = msg_send![self.floating_window, animator];
+            let _: () = msg_send![animator, setAlphaValue: alpha];
+            let _: () = msg_send![class!(NSAnimationContext), endGrouping];
+        }
+    }
+
+    /// Shows the window above others without taking keyboard focus from the app.
+    #[cfg(target_os = "macos")]
+    pub fn show_without_activating(&self) {
+        unsafe {
+            let _: () = msg_send![self.floating_window, orderFront:nil];
+            self.attach_to_parent();
+        }
+        self.apply_pending_bounds();
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn attach_to_parent(&self) {
+        if self.parent_window != nil {
+            unsafe {
+                let _: () = msg_send![
+                    self.parent_window,
+                    addChildWindow: self.floating_window
+                    ordered: 1i64 // NSWindowAbove
+                ];
+            }
+        }
+    }
+
+    /// Asks the window for its parent rather than using `parent_window`, since a hidden window
+    /// isn't attached.
+    #[cfg(target_os = "macos")]
+    unsafe fn detach_from_parent(&self) {
+        unsafe {
+            let parent_window: id = msg_send![self.floating_window, parentWindow];
+            if parent_window != nil {
+                let _: () = msg_send![parent_window, removeChildWindow: self.floating_window];
+            }
+        }
+    }
+
+    /// Makes the window key and gives the webview keyboard focus.
+    #[cfg(target_os = "macos")]
+    pub fn focus(&self) {
+        unsafe {
+            let _: () = msg_send![self.floating_window, makeKeyWindow];
+            let _: bool = msg_send![self.floating_window, makeFirstResponder: self.ns_webview];
+        }
+    }
+
+    /// Whether the window is key and its first responder is the webview or one of its subviews.
+    #[cfg(target_os = "macos")]
+    pub fn is_focused(&self) -> bool {
+        unsafe {
+            let is_key: bool = msg_send![self.floating_window, isKeyWindow];
+            if !is_key {
+                return false;
+            }
+            let responder: id = msg_send![self.floating_window, firstResponder];
+            if responder == nil {
+                return false;
+            }
+            // The window itself is first responder after `blur`, and only views respond to
+            // `isDescendantOf:`.
+            let is_view: bool = msg_send![responder, isKindOfClass: class!(NSView)];
+            if !is_view {
+                return false;
+            }
+            msg_send![responder, isDescendantOf: self.ns_webview]
+        }
+    }
+
+    /// Removes keyboard focus from the webview and makes the app's main window key again.
+    #[cfg(target_os = "macos")]
+    pub fn blur(&self) {
+        unsafe {
+            let _: bool = msg_send![self.floating_window, makeFirstResponder: nil];
+            let app: id = msg_send![class!(NSApplication), sharedApplication];
+            let main_window: id = msg_send![app, mainWindow];
+            if main_window != nil && main_window != self.floating_window {
+                let _: () = msg_send![main_window, makeKeyWindow];
+            }
+        }
+    }
+
+    /// Whether a navigation is in progress.
+    #[cfg(target_os = "macos")]
+    pub fn is_loading(&self) -> bool {
+        unsafe { msg_send![self.ns_webview, isLoading] }
+    }
+
+    /// WebKit has no API to suspend a page, so this suspends media playback, on macOS 12 and
+    /// later, and tells the page it is hidden so that well-behaved pages stop their own timers and
+    /// animations. How much work that saves is up to the page; WebKit additionally throttles
+    /// timers in windows that are ordered out.
+    #[cfg(target_os = "macos")]
+    pub fn set_suspended(&self, suspended: bool) {
+        unsafe {
+            let responds: bool = msg_send![
+                self.ns_webview,
+                respondsToSelector: sel!(setAllMediaPlaybackSuspended:completionHandler:)
+            ];
+            if responds {
+                let suspended = if suspended { YES } else { NO };
+                let _: () = msg_send![
+                    self.ns_webview,
+                    setAllMediaPlaybackSuspended: suspended
+                    completionHandler: nil
+                ];
+            }
+        }
+        self.evaluate_javascript_fire_and_forget(&page_visibility_script(suspended));
+    }
+
+    /// Mutes the page's audio, including media started later and on pages loaded afterwards.
+    /// WebKit has no public mute API, so this mutes the page's media elements instead.
+    #[cfg(target_os = "macos")]
+    pub fn set_muted(&self, muted: bool) {
+        self.state.borrow_mut().muted = muted;
+        self.evaluate_javascript_fire_and_forget(&mute_script(muted));
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn is_muted(&self) -> bool {
+        self.state.borrow().muted
+    }
+
+    /// Minimizes the window to the Dock.
+    #[cfg(target_os = "macos")]
+    pub fn minimize(&self) {
+        unsafe {
+            let _: () = msg_send![self.floating_window, miniaturize: nil];
+        }
+    }
+
+    /// Restores a minimized window and brings it to the front, showing it if it was hidden.
+    #[cfg(target_os = "macos")]
+    pub fn restore(&self) {
+        unsafe {
+            let _: () = msg_send![self.floating_window, deminiaturize: nil];
+            let _: () = msg_send![self.floating_window, makeKeyAndOrderFront: nil];
+        }
+        self.apply_pending_bounds();
+    }
+
+    /// Toggles between the window's standard frame and the frame the user last chose, like the
+    /// titlebar's zoom button.
+    #[cfg(target_os = "macos")]
+    pub fn toggle_zoom(&self) {
+        unsafe {
+            let _: () = msg_send![self.floating_window, zoom: nil];
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn is_visible(&self) -> bool {
+        unsafe {
+            let visible: bool = msg_send![self.floating_window, isVisible];
+            visible
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for WebViewManager {
+    fn drop(&mut self) {
+        // Everything that can call back into the delegate is detached before anything is
+        // released, and the window is ordered out rather than closed, so no close animation or
+        // delegate callback runs during teardown. The delegate is released last.
+        unsafe {
+            webview_info!("Cleaning up floating webview window");
+            let _: () = msg_send![self.ns_webview, setNavigationDelegate: nil];
+            let _: () = msg_send![self.ns_webview, setUIDelegate: nil];
+            let _: () = msg_send![self.floating_window, setDelegate: nil];
+            let downloads: Vec<usize> = self.state.borrow().downloads.keys().copied().collect();
+            for download in downloads {
+                let download = download as id;
+                let _: () = msg_send![download, setDelegate: nil];
+                let _: () = msg_send![download, cancel: nil];
+            }
+            for key_path in OBSERVED_KEY_PATHS {
+                let _: () = msg_send![
+                    self.ns_webview,
+                    removeObserver: self.delegate
+                    forKeyPath: ns_string(key_path)
+                ];
+            }
+            // The content controller retains the delegate for each message handler.
+            for name in self.state.borrow().message_handlers.keys() {
+                let _: () = msg_send![
+                    self.user_content_controller,
+                    removeScriptMessageHandlerForName: ns_string(name)
+                ];
+            }
+            let _: () = msg_send![self.ns_webview, stopLoading];
+            let parent_view: id = *(*self.ns_webview).get_ivar(PARENT_VIEW_IVAR);
+            if parent_view != nil {
+                (*self.ns_webview).set_ivar::<id>(PARENT_VIEW_IVAR, nil);
+                let _: () = msg_send![parent_view, release];
+            }
+
+            self.detach_from_parent();
+            let _: () = msg_send![self.floating_window, orderOut: nil];
+            let _: () = msg_send![self.floating_window, release];
+            if self.parent_window != nil {
+                let _: () = msg_send![self.parent_window, release];
+            }
+            let _: () = msg_send![self.user_content_controller, release];
+            let _: () = msg_send![self.delegate, release];
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+impl WebViewManager {
+    pub fn is_supported() -> bool {
+        false
+    }
+
+    fn create(_: *mut std::ffi::c_void, options: &WebViewManagerBuilder, cx: &App) -> Result<Self> {
+        let url = normalize_url(&options.url)?;
+        Ok(Self {
+            history: NavigationHistory::new(&url),
+            context_menu_listener_installed: std::cell::Cell::new(false),
+            current_url: url,
+            window_title: String::new(),
+            data_store: options.data_store.clone(),
+            zoom: std::cell::Cell::new(1.0),
+            foreground_executor: cx.foreground_executor().clone(),
+            background_executor: cx.background_executor().clone(),
+            _slot: None,
+            _not_send: PhantomData,
+        })
+    }
+
+    pub fn navigate(&mut self, url: &str) -> Result<()> {
+        self.start_navigation(url, |_, _| Ok(()))?;
+        Ok(())
+    }
+
+    pub async fn navigate_async(&mut self, url: &str) -> Result<()> {
+        self.current_url = normalize_url(url)?;
+        Ok(())
+    }
+
+    pub fn navigate_with_headers(&mut self, url: &str, _headers: &[(&str, &str)]) -> Result<()> {
+        self.navigate(url)
+    }
+
+    pub fn load_post(&mut self, url: &str, _body: &[u8], _content_type: &str) -> Result<()> {
+        self.start_load(url, |_, _| Ok(()))?;
+        Ok(())
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let (_, url) = file_url(path)?;
+        self.start_navigation(&url, |_, _| Ok(()))?;
+        Ok(())
+    }
+
+    pub fn load_html(&mut self, _html: &str, base_url: Option<&str>) {
+        self.current_url = base_url.unwrap_or("about:blank").to_string();
+    }
+
+    pub fn reload(&self) {}
+
+    pub fn reload_ignoring_cache(&self) {}
+
+    pub fn stop_loading(&self) {}
+
+    pub fn go_back(&self) {}
+
+    pub fn go_forward(&self) {}
+
+    pub fn can_go_back(&self) -> bool {
+        false
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        false
+    }
+
+    pub fn set_bounds(&self, _bounds: Bounds<Pixels>) {}
+
+    pub fn get_bounds(&self) -> Bounds<Pixels> {
+        Bounds::default()
+    }
+
+    fn work_area(&self) -> Option<Bounds<Pixels>> {
+        None
+    }
+
+    pub fn on_bounds_changed(&mut self, _handler: impl FnMut(Bounds<Pixels>) + 'static) {}
+
+    pub fn evaluate_javascript(
+        &self,
+        _script: &str,
+        callback: impl FnOnce(Result<String>) + 'static,
+    ) {
+        callback(Err(anyhow::anyhow!(
+            "JavaScript evaluation is not supported on this platform"
+        )));
+    }
+
+    pub fn evaluate_javascript_fire_and_forget(&self, _script: &str) {}
+
+    pub fn on_did_finish_navigation(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    pub fn on_navigation_failed(&mut self, _handler: impl FnMut(String, i64) + 'static) {}
+
+    pub fn on_crash(&mut self, _handler: impl FnMut() + 'static) {}
+
+    pub fn on_progress(&mut self, _handler: impl FnMut(f64) + 'static) {}
+
+    pub fn estimated_progress(&self) -> f64 {
+        0.0
+    }
+
+    pub fn on_title_changed(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    pub fn title(&mut self) -> &str {
+        ""
+    }
+
+    pub fn set_navigation_policy(
+        &mut self,
+        _policy: impl FnMut(&str) -> NavigationDecision + 'static,
+    ) {
+    }
+
+    pub fn on_new_window(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    pub fn on_js_alert(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    pub fn on_permission_request(
+        &mut self,
+        _handler: impl FnMut(PermissionKind, String) -> bool + 'static,
+    ) {
+    }
+
+    pub fn on_js_confirm(&mut self, _handler: impl FnMut(String) -> bool + 'static) {}
+
+    pub fn on_auth_challenge(
+        &mut self,
+        _handler: impl FnMut(AuthChallenge) -> AuthResponse + 'static,
+    ) {
+    }
+
+    pub fn set_basic_auth(
+        &mut self,
+        _host: &str,
+        _port: Option<u16>,
+        _username: &str,
+        _password: &str,
+    ) {
+    }
+
+    pub fn set_key_passthrough(&self, _keys: &[&str]) {}
+
+    pub fn set_close_on_cmd_w(&self, _close: bool) {}
+
+    pub fn on_context_menu(
+        &mut self,
+        _handler: impl FnMut(ContextMenuContext) -> Vec<MenuItem> + 'static,
+    ) {
+    }
+
+    pub fn clear_basic_auth(&mut self) {}
+
+    pub fn on_js_prompt(
+        &mut self,
+        _handler: impl FnMut(String, String) -> Option<String> + 'static,
+    ) {
+    }
+
+    pub fn on_close(&mut self, _handler: impl FnOnce() + 'static) {}
+
+    pub fn on_download(&mut self, _handler: impl FnMut(String) -> Option<PathBuf> + 'static) {}
+
+    pub fn on_download_finished(&mut self, _handler: impl FnMut(Result<PathBuf>) + 'static) {}
+
+    pub fn set_inspectable(&self, _inspectable: bool) {}
+
+    pub fn set_content_rules(
+        &self,
+        _json_rules: &str,
+        callback: impl FnOnce(Result<()>) + 'static,
+    ) {
+        callback(Err(anyhow::anyhow!(
+            "Content rules are not supported on this platform"
+        )));
+    }
+
+    pub fn take_snapshot(&self, callback: impl FnOnce(Result<Vec<u8>>) + 'static) {
+        callback(Err(anyhow::anyhow!(
+            "Snapshots are not supported on this platform"
+        )));
+    }
+
+    pub fn snapshot_element(
+        &self,
+        _selector: &str,
+        callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+    ) {
+        self.take_snapshot(callback);
+    }
+
+    pub fn find(&self, _query: &str, _forward: bool, callback: impl FnOnce(bool) + 'static) {
+        callback(false);
+    }
+
+    pub fn clear_find(&self) {}
+
+    pub fn print_to_pdf(&self, _path: &Path, callback: impl FnOnce(Result<()>) + 'static) {
+        callback(Err(anyhow::anyhow!(
+            "Printing to PDF is not supported on this platform"
+        )));
+    }
+
+    fn data_store_directories(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    pub fn clear_website_data(&self, callback: impl FnOnce() + 'static) {
+        callback();
+    }
+
+    pub fn clear_cookies_only(&self, callback: impl FnOnce() + 'static) {
+        callback();
+    }
+
+    pub fn set_cookie(&self, _cookie: Cookie, callback: impl FnOnce() + 'static) {
+        callback();
+    }
+
+    pub fn get_cookies(&self, _url: &str, callback: impl FnOnce(Vec<Cookie>) + 'static) {
+        callback(Vec::new());
+    }
+
+    pub fn set_accepts_file_drops(&self, _accept: bool) {}
+
+    pub fn set_allow_element_fullscreen(&self, _allow: bool) {}
+
+    pub fn set_javascript_enabled(&self, _enabled: bool) {}
+
+    pub fn set_load_images(&self, _load: bool) {}
+
+    pub fn set_fraudulent_website_warning_enabled(&self, _enabled: bool) {}
+
+    pub fn set_zoom(&self, factor: f64) {
+        self.zoom.set(clamp_zoom(factor));
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom.get()
+    }
+
+    pub fn set_user_agent(&self, _user_agent: &str) {}
+
+    pub fn add_user_script(&self, _source: &str, _at_document_start: bool) {}
+
+    pub fn register_message_handler(
+        &mut self,
+        _name: &str,
+        _handler: impl FnMut(String) + 'static,
+    ) {
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.window_title = title.to_string();
+    }
+
+    pub fn set_title_visible(&self, _visible: bool) {}
+
+    pub fn set_has_shadow(&self, _shadow: bool) {}
+
+    pub fn set_appearance(&self, _appearance: Appearance) {}
+
+    pub fn set_resizable(&self, _resizable: bool) {}
+
+    pub fn set_min_size(&self, _size: Size<Pixels>) {}
+
+    pub fn set_max_size(&self, _size: Size<Pixels>) {}
+
+    pub fn set_aspect_ratio(&self, _ratio: Option<(f64, f64)>) {}
+
+    pub fn set_viewport_size(&self, _size: Size<Pixels>) {}
+
+    pub fn set_content_insets(&self, _insets: Edges<Pixels>) {}
+
+    pub fn set_chrome_height(&self, _height: Pixels) {}
+
+    pub fn clear_viewport_size(&self) {}
+
+    pub fn set_window_level(&self, _level: WindowLevel) {}
+
+    pub fn set_opacity(&self, _alpha: f64) {}
+
+    pub fn set_background_transparent(&self, _transparent: bool) {}
+
+    pub fn set_hidden(&self, _hidden: bool) {}
+
+    pub fn show_without_activating(&self) {}
+
+    pub fn focus(&self) {}
+
+    pub fn is_focused(&self) -> bool {
+        false
+    }
+
+    pub fn blur(&self) {}
+
+    pub fn is_loading(&self) -> bool {
+        false
+    }
+
+    pub fn set_suspended(&self, _suspended: bool) {}
+
+    pub fn set_muted(&self, _muted: bool) {}
+
+    pub fn is_muted(&self) -> bool {
+        false
+    }
+
+    pub fn minimize(&self) {}
+
+    pub fn restore(&self) {}
+
+    pub fn toggle_zoom(&self) {}
+
+    pub fn is_visible(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flip_y() {
+        assert_eq!(flip_y(0.0, 600.0, 1080.0), 480.0);
+        assert_eq!(flip_y(480.0, 600.0, 1080.0), 0.0);
+        assert_eq!(flip_y(100.0, 200.0, 900.0), 600.0);
+    }
+
+    #[test]
+    fn test_bounds_round_trip_through_flipped_frame() {
+        let screen_height = 1117.0;
+        for (y, height) in [(0.0_f32, 600.0_f32), (37.5, 412.25), (900.0, 217.0)] {
+            let cocoa_y = flip_y(y as f64, height as f64, screen_height);
+            let round_tripped = flip_y(cocoa_y, height as f64, screen_height) as f32;
+            assert!((round_tripped - y).abs() < 1.0, "{round_tripped} != {y}");
+        }
+    }
+
+    #[test]
+    fn test_parse_scroll_position() {
+        assert_eq!(parse_scroll_position("0,0"), Some((0.0, 0.0)));
+        assert_eq!(parse_scroll_position("12.5,300"), Some((12.5, 300.0)));
+        assert_eq!(parse_scroll_position(""), None);
+        assert_eq!(parse_scroll_position("12"), None);
+        assert_eq!(parse_scroll_position("x,y"), None);
+    }
+
+    #[test]
+    fn test_find_script() {
+        assert_eq!(
+            find_script("needle", true),
+            r#"window.find("needle", false, false, true)"#
+        );
+        assert_eq!(
+            find_script("quote \" and \\", false),
+            r#"window.find("quote \" and \\", false, true, true)"#
+        );
+        assert!(find_script_matched("true"));
+        assert!(find_script_matched("1"));
+        assert!(!find_script_matched("false"));
+        assert!(!find_script_matched("0"));
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    #[test]
+    fn test_cancelled_navigation_is_blocked() {
+        let state = RefCell::new(WebViewState::default());
+        assert_eq!(
+            WebViewState::decide_navigation(&state, "https://example.com/"),
+            NavigationDecision::Allow
+        );
+
+        state.borrow_mut().navigation_policy = Some(Box::new(|url: &str| {
+            if url.starts_with("https://zed.dev/") {
+                NavigationDecision::Allow
+            } else {
+                NavigationDecision::Cancel
+            }
+        }));
+        assert_eq!(
+            wk_navigation_action_policy(WebViewState::decide_navigation(
+                &state,
+                "https://zed.dev/docs"
+            )),
+            WK_NAVIGATION_ACTION_POLICY_ALLOW
+        );
+        assert_eq!(
+            wk_navigation_action_policy(WebViewState::decide_navigation(
+                &state,
+                "https://example.com/"
+            )),
+            WK_NAVIGATION_ACTION_POLICY_CANCEL
+        );
+        assert!(state.borrow().navigation_policy.is_some());
+
+        let (sender, mut receiver) = oneshot::channel();
+        state.borrow_mut().pending_navigation = Some(sender);
+        WebViewState::cancel_pending_navigation(&state, "https://example.com/");
+        assert!(matches!(receiver.try_recv(), Ok(Some(Err(_)))));
+        assert_eq!(
+            wk_navigation_action_policy(NavigationDecision::OpenExternally),
+            WK_NAVIGATION_ACTION_POLICY_CANCEL
+        );
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    #[test]
+    fn test_update_title() {
+        let state = RefCell::new(WebViewState::default());
+        let titles = Rc::new(RefCell::new(Vec::new()));
+        state.borrow_mut().title_changed = Some(Box::new({
+            let titles = titles.clone();
+            move |title| titles.borrow_mut().push(title)
+        }));
+        WebViewState::update_title(&state, "Zed".to_string());
+        assert_eq!(state.borrow_mut().new_title.take().as_deref(), Some("Zed"));
+        assert_eq!(*titles.borrow(), ["Zed"]);
+    }
+
+    #[test]
+    fn test_load_state_ends_first_load() {
+        let mut load = LoadState::default();
+        load.deferred_scripts.push("setup()");
+        assert_eq!(load.end_load(true), ["setup()"]);
+        assert!(load.has_loaded && !load.last_load_failed);
+        assert!(load.end_load(false).is_empty());
+        assert!(load.last_load_failed);
+
+        // A cancelled first load ends it, but a cancel after it ended leaves the page alone.
+        let mut load = LoadState::default();
+        load.deferred_scripts.push("setup()");
+        assert_eq!(load.cancel_load(), ["setup()"]);
+        assert!(load.has_loaded && load.last_load_failed);
+        load.end_load(true);
+        assert!(load.cancel_load().is_empty());
+        assert!(!load.last_load_failed);
+    }
+
+    #[test]
+    fn test_load_state_crashes() {
+        // The reload ends the first load, so the scripts stay queued for it.
+        let mut load = LoadState::default();
+        load.deferred_scripts.push("setup()");
+        assert_eq!(load.crash(false), (true, Vec::new()));
+        assert!(!load.has_loaded);
+
+        // Crashing again before the reload finished gives up on the first load.
+        assert_eq!(load.crash(false), (false, vec!["setup()"]));
+        assert!(load.has_loaded && load.last_load_failed);
+        load.end_load(true);
+        assert_eq!(load.crash(false), (true, Vec::new()));
+
+        // A crash handler replaces the reload.
+        let mut load = LoadState::default();
+        load.deferred_scripts.push("setup()");
+        assert_eq!(load.crash(true), (false, vec!["setup()"]));
+        assert!(load.has_loaded);
+    }
+
+    #[test]
+    fn test_external_url() {
+        assert_eq!(external_url("zed.dev").unwrap(), "https://zed.dev/");
+        assert_eq!(
+            external_url("HTTP://zed.dev/docs").unwrap(),
+            "http://zed.dev/docs"
+        );
+        assert_eq!(
+            external_url("mailto:hi@zed.dev").unwrap(),
+            "mailto:hi@zed.dev"
+        );
+        assert!(external_url("file:///Applications/Calculator.app").is_err());
+        assert!(external_url("javascript:alert(1)").is_err());
+        assert!(external_url("vscode://open?file=/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("https://zed.dev").unwrap(),
+            "https://zed.dev/"
+        );
+        assert_eq!(
+            normalize_url("zed.dev/docs").unwrap(),
+            "https://zed.dev/docs"
+        );
+        assert_eq!(
+            normalize_url("  localhost:3000/path  ").unwrap(),
+            "https://localhost:3000/path"
+        );
+        assert_eq!(
+            normalize_url("https://zed.dev/a page?q=two words").unwrap(),
+            "https://zed.dev/a%20page?q=two%20words"
+        );
+        assert_eq!(normalize_url("about:blank").unwrap(), "about:blank");
+        assert_eq!(
+            normalize_url("file:///tmp/a.html").unwrap(),
+            "file:///tmp/a.html"
+        );
+
+        assert!(normalize_url("").is_err());
+        assert!(normalize_url("   ").is_err());
+        assert!(normalize_url("https://exa mple.com").is_err());
+        assert!(!is_valid_url("http://[::1"));
+        assert!(is_valid_url("example.com"));
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let builder = WebViewManager::builder();
+        assert_eq!(builder.url, "about:blank");
+        assert!(builder.data_store.is_persistent());
+        assert!(!builder.inspectable);
+        assert!(builder.activate);
+        assert!(!builder.initially_hidden);
+        assert_eq!(builder.titlebar_style, TitlebarStyle::Transparent);
+        assert!(builder.resizable);
+        assert_eq!(builder.min_size, None);
+        assert_eq!(builder.max_size, None);
+        assert!(builder.scheme_handlers.is_empty());
+        assert_eq!(builder.media_autoplay, AutoplayPolicy::RequireUserGesture);
+        assert_eq!(builder.title, None);
+        assert_eq!(builder.proxy, None);
+        assert_eq!(builder.cache_policy, CachePolicy::Default);
+        assert_eq!(builder.timeout, DEFAULT_REQUEST_TIMEOUT);
+        assert!(builder.attach_to_parent);
+        assert_eq!(builder.screen, ScreenSelector::Parent);
+        assert!(builder.javascript_enabled);
+        assert!(builder.load_images);
+        assert!(builder.fraudulent_website_warning);
+
+        let bounds = Bounds {
+            origin: point(px(10.0), px(20.0)),
+            size: size(px(800.0), px(480.0)),
+        };
+        let builder = builder
+            .url("https://zed.dev")
+            .bounds(bounds)
+            .title("Docs")
+            .user_agent("Zed")
+            .persistent(false)
+            .inspectable(true)
+            .window_level(WindowLevel::Normal)
+            .activate(false)
+            .initially_hidden(true)
+            .titlebar_style(TitlebarStyle::Hidden)
+            .resizable(false)
+            .min_size(size(px(320.0), px(240.0)))
+            .max_size(size(px(1280.0), px(960.0)))
+            .register_scheme_handler("Zed-Docs", |_| SchemeResponse::not_found())
+            .media_autoplay(AutoplayPolicy::Block)
+            .attach_to_parent(false)
+            .screen(ScreenSelector::Main)
+            .javascript_enabled(false)
+            .load_images(false)
+            .fraudulent_website_warning(false);
+        assert_eq!(builder.url, "https://zed.dev");
+        assert_eq!(builder.bounds, bounds);
+        assert_eq!(builder.title.as_deref(), Some("Docs"));
+        assert_eq!(builder.user_agent.as_deref(), Some("Zed"));
+        assert!(!builder.data_store.is_persistent());
+        assert!(builder.inspectable);
+        assert_eq!(builder.window_level, WindowLevel::Normal);
+        assert!(!builder.activate);
+        assert!(builder.initially_hidden);
+        assert_eq!(builder.titlebar_style, TitlebarStyle::Hidden);
+        assert!(!builder.resizable);
+        assert_eq!(builder.min_size, Some(size(px(320.0), px(240.0))));
+        assert_eq!(builder.max_size, Some(size(px(1280.0), px(960.0))));
+        assert_eq!(builder.scheme_handlers.len(), 1);
+        assert_eq!(builder.scheme_handlers[0].0, "zed-docs");
+        assert_eq!(builder.media_autoplay, AutoplayPolicy::Block);
+        assert!(!builder.attach_to_parent);
+        assert_eq!(builder.screen, ScreenSelector::Main);
+        assert!(!builder.javascript_enabled);
+        assert!(!builder.load_images);
+        assert!(!builder.fraudulent_website_warning);
+    }
+
+    #[test]
+    fn test_navigation_history() {
+        let mut history = NavigationHistory::new("about:blank");
+        history.push("https://zed.dev/".to_string());
+        history.push("https://zed.dev/".to_string());
+        history.push("https://zed.dev/docs".to_string());
+        history.push("https://zed.dev/".to_string());
+        assert_eq!(
+            history.entries,
+            [
+                "about:blank",
+                "https://zed.dev/",
+                "https://zed.dev/docs",
+                "https://zed.dev/"
+            ]
+        );
+
+        history.set_limit(2);
+        assert_eq!(
+            history.entries,
+            ["https://zed.dev/docs", "https://zed.dev/"]
+        );
+        history.push("https://zed.dev/blog".to_string());
+        assert_eq!(
+            history.entries,
+            ["https://zed.dev/", "https://zed.dev/blog"]
+        );
+    }
+
+    #[test]
+    fn test_pending_bounds() {
+        let pending_bounds = PendingBounds::default();
+        let bounds = Bounds {
+            origin: point(px(10.0), px(20.0)),
+            size: size(px(300.0), px(200.0)),
+        };
+        pending_bounds.remember_if_hidden(bounds, false);
+        assert_eq!(pending_bounds.take(), None);
+
+        pending_bounds.remember_if_hidden(Bounds::default(), true);
+        pending_bounds.remember_if_hidden(bounds, true);
+        assert_eq!(pending_bounds.take(), Some(bounds));
+        assert_eq!(pending_bounds.take(), None);
+    }
+
+    #[test]
+    fn test_navigation_target() {
+        assert_eq!(
+            navigation_target("https://zed.dev/", "zed.dev/docs", true).unwrap(),
+            Some("https://zed.dev/docs".to_string())
+        );
+        assert_eq!(
+            navigation_target("https://zed.dev/", "zed.dev", true).unwrap(),
+            None
+        );
+        assert_eq!(
+            navigation_target("https://zed.dev/", "zed.dev", false).unwrap(),
+            Some("https://zed.dev/".to_string())
+        );
+        assert!(navigation_target("https://zed.dev/", "", true).is_err());
+
+        let mut current_url = "about:blank".to_string();
+        let mut history = NavigationHistory::new(&current_url);
+        record_navigation(&mut current_url, &mut history, "https://zed.dev/");
+        assert_eq!(current_url, "https://zed.dev/");
+        assert_eq!(history.entries, ["about:blank", "https://zed.dev/"]);
+    }
+
+    #[test]
+    fn test_parse_extracted_text() {
+        assert_eq!(parse_extracted_text(r#""Zed\nDocs""#).unwrap(), "Zed\nDocs");
+        assert_eq!(parse_extracted_text("\"\"").unwrap(), "");
+        assert!(parse_extracted_text("null").is_err());
+    }
+
+    #[test]
+    fn test_parse_element_rect() {
+        assert_eq!(
+            parse_element_rect("10,20.5,300,150,800"),
+            Some(ElementRect {
+                x: 10.0,
+                y: 20.5,
+                width: 300.0,
+                height: 150.0,
+                viewport_width: 800.0,
+            })
+        );
+        assert_eq!(parse_element_rect(""), None);
+        assert_eq!(parse_element_rect("10,20,0,150,800"), None);
+        assert_eq!(parse_element_rect("10,20,300"), None);
+    }
+
+    #[test]
+    fn test_crop_png() {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(200, 100)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        // A 2x snapshot of a 100px-wide viewport.
+        let rect = |x, y, width, height| ElementRect {
+            x,
+            y,
+            width,
+            height,
+            viewport_width: 100.0,
+        };
+        let size = |png: Vec<u8>| {
+            let image = image::load_from_memory(&png).unwrap();
+            (image.width(), image.height())
+        };
+
+        assert_eq!(
+            size(crop_png(&png, rect(10.0, 5.0, 20.0, 10.0)).unwrap()),
+            (40, 20)
+        );
+        // Clipped to the visible area.
+        assert_eq!(
+            size(crop_png(&png, rect(80.0, 40.0, 50.0, 50.0)).unwrap()),
+            (40, 20)
+        );
+        assert!(crop_png(&png, rect(150.0, 0.0, 10.0, 10.0)).is_err());
+    }
+
+    #[test]
+    fn test_directory_size() {
+        let root = std::env::temp_dir().join(format!("zed-webview-size-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        std::fs::write(root.join("a"), [0; 10]).unwrap();
+        std::fs::write(root.join("nested/b"), [0; 32]).unwrap();
+        assert_eq!(directory_size(&root), 42);
+        assert_eq!(directory_size(&root.join("nested/b")), 32);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(directory_size(&root), 0);
+    }
+
+    #[test]
+    fn test_data_store_handles() {
+        assert!(DataStoreHandle::default().is_persistent());
+        assert!(!DataStoreHandle::ephemeral().is_persistent());
+        let identifier = Uuid::new_v4();
+        let named = DataStoreHandle::named(identifier);
+        assert!(named.is_persistent());
+        assert_eq!(named.identifier(), Some(identifier));
+        assert_eq!(DataStoreHandle::ephemeral().identifier(), None);
+
+        let builder = WebViewManager::builder().data_store(named.clone());
+        assert_eq!(builder.data_store.identifier(), Some(identifier));
+        let builder = builder.persistent(false);
+        assert_eq!(builder.data_store.identifier(), None);
+        assert!(!builder.data_store.is_persistent());
+    }
+
+    #[test]
+    fn test_file_url() {
+        let root = std::env::temp_dir().join(format!("zed-webview-file-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("read me.html");
+        std::fs::write(&path, "<p>hi</p>").unwrap();
+
+        let (resolved, url) = file_url(&path).unwrap();
+        assert_eq!(resolved, std::fs::canonicalize(&path).unwrap());
+        assert!(url.starts_with("file://"));
+        assert!(url.ends_with("/read%20me.html"));
+        assert!(file_url(&root).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(file_url(&path).is_err());
+    }
+
+    #[test]
+    fn test_webview_slots() {
+        // Separate from the app's slots, so that webviews built by other tests don't count.
+        static SLOTS: WebViewSlots = WebViewSlots::new();
+        SLOTS.set_max(Some(1));
+        let slot = SLOTS.acquire().unwrap();
+        let error = SLOTS.acquire().err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("cannot open more than 1 webviews")
+        );
+
+        drop(slot);
+        assert_eq!(SLOTS.active.load(Ordering::Relaxed), 0);
+        let slot = SLOTS.acquire().unwrap();
+        SLOTS.set_max(None);
+        let another_slot = SLOTS.acquire().unwrap();
+        assert_eq!(SLOTS.active.load(Ordering::Relaxed), 2);
+        drop((slot, another_slot));
+    }
+
+    #[test]
+    fn test_batch_script() {
+        let script = batch_script(&["document.title", "window.scrollY // offset"]);
+        assert!(script.starts_with("(() => {"));
+        assert!(script.contains("__zedStringify((\ndocument.title\n))"));
+        assert!(script.contains("__zedStringify((\nwindow.scrollY // offset\n))"));
+        assert!(script.ends_with("return JSON.stringify(__zedResults);\n})()"));
+
+        let results = parse_batch_results(
+            Ok(r#"[{"ok":"Zed"},{"error":"ReferenceError: x is not defined"}]"#.to_string()),
+            2,
+        );
+        assert_eq!(results[0].as_ref().unwrap(), "Zed");
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "ReferenceError: x is not defined"
+        );
+
+        let results = parse_batch_results(Err(anyhow::anyhow!("SyntaxError")), 2);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.is_err()));
+        assert!(parse_batch_results(Ok(r#"[{"ok":"Zed"}]"#.to_string()), 2)[1].is_err());
+    }
+
+    #[test]
+    fn test_network_idle_script() {
+        let script = network_idle_script(500);
+        assert!(script.starts_with("window.__zedNetworkIdleMs = 500;"));
+        assert!(script.contains("messageHandlers?.zedNetworkIdle?.postMessage('')"));
+        assert!(script.contains("document.readyState !== 'complete'"));
+    }
+
+    #[test]
+    fn test_mute_script() {
+        let muted = mute_script(true);
+        assert!(muted.contains("window.__zedMuted = true;"));
+        assert!(muted.contains("media.muted = true;"));
+        assert!(mute_script(false).contains("media.muted = false;"));
+    }
+
+    #[test]
+    fn test_page_visibility_script() {
+        let hidden = page_visibility_script(true);
+        assert!(hidden.contains("get: () => 'hidden'"));
+        assert!(hidden.contains("new Event('visibilitychange')"));
+
+        let visible = page_visibility_script(false);
+        assert!(visible.contains("delete document.visibilityState"));
+        assert!(visible.contains("new Event('visibilitychange')"));
+    }
+
+    #[test]
+    fn test_anchored_origin() {
+        let area = Bounds {
+            origin: point(px(0.0), px(25.0)),
+            size: size(px(1000.0), px(775.0)),
+        };
+        let window = size(px(300.0), px(200.0));
+        let button = |x: f32, y: f32| Bounds {
+            origin: point(px(x), px(y)),
+            size: size(px(80.0), px(20.0)),
+        };
+        let origin = |anchor, side| anchored_origin(anchor, window, px(4.0), side, Some(area));
+
+        assert_eq!(
+            origin(button(100.0, 100.0), AnchorSide::Below),
+            point(px(100.0), px(124.0))
+        );
+        // No room below, so it opens above the anchor.
+        assert_eq!(
+            origin(button(100.0, 700.0), AnchorSide::Below),
+            point(px(100.0), px(496.0))
+        );
+        // Clamped to the right edge of the work area.
+        assert_eq!(
+            origin(button(900.0, 100.0), AnchorSide::Below),
+            point(px(700.0), px(124.0))
+        );
+        assert_eq!(
+            origin(button(100.0, 100.0), AnchorSide::Right),
+            point(px(184.0), px(100.0))
+        );
+        // No room to the right, so it opens to the left, clamped below the menu bar.
+        assert_eq!(
+            origin(button(900.0, 0.0), AnchorSide::Right),
+            point(px(596.0), px(25.0))
+        );
+        assert_eq!(
+            anchored_origin(
+                button(900.0, 700.0),
+                window,
+                px(4.0),
+                AnchorSide::Below,
+                None
+            ),
+            point(px(900.0), px(724.0))
+        );
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let session = WebViewSession {
+            url: "https://zed.dev/docs".into(),
+            history: vec!["https://zed.dev/".into(), "https://zed.dev/docs".into()],
+            bounds: Some(Bounds {
+                origin: point(px(10.0), px(20.0)),
+                size: size(px(800.0), px(600.0)),
+            }),
+            visible: true,
+            interaction_state: None,
+        };
+        let json = serde_json::to_string(&session).unwrap();
+        assert!(!json.contains("interaction_state"));
+        assert_eq!(
+            serde_json::from_str::<WebViewSession>(&json).unwrap(),
+            session
+        );
+
+        let mut history = NavigationHistory::new("about:blank");
+        history.set_limit(1);
+        history.replace(session.history);
+        assert_eq!(history.entries, ["https://zed.dev/docs"]);
+    }
+
+    #[test]
+    fn test_origin_string() {
+        assert_eq!(origin_string("https", "zed.dev", 0), "https://zed.dev");
+        assert_eq!(
+            origin_string("http", "localhost", 8080),
+            "http://localhost:8080"
+        );
+    }
+
+    #[test]
+    fn test_basic_auth_matches_its_server() {
+        let basic_auth = |port| BasicAuth {
+            host: "intranet.example.com".to_string(),
+            port,
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        };
+        assert!(basic_auth(None).matches("intranet.example.com", 8443));
+        assert!(basic_auth(None).matches("Intranet.Example.com", 443));
+        assert!(!basic_auth(None).matches("example.com", 443));
+        assert!(basic_auth(Some(8443)).matches("intranet.example.com", 8443));
+        assert!(!basic_auth(Some(8443)).matches("intranet.example.com", 443));
+    }
+
+    #[test]
+    fn test_auth_method_from_name() {
+        assert_eq!(
+            auth_method_from_name("NSURLAuthenticationMethodServerTrust"),
+            AuthMethod::ServerTrust
+        );
+        assert_eq!(
+            auth_method_from_name("NSURLAuthenticationMethodHTTPBasic"),
+            AuthMethod::HttpBasic
+        );
+        assert_eq!(
+            auth_method_from_name("NSURLAuthenticationMethodNTLM"),
+            AuthMethod::Other
+        );
+    }
+
+    #[test]
+    fn test_cookie_matches_url() {
+        let cookie = Cookie {
+            name: "session".into(),
+            domain: ".zed.dev".into(),
+            path: "/docs".into(),
+            secure: true,
+            ..Default::default()
+        };
+        let matches = |url: &str| cookie_matches_url(&cookie, &url::Url::parse(url).unwrap());
+        assert!(matches("https://zed.dev/docs"));
+        assert!(matches("https://www.zed.dev/docs/extensions"));
+        assert!(!matches("https://notzed.dev/docs"));
+        assert!(!matches("https://zed.dev/documentation"));
+        assert!(!matches("http://zed.dev/docs"));
+
+        let host_only = Cookie {
+            domain: "zed.dev".into(),
+            path: "/".into(),
+            ..Default::default()
+        };
+        assert!(cookie_matches_url(
+            &host_only,
+            &url::Url::parse("http://zed.dev/blog").unwrap()
+        ));
+        assert!(!cookie_matches_url(
+            &host_only,
+            &url::Url::parse("http://www.zed.dev/").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_has_droppable_extension() {
+        assert!(has_droppable_extension(Path::new("/tmp/report.html")));
+        assert!(has_droppable_extension(Path::new("/tmp/Manual.PDF")));
+        assert!(!has_droppable_extension(Path::new("/tmp/script.sh")));
+        assert!(!has_droppable_extension(Path::new("/tmp/README")));
+    }
+
+    #[test]
+    fn test_modifiers_from_ns() {
+        assert_eq!(modifiers_from_ns(1 << 20), Modifiers::command());
+        assert_eq!(
+            modifiers_from_ns((1 << 20) | (1 << 17)),
+            Modifiers::command_shift()
+        );
+        assert_eq!(modifiers_from_ns(0), Modifiers::none());
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_keystroke_matches() {
+        let keystroke = |source| Keystroke::parse(source).unwrap();
+        let up = gpui::key_to_native("up");
+        assert!(keystroke_matches(
+            &keystroke("cmd-up"),
+            Modifiers::command(),
+            &up,
+            &up
+        ));
+        assert!(keystroke_matches(
+            &keystroke("cmd-shift-p"),
+            Modifiers::command_shift(),
+            "P",
+            "p"
+        ));
+        assert!(keystroke_matches(
+            &keystroke("cmd-shift-/"),
+            Modifiers::command_shift(),
+            "?",
+            "/"
+        ));
+        assert!(keystroke_matches(
+            &keystroke("cmd-enter"),
+            Modifiers::command(),
+            "\r",
+            "\r"
+        ));
+        assert!(!keystroke_matches(
+            &keystroke("cmd-p"),
+            Modifiers::command_shift(),
+            "P",
+            "p"
+        ));
+    }
+
+    #[test]
+    fn test_fullscreen_restore() {
+        let original = Bounds {
+            origin: point(px(100.0), px(100.0)),
+            size: size(px(640.0), px(480.0)),
+        };
+        let fullscreen = Bounds {
+            origin: point(px(0.0), px(0.0)),
+            size: size(px(1920.0), px(1080.0)),
+        };
+        const WK_FULLSCREEN_STATE_IN_FULLSCREEN: isize = 2;
+        let mut restore = FullscreenRestore::default();
+        assert_eq!(
+            restore.update(WK_FULLSCREEN_STATE_NOT_IN_FULLSCREEN, original, None),
+            None
+        );
+
+        assert_eq!(
+            restore.update(
+                WK_FULLSCREEN_STATE_ENTERING_FULLSCREEN,
+                original,
+                Some(fullscreen)
+            ),
+            Some(fullscreen)
+        );
+        assert_eq!(
+            restore.update(WK_FULLSCREEN_STATE_IN_FULLSCREEN, fullscreen, None),
+            None
+        );
+        assert_eq!(
+            restore.update(
+                WK_FULLSCREEN_STATE_ENTERING_FULLSCREEN,
+                fullscreen,
+                Some(fullscreen)
+            ),
+            Some(fullscreen)
+        );
+        assert_eq!(
+            restore.update(
+                WK_FULLSCREEN_STATE_NOT_IN_FULLSCREEN,
+                fullscreen,
+                Some(fullscreen)
+            ),
+            Some(original)
+        );
+        assert_eq!(
+            restore.update(WK_FULLSCREEN_STATE_NOT_IN_FULLSCREEN, original, None),
+            None
+        );
+
+        // Without a screen to fill, the window stays put but is still restored.
+        assert_eq!(
+            restore.update(WK_FULLSCREEN_STATE_ENTERING_FULLSCREEN, original, None),
+            None
+        );
+        assert_eq!(
+            restore.update(WK_FULLSCREEN_STATE_NOT_IN_FULLSCREEN, original, None),
+            Some(original)
+        );
+    }
+
+    #[test]
+    fn test_clamp_zoom() {
+        assert_eq!(clamp_zoom(1.0), 1.0);
+        assert_eq!(clamp_zoom(1.5), 1.5);
+        assert_eq!(clamp_zoom(0.0), MIN_ZOOM);
+        assert_eq!(clamp_zoom(-2.0), MIN_ZOOM);
+        assert_eq!(clamp_zoom(100.0), MAX_ZOOM);
+        assert_eq!(clamp_zoom(f64::INFINITY), MAX_ZOOM);
+        assert_eq!(clamp_zoom(f64::NAN), 1.0);
     }
 }
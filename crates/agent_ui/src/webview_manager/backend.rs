@@ -0,0 +1,391 @@
+use super::WebViewManager;
+#[cfg(any(test, feature = "test-support"))]
+use super::{
+    LoadState, NavigationHistory, PendingBounds, navigation_target, normalize_url,
+    record_navigation,
+};
+use anyhow::Result;
+use gpui::{Bounds, Pixels};
+#[cfg(any(test, feature = "test-support"))]
+use std::cell::{Cell, RefCell};
+
+/// The core operations shared by [`WebViewManager`] and [`MockWebViewBackend`], so that code
+/// driving a webview can be tested without a display.
+pub trait WebViewBackend {
+    /// Loads `url` unless it is already the current URL. Fails when `url` is invalid.
+    fn navigate(&mut self, url: &str) -> Result<()>;
+    fn set_hidden(&self, hidden: bool);
+    fn is_visible(&self) -> bool;
+    /// Bounds set while hidden take effect when the window is shown again.
+    fn set_bounds(&self, bounds: Bounds<Pixels>);
+    fn get_bounds(&self) -> Bounds<Pixels>;
+    /// Follows redirects and links followed in the page, not just [`Self::navigate`] calls.
+    fn current_url(&mut self) -> &str;
+    /// Scripts evaluated before the first load ends are queued and run in call order once it
+    /// does.
+    fn evaluate_javascript_fire_and_forget(&self, script: &str);
+    fn has_loaded(&self) -> bool;
+}
+
+impl WebViewBackend for WebViewManager {
+    fn navigate(&mut self, url: &str) -> Result<()> {
+        WebViewManager::navigate(self, url)
+    }
+
+    fn set_hidden(&self, hidden: bool) {
+        WebViewManager::set_hidden(self, hidden);
+    }
+
+    fn is_visible(&self) -> bool {
+        WebViewManager::is_visible(self)
+    }
+
+    fn set_bounds(&self, bounds: Bounds<Pixels>) {
+        WebViewManager::set_bounds(self, bounds);
+    }
+
+    fn get_bounds(&self) -> Bounds<Pixels> {
+        WebViewManager::get_bounds(self)
+    }
+
+    fn current_url(&mut self) -> &str {
+        WebViewManager::current_url(self)
+    }
+
+    fn evaluate_javascript_fire_and_forget(&self, script: &str) {
+        WebViewManager::evaluate_javascript_fire_and_forget(self, script);
+    }
+
+    fn has_loaded(&self) -> bool {
+        WebViewManager::has_loaded(self)
+    }
+}
+
+/// Records navigations, visibility, and bounds in memory, applying the same URL validation and
+/// deduplication as the native backends. Like some native windows, it ignores frame changes while
+/// hidden, relying on the bounds being reapplied when shown.
+#[cfg(any(test, feature = "test-support"))]
+pub struct MockWebViewBackend {
+    current_url: String,
+    history: NavigationHistory,
+    hidden: Cell<bool>,
+    bounds: Cell<Bounds<Pixels>>,
+    pending_bounds: PendingBounds,
+    load_count: usize,
+    crash: Option<Box<dyn FnMut()>>,
+    /// Until the first load ends, scripts are queued like on the native backends instead of
+    /// being evaluated.
+    load: RefCell<LoadState<String>>,
+    evaluated_scripts: RefCell<Vec<String>>,
+    /// Navigations requested before [`Self::become_ready`], when created with
+    /// [`Self::new_not_ready`].
+    queued_navigations: Option<Vec<String>>,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl MockWebViewBackend {
+    pub fn new(url: &str) -> Result<Self> {
+        let url = normalize_url(url)?;
+        Ok(Self {
+            history: NavigationHistory::new(&url),
+            current_url: url,
+            hidden: Cell::new(false),
+            bounds: Cell::new(Bounds::default()),
+            pending_bounds: PendingBounds::default(),
+            load_count: 1,
+            crash: None,
+            load: RefCell::new(LoadState::default()),
+            evaluated_scripts: RefCell::new(Vec::new()),
+            queued_navigations: None,
+        })
+    }
+
+    /// Like [`Self::new`], but simulates a webview that is still being created: navigations are
+    /// validated and queued until [`Self::become_ready`], instead of loading.
+    pub fn new_not_ready(url: &str) -> Result<Self> {
+        Ok(Self {
+            queued_navigations: Some(Vec::new()),
+            ..Self::new(url)?
+        })
+    }
+
+    /// Finishes creating a webview made with [`Self::new_not_ready`], applying the navigations
+    /// queued until now in call order.
+    pub fn become_ready(&mut self) {
+        for url in self.queued_navigations.take().unwrap_or_default() {
+            self.load_url(&url).ok();
+        }
+    }
+
+    fn load_url(&mut self, url: &str) -> Result<()> {
+        let load = self.load.get_mut();
+        if let Some(url) = navigation_target(&self.current_url, url, !load.last_load_failed)? {
+            record_navigation(&mut self.current_url, &mut self.history, &url);
+            self.load_count += 1;
+            load.last_load_failed = false;
+        }
+        Ok(())
+    }
+
+    /// How many loads were issued, counting the initial one.
+    pub fn load_count(&self) -> usize {
+        self.load_count
+    }
+
+    /// Simulates the current load failing, like a native backend's navigation delegate would
+    /// report it.
+    pub fn fail_load(&mut self) {
+        let deferred_scripts = self.load.get_mut().end_load(false);
+        self.evaluated_scripts.get_mut().extend(deferred_scripts);
+    }
+
+    pub fn finish_load(&mut self) {
+        let deferred_scripts = self.load.get_mut().end_load(true);
+        self.evaluated_scripts.get_mut().extend(deferred_scripts);
+    }
+
+    /// Simulates a navigation policy cancelling the current load.
+    pub fn cancel_load(&mut self) {
+        let deferred_scripts = self.load.get_mut().cancel_load();
+        self.evaluated_scripts.get_mut().extend(deferred_scripts);
+    }
+
+    /// Scripts evaluated so far, in the order they ran.
+    pub fn evaluated_scripts(&self) -> Vec<String> {
+        self.evaluated_scripts.borrow().clone()
+    }
+
+    pub fn on_crash(&mut self, handler: impl FnMut() + 'static) {
+        self.crash = Some(Box::new(handler));
+    }
+
+    /// Simulates the page's content process crashing. Without a crash handler, the page is
+    /// reloaded unless it crashed again before that reload finished, like on the native backends.
+    /// Scripts waiting for a first load that won't end are dropped.
+    pub fn crash(&mut self) {
+        let handled = self.crash.as_mut().map(|crash| crash()).is_some();
+        let (reload, _abandoned) = self.load.get_mut().crash(handled);
+        if reload {
+            self.load_count += 1;
+        }
+    }
+
+    /// Simulates the page navigating on its own, e.g. through a redirect, without going through
+    /// [`WebViewBackend::navigate`].
+    pub fn redirect(&mut self, url: &str) -> Result<()> {
+        self.current_url = normalize_url(url)?;
+        Ok(())
+    }
+
+    /// Every URL loaded, starting with the initial one.
+    pub fn history(&self) -> &[String] {
+        &self.history.entries
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl WebViewBackend for MockWebViewBackend {
+    fn navigate(&mut self, url: &str) -> Result<()> {
+        if let Some(queued_navigations) = &mut self.queued_navigations {
+            queued_navigations.push(normalize_url(url)?);
+            return Ok(());
+        }
+        self.load_url(url)
+    }
+
+    fn set_hidden(&self, hidden: bool) {
+        self.hidden.set(hidden);
+        if !hidden && let Some(bounds) = self.pending_bounds.take() {
+            self.set_bounds(bounds);
+        }
+    }
+
+    fn is_visible(&self) -> bool {
+        !self.hidden.get()
+    }
+
+    fn set_bounds(&self, bounds: Bounds<Pixels>) {
+        self.pending_bounds
+            .remember_if_hidden(bounds, self.hidden.get());
+        if !self.hidden.get() {
+            self.bounds.set(bounds);
+        }
+    }
+
+    fn get_bounds(&self) -> Bounds<Pixels> {
+        self.bounds.get()
+    }
+
+    fn current_url(&mut self) -> &str {
+        &self.current_url
+    }
+
+    fn evaluate_javascript_fire_and_forget(&self, script: &str) {
+        let mut load = self.load.borrow_mut();
+        if load.has_loaded {
+            self.evaluated_scripts.borrow_mut().push(script.to_string());
+        } else {
+            load.deferred_scripts.push(script.to_string());
+        }
+    }
+
+    fn has_loaded(&self) -> bool {
+        self.load.borrow().has_loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::{point, px, size};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_mock_navigation() {
+        let mut backend = MockWebViewBackend::new("zed.dev").unwrap();
+        assert_eq!(backend.current_url(), "https://zed.dev/");
+
+        backend.navigate("https://zed.dev/").unwrap();
+        backend.navigate("https://zed.dev/docs").unwrap();
+        backend.navigate("https://zed.dev/docs").unwrap();
+        assert!(backend.navigate("https://exa mple.com").is_err());
+        assert_eq!(backend.current_url(), "https://zed.dev/docs");
+        assert_eq!(
+            backend.history(),
+            ["https://zed.dev/", "https://zed.dev/docs"]
+        );
+
+        assert!(backend.is_visible());
+        backend.set_hidden(true);
+        assert!(!backend.is_visible());
+    }
+
+    #[test]
+    fn test_mock_applies_navigations_queued_before_ready_in_order() {
+        let mut backend = MockWebViewBackend::new_not_ready("https://zed.dev/").unwrap();
+        backend.navigate("https://zed.dev/docs").unwrap();
+        backend.navigate("https://zed.dev/blog").unwrap();
+        backend.navigate("https://zed.dev/blog").unwrap();
+        backend.navigate("https://zed.dev/docs").unwrap();
+        assert!(backend.navigate("https://exa mple.com").is_err());
+        assert_eq!(backend.load_count(), 1);
+        assert_eq!(backend.current_url(), "https://zed.dev/");
+
+        backend.become_ready();
+        assert_eq!(backend.current_url(), "https://zed.dev/docs");
+        assert_eq!(
+            backend.history(),
+            [
+                "https://zed.dev/",
+                "https://zed.dev/docs",
+                "https://zed.dev/blog",
+                "https://zed.dev/docs"
+            ]
+        );
+        assert_eq!(backend.load_count(), 4);
+
+        backend.navigate("https://zed.dev/").unwrap();
+        assert_eq!(backend.load_count(), 5);
+    }
+
+    #[test]
+    fn test_mock_applies_bounds_set_while_hidden() {
+        let backend = MockWebViewBackend::new("https://zed.dev/").unwrap();
+        let bounds = Bounds {
+            origin: point(px(40.0), px(60.0)),
+            size: size(px(800.0), px(600.0)),
+        };
+
+        backend.set_hidden(true);
+        backend.set_bounds(bounds);
+        backend.set_hidden(false);
+        assert_eq!(backend.get_bounds(), bounds);
+
+        backend.set_hidden(true);
+        backend.set_hidden(false);
+        assert_eq!(backend.get_bounds(), bounds);
+    }
+
+    #[test]
+    fn test_mock_reloads_after_crash() {
+        let mut backend = MockWebViewBackend::new("https://zed.dev/").unwrap();
+        backend.crash();
+        assert_eq!(backend.load_count(), 2);
+        backend.crash();
+        assert_eq!(backend.load_count(), 2);
+
+        backend.finish_load();
+        backend.crash();
+        assert_eq!(backend.load_count(), 3);
+
+        let crashes = Rc::new(Cell::new(0));
+        backend.on_crash({
+            let crashes = crashes.clone();
+            move || crashes.set(crashes.get() + 1)
+        });
+        backend.finish_load();
+        backend.crash();
+        assert_eq!(crashes.get(), 1);
+        assert_eq!(backend.load_count(), 3);
+    }
+
+    #[test]
+    fn test_mock_defers_scripts_until_first_load() {
+        let mut backend = MockWebViewBackend::new("https://zed.dev/").unwrap();
+        assert!(!backend.has_loaded());
+        backend.evaluate_javascript_fire_and_forget("first()");
+        backend.navigate("https://zed.dev/docs").unwrap();
+        backend.evaluate_javascript_fire_and_forget("second()");
+        assert_eq!(backend.load_count(), 2);
+        assert!(backend.evaluated_scripts().is_empty());
+
+        backend.finish_load();
+        assert!(backend.has_loaded());
+        assert_eq!(backend.evaluated_scripts(), ["first()", "second()"]);
+        backend.evaluate_javascript_fire_and_forget("third()");
+        assert_eq!(
+            backend.evaluated_scripts(),
+            ["first()", "second()", "third()"]
+        );
+
+        let mut backend = MockWebViewBackend::new("https://zed.dev/").unwrap();
+        backend.evaluate_javascript_fire_and_forget("first()");
+        backend.fail_load();
+        assert!(backend.has_loaded());
+        assert_eq!(backend.evaluated_scripts(), ["first()"]);
+
+        let mut backend = MockWebViewBackend::new("https://zed.dev/").unwrap();
+        backend.evaluate_javascript_fire_and_forget("first()");
+        backend.cancel_load();
+        assert!(backend.has_loaded());
+        assert_eq!(backend.evaluated_scripts(), ["first()"]);
+    }
+
+    #[test]
+    fn test_mock_tracks_redirects() {
+        let mut backend = MockWebViewBackend::new("https://zed.dev/account").unwrap();
+        backend.redirect("https://zed.dev/sign_in").unwrap();
+        assert_eq!(backend.current_url(), "https://zed.dev/sign_in");
+
+        backend.navigate("https://zed.dev/sign_in").unwrap();
+        assert_eq!(backend.load_count(), 1);
+        backend.navigate("https://zed.dev/account").unwrap();
+        assert_eq!(backend.load_count(), 2);
+        assert_eq!(backend.current_url(), "https://zed.dev/account");
+    }
+
+    #[test]
+    fn test_mock_retries_failed_load() {
+        let mut backend = MockWebViewBackend::new("https://zed.dev/").unwrap();
+        assert_eq!(backend.load_count(), 1);
+
+        backend.fail_load();
+        backend.navigate("https://zed.dev/").unwrap();
+        assert_eq!(backend.load_count(), 2);
+
+        backend.finish_load();
+        backend.navigate("https://zed.dev/").unwrap();
+        assert_eq!(backend.load_count(), 2);
+        assert_eq!(backend.history(), ["https://zed.dev/"]);
+    }
+}
@@ -0,0 +1,1288 @@
+//! GTK isn't integrated with gpui's event loop, so the WebKitGTK windows live on a dedicated
+//! thread that runs the GTK main loop. `WebViewManager` forwards each call to that thread and
+//! blocks on the reply when a value is needed.
+//!
+//! Handlers are not `Send` and can't be invoked from the GTK thread, so the GTK thread reports
+//! events over a channel that a task on the manager's thread drains. Most callback-based APIs (the
+//! `on_*` event handlers, `register_message_handler`, and methods like `evaluate_javascript` that
+//! report a result through a callback) are not yet supported on Linux.
+
+use super::{
+    Appearance, AuthChallenge, AuthResponse, AutoplayPolicy, CachePolicy, ContextMenuContext,
+    Cookie, DeferredScript, MenuItem, NavigationDecision, NavigationHistory, PendingBounds,
+    PermissionKind, ScreenSelector, TitlebarStyle, WebViewManager, WebViewManagerBuilder,
+    WebViewState, WindowLevel, clamp_zoom, context_menu_flag_script, file_url, mute_script,
+    normalize_url, open_in_default_browser, page_visibility_script,
+};
+use anyhow::{Result, anyhow};
+use futures::{
+    StreamExt as _,
+    channel::{
+        mpsc::{UnboundedSender, unbounded},
+        oneshot,
+    },
+};
+use gpui::{App, Bounds, Edges, Pixels, Size, point, px, size};
+use gtk::{glib, prelude::*};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::c_void,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+};
+use util::ResultExt as _;
+use webkit2gtk::{
+    FindController, FindControllerExt as _, FindOptions, LoadEvent, NavigationPolicyDecision,
+    NavigationPolicyDecisionExt as _, PolicyDecision, PolicyDecisionExt as _, PolicyDecisionType,
+    SettingsExt as _, URIRequest, URIRequestExt as _, UserContentInjectedFrames,
+    UserContentManagerExt as _, UserScript, UserScriptInjectionTime, WebContext, WebView,
+    WebViewExt, WebsiteDataManagerExt as _, WebsiteDataTypes,
+};
+
+thread_local! {
+    /// Only ever accessed on the GTK thread.
+    static WEBVIEWS: RefCell<HashMap<u64, (gtk::Window, WebView)>> = RefCell::default();
+    /// Navigations waiting for a navigation policy to answer, by decision id. Only ever accessed
+    /// on the GTK thread.
+    static POLICY_DECISIONS: RefCell<HashMap<u64, PolicyDecision>> = RefCell::default();
+}
+
+/// Reported by the GTK thread to the thread that owns the manager.
+enum GtkEvent {
+    /// A navigation to `url` is waiting for the navigation policy to answer `decision_id`.
+    DecideNavigation { decision_id: u64, url: String },
+    /// The document title changed.
+    TitleChanged(String),
+    /// A main-frame load ended at `url`, which differs from the requested URL after redirects.
+    /// `load` is the value of `WebViewState::requested_load` when it started.
+    LoadEnded {
+        load: u64,
+        url: String,
+        result: std::result::Result<(), String>,
+    },
+}
+
+/// Holds on to navigation decisions that a navigation policy has to answer, which WebKitGTK waits
+/// for. Returns whether the decision was taken over.
+fn defer_navigation_decision(
+    decision: &PolicyDecision,
+    decision_type: PolicyDecisionType,
+    has_navigation_policy: &AtomicBool,
+    events: &UnboundedSender<GtkEvent>,
+) -> bool {
+    static NEXT_DECISION_ID: AtomicU64 = AtomicU64::new(0);
+
+    if decision_type != PolicyDecisionType::NavigationAction
+        || !has_navigation_policy.load(Ordering::Relaxed)
+    {
+        return false;
+    }
+    let Some(url) = decision
+        .downcast_ref::<NavigationPolicyDecision>()
+        .and_then(|decision| decision.request())
+        .and_then(|request| request.uri())
+    else {
+        return false;
+    };
+    let decision_id = NEXT_DECISION_ID.fetch_add(1, Ordering::Relaxed);
+    let event = GtkEvent::DecideNavigation {
+        decision_id,
+        url: url.to_string(),
+    };
+    if events.unbounded_send(event).is_err() {
+        return false;
+    }
+    POLICY_DECISIONS.with_borrow_mut(|decisions| decisions.insert(decision_id, decision.clone()));
+    true
+}
+
+/// Runs `f` on the GTK thread with the window and webview of the manager with `id`, if it still
+/// exists.
+fn run_on_webview(id: u64, f: impl FnOnce(&gtk::Window, &WebView) + Send + 'static) {
+    glib::MainContext::default().invoke(move || {
+        WEBVIEWS.with_borrow(|webviews| {
+            if let Some((window, webview)) = webviews.get(&id) {
+                f(window, webview);
+            }
+        });
+    });
+}
+
+fn run_javascript(webview: &WebView, script: &str) {
+    webview.run_javascript(script, None::<&gtk::gio::Cancellable>, |_| {});
+}
+
+fn install_user_script(webview: &WebView, source: &str, at_document_start: bool) {
+    let injection_time = if at_document_start {
+        UserScriptInjectionTime::Start
+    } else {
+        UserScriptInjectionTime::End
+    };
+    let script = UserScript::new(
+        source,
+        UserContentInjectedFrames::TopFrame,
+        injection_time,
+        &[],
+        &[],
+    );
+    if let Some(user_content_manager) = webview.user_content_manager() {
+        user_content_manager.add_script(&script);
+    }
+}
+
+/// Runs the scripts queued until the first load ended in the webview of the manager with `id`, in
+/// call order. Evaluations waiting for a result fail, since results can't be reported on Linux.
+fn run_deferred_scripts(id: u64, scripts: Vec<DeferredScript>) {
+    if scripts.is_empty() {
+        return;
+    }
+    let mut queued = Vec::new();
+    for script in scripts {
+        match script {
+            DeferredScript::Evaluate(_, Some(callback)) => callback(Err(evaluation_unsupported())),
+            DeferredScript::Evaluate(script, None) => queued.push((script, None)),
+            DeferredScript::UserScript {
+                source,
+                at_document_start,
+            } => queued.push((source, Some(at_document_start))),
+        }
+    }
+    run_on_webview(id, move |_, webview| {
+        for (source, user_script) in queued {
+            if let Some(at_document_start) = user_script {
+                install_user_script(webview, &source, at_document_start);
+            }
+            run_javascript(webview, &source);
+        }
+    });
+}
+
+fn evaluation_unsupported() -> anyhow::Error {
+    anyhow!("JavaScript evaluation with a result is not supported on Linux")
+}
+
+fn handle_gtk_event(id: u64, state: &RefCell<WebViewState>, event: GtkEvent) {
+    match event {
+        GtkEvent::DecideNavigation { decision_id, url } => {
+            let decision = WebViewState::decide_navigation(state, &url);
+            if decision == NavigationDecision::OpenExternally {
+                open_in_default_browser(&url).log_err();
+            }
+            let allow = decision == NavigationDecision::Allow;
+            if !allow && state.borrow().pending_navigation_url == url {
+                let deferred_scripts = WebViewState::cancel_pending_navigation(state, &url);
+                run_deferred_scripts(id, deferred_scripts);
+            }
+            glib::MainContext::default().invoke(move || {
+                let decision =
+                    POLICY_DECISIONS.with_borrow_mut(|decisions| decisions.remove(&decision_id));
+                match decision {
+                    Some(decision) if allow => decision.use_(),
+                    Some(decision) => decision.ignore(),
+                    None => {}
+                }
+            });
+        }
+        GtkEvent::TitleChanged(title) => WebViewState::update_title(state, title),
+        GtkEvent::LoadEnded { load, url, result } => {
+            // Loads replaced by a newer one still report ending, which says nothing about the page
+            // now loading.
+            if load < state.borrow().requested_load {
+                return;
+            }
+            if result.is_ok() && !url.is_empty() {
+                state.borrow_mut().page_url = Some(url);
+            }
+            let deferred_scripts = WebViewState::finish_pending_navigation(
+                state,
+                result.map_err(|error| anyhow!(error)),
+            );
+            run_deferred_scripts(id, deferred_scripts);
+        }
+    }
+}
+
+/// The reply to a search and the signal handlers waiting to send it.
+type FindReply = RefCell<Option<(oneshot::Sender<bool>, Vec<glib::SignalHandlerId>)>>;
+
+/// Sends the result of a search the first time it is reported, then stops listening for it.
+fn finish_find(reply: &FindReply, find_controller: &FindController, found: bool) {
+    if let Some((tx, handlers)) = reply.take() {
+        for handler in handlers {
+            find_controller.disconnect(handler);
+        }
+        tx.send(found).ok();
+    }
+}
+
+fn ensure_gtk_thread() -> Result<()> {
+    static GTK_THREAD: OnceLock<std::result::Result<(), String>> = OnceLock::new();
+    GTK_THREAD
+        .get_or_init(|| {
+            let (tx, rx) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("webview-gtk".into())
+                .spawn(move || match gtk::init() {
+                    Ok(()) => {
+                        // Report readiness from inside the loop so that `invoke` never runs a
+                        // closure on the calling thread while the context is unowned.
+                        glib::idle_add_once(move || {
+                            tx.send(Ok(())).ok();
+                        });
+                        gtk::main();
+                    }
+                    Err(error) => {
+                        tx.send(Err(error.to_string())).ok();
+                    }
+                })
+                .map_err(|error| error.to_string())?;
+            rx.recv().map_err(|error| error.to_string())?
+        })
+        .clone()
+        .map_err(|error| anyhow!("failed to initialize GTK: {error}"))
+}
+
+pub(super) fn open_url(url: &str) -> Result<()> {
+    let mut child = util::command::new_std_command("xdg-open")
+        .arg(url)
+        .spawn()
+        .map_err(|error| anyhow!("failed to run xdg-open: {error}"))?;
+    // Reap the child without blocking the caller.
+    std::thread::spawn(move || child.wait().log_err());
+    Ok(())
+}
+
+impl WebViewManager {
+    /// Whether GTK could be initialized, which fails without a display.
+    pub fn is_supported() -> bool {
+        ensure_gtk_thread().is_ok()
+    }
+
+    /// WebKitGTK's default autoplay policy already matches
+    /// [`AutoplayPolicy::RequireUserGesture`], and changing it needs a newer WebKitGTK.
+    pub(super) fn create(
+        _parent_window_ptr: *mut c_void,
+        options: &WebViewManagerBuilder,
+        cx: &App,
+    ) -> Result<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let bounds = options.bounds;
+        let persistent = options.data_store.is_persistent();
+        let activate = options.activate;
+        let initially_hidden = options.initially_hidden;
+        let decorated = options.titlebar_style != TitlebarStyle::Hidden;
+        let url = &normalize_url(&options.url)?;
+        ensure_gtk_thread()?;
+        if options.cache_policy != CachePolicy::Default {
+            log::warn!(
+                "WebView cache policy {:?} is not supported on Linux",
+                options.cache_policy
+            );
+        }
+        if options.media_autoplay != AutoplayPolicy::RequireUserGesture {
+            log::warn!(
+                "WebView autoplay policy {:?} is not supported on Linux",
+                options.media_autoplay
+            );
+        }
+
+        anyhow::ensure!(
+            options.proxy.is_none(),
+            "webview proxies are not supported on Linux"
+        );
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let x = f32::from(bounds.origin.x) as i32;
+        let y = f32::from(bounds.origin.y) as i32;
+        let width = f32::from(bounds.size.width) as i32;
+        let height = f32::from(bounds.size.height) as i32;
+        let screen = options.screen;
+        let javascript_enabled = options.javascript_enabled;
+        let load_images = options.load_images;
+        let has_navigation_policy = Arc::new(AtomicBool::new(false));
+        let requested_load = Arc::new(AtomicU64::new(0));
+        let context_menu_disabled = Arc::new(AtomicBool::new(false));
+        let (events, mut event_receiver) = unbounded();
+        let (tx, rx) = mpsc::channel();
+        glib::MainContext::default().invoke({
+            let url = url.to_string();
+            let has_navigation_policy = has_navigation_policy.clone();
+            let requested_load = requested_load.clone();
+            let context_menu_disabled = context_menu_disabled.clone();
+            move || {
+                let window = gtk::Window::new(gtk::WindowType::Toplevel);
+                // The parent handle isn't a GTK window, so `Parent` uses the primary monitor.
+                let monitor = gtk::gdk::Display::default().and_then(|display| match screen {
+                    ScreenSelector::ContainingPoint(point) => display
+                        .monitor_at_point(f32::from(point.x) as i32, f32::from(point.y) as i32),
+                    ScreenSelector::Parent | ScreenSelector::Main => display.primary_monitor(),
+                });
+                // Keeps the window at `bounds`, moved and shrunk as needed to fit the work area.
+                let (x, y, width, height) = match monitor.map(|monitor| monitor.workarea()) {
+                    Some(area) => {
+                        let width = width.min(area.width());
+                        let height = height.min(area.height());
+                        (
+                            x.clamp(area.x(), area.x() + area.width() - width),
+                            y.clamp(area.y(), area.y() + area.height() - height),
+                            width,
+                            height,
+                        )
+                    }
+                    None => (x, y, width, height),
+                };
+                window.set_default_size(width, height);
+                window.move_(x, y);
+                window.set_keep_above(true);
+                window.set_decorated(decorated);
+                window.set_focus_on_map(activate);
+                // Closing only hides the window, matching `setReleasedWhenClosed:NO` on macOS:
+                // the manager owns the window and destroys it on drop.
+                window.connect_delete_event(|window, _| {
+                    window.hide();
+                    glib::Propagation::Stop
+                });
+
+                let webview = if persistent {
+                    WebView::new()
+                } else {
+                    WebView::with_context(&WebContext::new_ephemeral())
+                };
+                // Returning no webview would drop popups, so load them in place instead.
+                webview.connect_create(|webview, navigation_action| {
+                    let mut navigation_action = navigation_action.clone();
+                    if let Some(uri) = navigation_action
+                        .request()
+                        .and_then(|request| request.uri())
+                    {
+                        webview.load_uri(&uri);
+                    }
+                    None
+                });
+                // Crash handlers can't run on this thread, so crashed pages are always reloaded, unless
+                // they crashed again before that reload finished.
+                let reloaded_after_crash = Rc::new(Cell::new(false));
+                webview.connect_web_process_terminated({
+                    let reloaded_after_crash = reloaded_after_crash.clone();
+                    move |webview, reason| {
+                        log::error!("WebView content process terminated: {reason:?}");
+                        if !reloaded_after_crash.replace(true) {
+                            webview.reload();
+                        }
+                    }
+                });
+                // Failed loads report finishing too, right after the failure.
+                let load_error = Rc::new(RefCell::new(None));
+                webview.connect_load_failed({
+                    let load_error = load_error.clone();
+                    move |_, _, uri, error| {
+                        load_error.replace(Some(format!("failed to load {uri}: {error}")));
+                        false
+                    }
+                });
+                let load = Cell::new(0);
+                webview.connect_load_changed({
+                    let events = events.clone();
+                    move |webview, event| match event {
+                        LoadEvent::Started => {
+                            load.set(requested_load.load(Ordering::Relaxed));
+                            load_error.take();
+                        }
+                        LoadEvent::Finished => {
+                            reloaded_after_crash.set(false);
+                            if context_menu_disabled.load(Ordering::Relaxed) {
+                                webview.run_javascript(
+                                    &context_menu_flag_script(true),
+                                    None::<&gtk::gio::Cancellable>,
+                                    |_| {},
+                                );
+                            }
+                            let result = load_error.take().map_or(Ok(()), Err);
+                            let url = webview.uri().map(String::from).unwrap_or_default();
+                            events
+                                .unbounded_send(GtkEvent::LoadEnded {
+                                    load: load.get(),
+                                    url,
+                                    result,
+                                })
+                                .ok();
+                        }
+                        _ => {}
+                    }
+                });
+                webview.connect_title_notify({
+                    let events = events.clone();
+                    move |webview| {
+                        let title = webview.title().map(String::from).unwrap_or_default();
+                        events.unbounded_send(GtkEvent::TitleChanged(title)).ok();
+                    }
+                });
+                webview.connect_decide_policy(move |_, decision, decision_type| {
+                    defer_navigation_decision(
+                        decision,
+                        decision_type,
+                        &has_navigation_policy,
+                        &events,
+                    )
+                });
+                if let Some(settings) = WebViewExt::settings(&webview) {
+                    settings.set_enable_javascript(javascript_enabled);
+                    settings.set_auto_load_images(load_images);
+                }
+                window.add(&webview);
+                webview.load_uri(&url);
+                if !initially_hidden {
+                    window.show_all();
+                }
+
+                let data_store_directories = webview
+                    .website_data_manager()
+                    .map(|data_manager| {
+                        [
+                            data_manager.base_data_directory(),
+                            data_manager.base_cache_directory(),
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .map(|directory| PathBuf::from(directory.as_str()))
+                        .collect()
+                    })
+                    .unwrap_or_default();
+                WEBVIEWS.with_borrow_mut(|webviews| webviews.insert(id, (window, webview)));
+                tx.send(data_store_directories).ok();
+            }
+        });
+        let data_store_directories: Vec<PathBuf> = rx
+            .recv()
+            .map_err(|_| anyhow!("GTK thread exited before creating the webview"))?;
+
+        webview_info!("Created floating webview window ({}x{})", width, height);
+
+        let state = Rc::new(RefCell::new(WebViewState::default()));
+        let gtk_events = cx.foreground_executor().spawn({
+            let state = Rc::downgrade(&state);
+            async move {
+                while let Some(event) = event_receiver.next().await {
+                    let Some(state) = state.upgrade() else {
+                        break;
+                    };
+                    handle_gtk_event(id, &state, event);
+                }
+            }
+        });
+
+        Ok(Self {
+            state,
+            has_navigation_policy,
+            requested_load,
+            _gtk_events: gtk_events,
+            data_store_directories,
+            id,
+            current_url: url.to_string(),
+            history: NavigationHistory::new(url),
+            context_menu_listener_installed: Cell::new(false),
+            pending_bounds: PendingBounds::default(),
+            window_title: String::new(),
+            title: String::new(),
+            data_store: options.data_store.clone(),
+            foreground_executor: cx.foreground_executor().clone(),
+            background_executor: cx.background_executor().clone(),
+            muted: Cell::new(false),
+            context_menu_disabled,
+            min_size: Cell::new(None),
+            max_size: Cell::new(None),
+            aspect_ratio: Cell::new(None),
+            _slot: None,
+            _not_send: PhantomData,
+        })
+    }
+
+    fn run_on_gtk_thread(&self, f: impl FnOnce(&gtk::Window, &WebView) + Send + 'static) {
+        run_on_webview(self.id, f);
+    }
+
+    fn query_gtk_thread<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&gtk::Window, &WebView) -> R + Send + 'static,
+    ) -> Option<R> {
+        let (tx, rx) = mpsc::channel();
+        self.run_on_gtk_thread(move |window, webview| {
+            tx.send(f(window, webview)).ok();
+        });
+        rx.recv().ok()
+    }
+
+    /// Runs `f` on the GTK thread without waiting for it, then invokes `callback` on this thread
+    /// with what `f` sends, or `None` when the webview is gone or `f` drops the sender.
+    fn reply_from_gtk_thread<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WebView, oneshot::Sender<R>) + Send + 'static,
+        callback: impl FnOnce(Option<R>) + 'static,
+    ) {
+        let (tx, rx) = oneshot::channel();
+        self.run_on_gtk_thread(move |_, webview| f(webview, tx));
+        self.foreground_executor
+            .spawn(async move { callback(rx.await.ok()) })
+            .detach();
+    }
+
+    /// Loads `url`, adding `https://` when it has no scheme. Does nothing when the webview is
+    /// already showing `url`, unless its last load failed. Fails when `url` is invalid.
+    pub fn navigate(&mut self, url: &str) -> Result<()> {
+        let url = self.start_navigation(url, |this, url| {
+            let url = url.to_string();
+            this.load(move |webview| webview.load_uri(&url));
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!("WebView navigated to: {}", url);
+        }
+        Ok(())
+    }
+
+    /// Starts a main-frame load with `load` on the GTK thread, counting it so that the events of
+    /// the loads it replaces can be told apart.
+    fn load(&self, load: impl FnOnce(&WebView) + Send + 'static) {
+        let requested = {
+            let mut state = self.state.borrow_mut();
+            state.requested_load += 1;
+            state.requested_load
+        };
+        let requested_load = self.requested_load.clone();
+        self.run_on_gtk_thread(move |_, webview| {
+            requested_load.store(requested, Ordering::Relaxed);
+            load(webview);
+        });
+    }
+
+    /// Makes `sender` resolve when the next main-frame load ends.
+    pub(super) fn await_load(&self, sender: oneshot::Sender<Result<()>>, url: &str) {
+        let mut state = self.state.borrow_mut();
+        state.pending_navigation = Some(sender);
+        url.clone_into(&mut state.pending_navigation_url);
+    }
+
+    /// Navigates like [`Self::navigate`], sending `headers` with the request, e.g. for
+    /// `Authorization`. Headers only apply to this request, not to subresources or later
+    /// navigations.
+    pub fn navigate_with_headers(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<()> {
+        let url = self.start_navigation(url, |this, url| {
+            let request_url = url.to_string();
+            let headers: Vec<(String, String)> = headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            this.load(move |webview| {
+                let request = URIRequest::new(&request_url);
+                if let Some(http_headers) = request.http_headers() {
+                    for (name, value) in &headers {
+                        http_headers.append(name, value);
+                    }
+                }
+                webview.load_request(&request);
+            });
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!(
+                "WebView navigated to: {} with {} headers",
+                url,
+                headers.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Always fails, since WebKitGTK can't attach a body to a request. Render an auto-submitting
+    /// `<form>` with [`Self::load_html`] instead.
+    pub fn load_post(&mut self, url: &str, _body: &[u8], _content_type: &str) -> Result<()> {
+        Err(anyhow!(
+            "WebView POST requests are not supported on Linux: {url}"
+        ))
+    }
+
+    /// Loads the local file at `path`. WebKitGTK lets file pages read other local files, so no
+    /// extra access needs granting. Like [`Self::navigate`], does nothing when the file is already
+    /// shown. Fails when `path` doesn't exist or isn't a file.
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let (_, url) = file_url(path)?;
+        let url = self.start_navigation(&url, |this, url| {
+            let url = url.to_string();
+            this.load(move |webview| webview.load_uri(&url));
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!("WebView loaded file: {}", url);
+        }
+        Ok(())
+    }
+
+    /// Renders `html` directly. Relative references in the document resolve against `base_url`.
+    pub fn load_html(&mut self, html: &str, base_url: Option<&str>) {
+        self.current_url = base_url.unwrap_or("about:blank").to_string();
+
+        webview_info!("WebView loaded HTML string ({} bytes)", html.len());
+        let html = html.to_string();
+        let base_url = base_url.map(str::to_string);
+        self.run_on_gtk_thread(move |_, webview| webview.load_html(&html, base_url.as_deref()));
+    }
+
+    pub fn reload(&self) {
+        webview_info!("WebView reloaded: {}", self.current_url);
+        self.run_on_gtk_thread(|_, webview| webview.reload());
+    }
+
+    /// Reloads the current page, bypassing the HTTP cache.
+    pub fn reload_ignoring_cache(&self) {
+        webview_info!("WebView reloaded from origin: {}", self.current_url);
+        self.run_on_gtk_thread(|_, webview| webview.reload_bypass_cache());
+    }
+
+    /// Cancels any in-flight load. Safe to call when nothing is loading.
+    pub fn stop_loading(&self) {
+        self.run_on_gtk_thread(|_, webview| webview.stop_loading());
+    }
+
+    pub fn go_back(&self) {
+        self.run_on_gtk_thread(|_, webview| webview.go_back());
+    }
+
+    pub fn go_forward(&self) {
+        self.run_on_gtk_thread(|_, webview| webview.go_forward());
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.query_gtk_thread(|_, webview| webview.can_go_back())
+            .unwrap_or(false)
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.query_gtk_thread(|_, webview| webview.can_go_forward())
+            .unwrap_or(false)
+    }
+
+    /// Moves and resizes the window. `bounds` is in gpui's logical screen coordinates.
+    pub fn set_bounds(&self, bounds: Bounds<Pixels>) {
+        self.remember_bounds_if_hidden(bounds);
+        let x = f32::from(bounds.origin.x) as i32;
+        let y = f32::from(bounds.origin.y) as i32;
+        let width = f32::from(bounds.size.width) as i32;
+        let height = f32::from(bounds.size.height) as i32;
+        self.run_on_gtk_thread(move |window, _| {
+            window.move_(x, y);
+            window.resize(width, height);
+        });
+    }
+
+    /// Returns the window frame in gpui's logical screen coordinates.
+    pub fn get_bounds(&self) -> Bounds<Pixels> {
+        self.query_gtk_thread(|window, _| {
+            let (x, y) = window.position();
+            let (width, height) = window.size();
+            Bounds {
+                origin: point(px(x as f32), px(y as f32)),
+                size: size(px(width as f32), px(height as f32)),
+            }
+        })
+        .unwrap_or_default()
+    }
+
+    /// The work area of the window's monitor, excluding panels.
+    pub(super) fn work_area(&self) -> Option<Bounds<Pixels>> {
+        self.query_gtk_thread(|window, _| {
+            let gdk_window = window.window()?;
+            let area = gdk_window
+                .display()
+                .monitor_at_window(&gdk_window)?
+                .workarea();
+            Some(Bounds {
+                origin: point(px(area.x() as f32), px(area.y() as f32)),
+                size: size(px(area.width() as f32), px(area.height() as f32)),
+            })
+        })
+        .flatten()
+    }
+
+    pub fn on_bounds_changed(&mut self, _handler: impl FnMut(Bounds<Pixels>) + 'static) {}
+
+    /// Always passes an error to `callback`, once the first load has ended.
+    pub fn evaluate_javascript(
+        &self,
+        script: &str,
+        callback: impl FnOnce(Result<String>) + 'static,
+    ) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::Evaluate(
+                script.to_string(),
+                Some(Box::new(callback)),
+            ));
+            return;
+        }
+        callback(Err(evaluation_unsupported()));
+    }
+
+    pub fn evaluate_javascript_fire_and_forget(&self, script: &str) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::Evaluate(script.to_string(), None));
+            return;
+        }
+        let script = script.to_string();
+        self.run_on_gtk_thread(move |_, webview| run_javascript(webview, &script));
+    }
+
+    pub fn on_did_finish_navigation(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    pub fn on_navigation_failed(&mut self, _handler: impl FnMut(String, i64) + 'static) {}
+
+    /// Crashed pages are always reloaded once on Linux.
+    pub fn on_crash(&mut self, _handler: impl FnMut() + 'static) {}
+
+    pub fn on_progress(&mut self, _handler: impl FnMut(f64) + 'static) {}
+
+    /// Consults `policy` with the URL of every navigation before it starts, including those
+    /// triggered by the page itself. WebKitGTK waits while the GTK thread hands each URL over to
+    /// this thread and back.
+    pub fn set_navigation_policy(
+        &mut self,
+        policy: impl FnMut(&str) -> NavigationDecision + 'static,
+    ) {
+        self.state.borrow_mut().navigation_policy = Some(Box::new(policy));
+        self.has_navigation_policy.store(true, Ordering::Relaxed);
+    }
+
+    pub fn on_download(&mut self, _handler: impl FnMut(String) -> Option<PathBuf> + 'static) {}
+
+    pub fn on_download_finished(&mut self, _handler: impl FnMut(Result<PathBuf>) + 'static) {}
+
+    pub fn on_close(&mut self, _handler: impl FnOnce() + 'static) {}
+
+    /// New-window requests always load in this webview on Linux.
+    pub fn on_new_window(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    /// WebKitGTK denies permission requests that aren't handled.
+    pub fn on_permission_request(
+        &mut self,
+        _handler: impl FnMut(PermissionKind, String) -> bool + 'static,
+    ) {
+    }
+
+    /// Certificate errors fail the load and other challenges use WebKitGTK's default handling on
+    /// Linux.
+    pub fn on_auth_challenge(
+        &mut self,
+        _handler: impl FnMut(AuthChallenge) -> AuthResponse + 'static,
+    ) {
+    }
+
+    /// Basic auth challenges use WebKitGTK's built-in sign-in dialog on Linux.
+    pub fn set_basic_auth(
+        &mut self,
+        _host: &str,
+        _port: Option<u16>,
+        _username: &str,
+        _password: &str,
+    ) {
+    }
+
+    pub fn clear_basic_auth(&mut self) {}
+
+    /// WebKitGTK's context menu is always shown on Linux.
+    pub fn on_context_menu(
+        &mut self,
+        _handler: impl FnMut(ContextMenuContext) -> Vec<MenuItem> + 'static,
+    ) {
+    }
+
+    /// Every shortcut reaches the webview first on Linux.
+    pub fn set_key_passthrough(&self, _keys: &[&str]) {}
+
+    /// WebKitGTK doesn't close the window on Ctrl-W, so there's nothing to configure on Linux.
+    pub fn set_close_on_cmd_w(&self, _close: bool) {}
+
+    /// JavaScript dialogs use WebKitGTK's built-in dialogs on Linux.
+    pub fn on_js_alert(&mut self, _handler: impl FnMut(String) + 'static) {}
+
+    pub fn on_js_confirm(&mut self, _handler: impl FnMut(String) -> bool + 'static) {}
+
+    pub fn on_js_prompt(
+        &mut self,
+        _handler: impl FnMut(String, String) -> Option<String> + 'static,
+    ) {
+    }
+
+    /// Registers a handler called with the document title whenever it changes.
+    pub fn on_title_changed(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().title_changed = Some(Box::new(handler));
+    }
+
+    pub fn estimated_progress(&self) -> f64 {
+        self.query_gtk_thread(|_, webview| webview.estimated_load_progress())
+            .unwrap_or(0.0)
+    }
+
+    /// Enables the Web Inspector, reachable through the "Inspect Element" context menu entry.
+    pub fn set_inspectable(&self, inspectable: bool) {
+        self.run_on_gtk_thread(move |_, webview| {
+            if let Some(settings) = WebViewExt::settings(webview) {
+                settings.set_enable_developer_extras(inspectable);
+            }
+        });
+    }
+
+    /// Highlights matches of `query` in the page, then invokes `callback` with whether there were
+    /// any.
+    pub fn find(&self, query: &str, forward: bool, callback: impl FnOnce(bool) + 'static) {
+        let query = query.to_string();
+        self.reply_from_gtk_thread(
+            move |webview, tx| {
+                let Some(find_controller) = webview.find_controller() else {
+                    return;
+                };
+                let reply = Rc::new(RefCell::new(Some((tx, Vec::new()))));
+                let found_text = find_controller.connect_found_text({
+                    let reply = reply.clone();
+                    move |find_controller, _| finish_find(&reply, find_controller, true)
+                });
+                let failed_to_find_text = find_controller.connect_failed_to_find_text({
+                    let reply = reply.clone();
+                    move |find_controller| finish_find(&reply, find_controller, false)
+                });
+                if let Some((_, handlers)) = reply.borrow_mut().as_mut() {
+                    handlers.extend([found_text, failed_to_find_text]);
+                }
+                let mut options = FindOptions::CASE_INSENSITIVE | FindOptions::WRAP_AROUND;
+                if !forward {
+                    options |= FindOptions::BACKWARDS;
+                }
+                find_controller.search(&query, options.bits(), u32::MAX);
+            },
+            |found| callback(found.unwrap_or(false)),
+        );
+    }
+
+    pub fn clear_find(&self) {
+        self.run_on_gtk_thread(|_, webview| {
+            if let Some(find_controller) = webview.find_controller() {
+                find_controller.search_finish();
+            }
+        });
+    }
+
+    pub fn print_to_pdf(&self, _path: &Path, callback: impl FnOnce(Result<()>) + 'static) {
+        callback(Err(anyhow!("Printing to PDF is not supported on Linux")));
+    }
+
+    pub fn set_content_rules(
+        &self,
+        _json_rules: &str,
+        callback: impl FnOnce(Result<()>) + 'static,
+    ) {
+        callback(Err(anyhow!("Content rules are not supported on Linux")));
+    }
+
+    pub fn take_snapshot(&self, callback: impl FnOnce(Result<Vec<u8>>) + 'static) {
+        callback(Err(anyhow!("Snapshots are not supported on Linux")));
+    }
+
+    pub fn snapshot_element(
+        &self,
+        _selector: &str,
+        callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+    ) {
+        self.take_snapshot(callback);
+    }
+
+    pub(super) fn data_store_directories(&self) -> Vec<PathBuf> {
+        self.data_store_directories.clone()
+    }
+
+    /// Removes all cookies, caches, and storage from this webview's data manager, then invokes
+    /// `callback` once WebKit has finished clearing.
+    pub fn clear_website_data(&self, callback: impl FnOnce() + 'static) {
+        self.clear_website_data_of_types(WebsiteDataTypes::ALL, callback);
+    }
+
+    /// Removes only cookies from this webview's data manager, then invokes `callback` once WebKit
+    /// has finished clearing.
+    pub fn clear_cookies_only(&self, callback: impl FnOnce() + 'static) {
+        self.clear_website_data_of_types(WebsiteDataTypes::COOKIES, callback);
+    }
+
+    fn clear_website_data_of_types(
+        &self,
+        types: WebsiteDataTypes,
+        callback: impl FnOnce() + 'static,
+    ) {
+        self.reply_from_gtk_thread(
+            move |webview, tx| {
+                if let Some(data_manager) = webview.website_data_manager() {
+                    // A zero timespan clears data regardless of when it was modified.
+                    data_manager.clear(
+                        types,
+                        glib::TimeSpan(0),
+                        None::<&gtk::gio::Cancellable>,
+                        move |result| {
+                            if let Err(error) = result {
+                                log::error!("Failed to clear website data: {error}");
+                            }
+                            tx.send(()).ok();
+                        },
+                    );
+                }
+            },
+            |_| callback(),
+        );
+    }
+
+    /// Cookie access isn't supported on Linux yet, so this only invokes `callback`.
+    pub fn set_cookie(&self, cookie: Cookie, callback: impl FnOnce() + 'static) {
+        log::warn!("Setting cookie {} is not supported on Linux", cookie.name);
+        callback();
+    }
+
+    /// Cookie access isn't supported on Linux yet, so `callback` always receives no cookies.
+    pub fn get_cookies(&self, _url: &str, callback: impl FnOnce(Vec<Cookie>) + 'static) {
+        callback(Vec::new());
+    }
+
+    /// Lets page elements such as video players use the Fullscreen API, which fullscreens the
+    /// window until they exit. Enabled by default on Linux.
+    pub fn set_allow_element_fullscreen(&self, allow: bool) {
+        self.run_on_gtk_thread(move |_, webview| {
+            if let Some(settings) = WebViewExt::settings(webview) {
+                settings.set_enable_fullscreen(allow);
+            }
+        });
+    }
+
+    /// Applies to pages loaded afterwards, and also stops [`Self::evaluate_javascript`].
+    pub fn set_javascript_enabled(&self, enabled: bool) {
+        self.run_on_gtk_thread(move |_, webview| {
+            if let Some(settings) = WebViewExt::settings(webview) {
+                settings.set_enable_javascript(enabled);
+            }
+        });
+    }
+
+    pub fn set_load_images(&self, load: bool) {
+        self.run_on_gtk_thread(move |_, webview| {
+            if let Some(settings) = WebViewExt::settings(webview) {
+                settings.set_auto_load_images(load);
+            }
+        });
+    }
+
+    /// WebKitGTK has no fraudulent website warnings, so this is a no-op on Linux.
+    pub fn set_fraudulent_website_warning_enabled(&self, _enabled: bool) {}
+
+    /// Dropped files get WebKitGTK's default handling on Linux.
+    pub fn set_accepts_file_drops(&self, _accept: bool) {}
+
+    /// Scales the page content. `factor` is clamped to 0.25..=5.0, where 1.0 is the default.
+    pub fn set_zoom(&self, factor: f64) {
+        let factor = clamp_zoom(factor);
+        self.run_on_gtk_thread(move |_, webview| webview.set_zoom_level(factor));
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.query_gtk_thread(|_, webview| webview.zoom_level())
+            .unwrap_or(1.0)
+    }
+
+    /// Overrides the user agent sent with requests. An empty string restores WebKit's default.
+    pub fn set_user_agent(&self, user_agent: &str) {
+        let user_agent = Some(user_agent.to_string()).filter(|user_agent| !user_agent.is_empty());
+        self.run_on_gtk_thread(move |_, webview| {
+            if let Some(settings) = WebViewExt::settings(webview) {
+                settings.set_user_agent(user_agent.as_deref());
+            }
+        });
+    }
+
+    /// Injects `source` into the main frame of every page loaded from now on, either before any
+    /// page script runs or once the document has finished parsing. Before the first load ends, the
+    /// script is queued and also runs once in the page that loaded; see [`Self::has_loaded`].
+    pub fn add_user_script(&self, source: &str, at_document_start: bool) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::UserScript {
+                source: source.to_string(),
+                at_document_start,
+            });
+            return;
+        }
+        let source = source.to_string();
+        self.run_on_gtk_thread(move |_, webview| {
+            install_user_script(webview, &source, at_document_start)
+        });
+    }
+
+    pub fn register_message_handler(
+        &mut self,
+        _name: &str,
+        _handler: impl FnMut(String) + 'static,
+    ) {
+    }
+
+    /// Sets the opacity of the whole window, from 0.0 (invisible) to 1.0 (opaque). Requires a
+    /// compositing window manager.
+    pub fn set_opacity(&self, alpha: f64) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        self.run_on_gtk_thread(move |window, _| window.set_opacity(alpha));
+    }
+
+    /// Lets the window background show through wherever the page doesn't paint a background.
+    pub fn set_background_transparent(&self, transparent: bool) {
+        self.run_on_gtk_thread(move |window, webview| {
+            let alpha = if transparent { 0.0 } else { 1.0 };
+            webview.set_background_color(&gtk::gdk::RGBA::new(1.0, 1.0, 1.0, alpha));
+            window.set_app_paintable(transparent);
+        });
+    }
+
+    /// GTK only offers keeping a window above others, so every level above `Normal` behaves the
+    /// same.
+    pub fn set_window_level(&self, level: WindowLevel) {
+        self.run_on_gtk_thread(move |window, _| {
+            window.set_keep_above(level != WindowLevel::Normal)
+        });
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.window_title = title.to_string();
+        let title = title.to_string();
+        self.run_on_gtk_thread(move |window, _| window.set_title(&title));
+    }
+
+    /// Window titles are always shown by the window manager on Linux.
+    pub fn set_title_visible(&self, _visible: bool) {}
+
+    /// The shadow is drawn by the window manager on Linux.
+    pub fn set_has_shadow(&self, _shadow: bool) {}
+
+    /// WebKitGTK follows the GTK theme, which can't be overridden per window.
+    pub fn set_appearance(&self, appearance: Appearance) {
+        if appearance != Appearance::System {
+            log::warn!("WebView appearance {appearance:?} is not supported on Linux");
+        }
+    }
+
+    /// Keeps the user from resizing the window below `size`.
+    pub fn set_min_size(&self, size: Size<Pixels>) {
+        self.min_size.set(Some(size));
+        self.apply_geometry_hints();
+    }
+
+    /// Keeps the user from resizing the window above `size`.
+    pub fn set_max_size(&self, size: Size<Pixels>) {
+        self.max_size.set(Some(size));
+        self.apply_geometry_hints();
+    }
+
+    /// Keeps the window at `ratio` (width, height) while the user resizes it, e.g. for video.
+    /// `None` lets it resize freely again.
+    pub fn set_aspect_ratio(&self, ratio: Option<(f64, f64)>) {
+        self.aspect_ratio.set(
+            ratio
+                .filter(|(width, height)| *width > 0.0 && *height > 0.0)
+                .map(|(width, height)| width / height),
+        );
+        self.apply_geometry_hints();
+    }
+
+    /// The webview always fills its window on Linux.
+    pub fn set_viewport_size(&self, _size: Size<Pixels>) {
+        log::warn!("WebView viewport sizes are not supported on Linux");
+    }
+
+    pub fn clear_viewport_size(&self) {}
+
+    /// The webview always fills its window on Linux.
+    pub fn set_chrome_height(&self, height: Pixels) {
+        if height > px(0.0) {
+            log::warn!("WebView chrome strips are not supported on Linux");
+        }
+    }
+
+    /// The webview always fills its window on Linux.
+    pub fn set_content_insets(&self, insets: Edges<Pixels>) {
+        if insets != Edges::default() {
+            log::warn!("WebView content insets are not supported on Linux");
+        }
+    }
+
+    /// Geometry hints replace each other, so the size limits and aspect ratio are always applied
+    /// together.
+    fn apply_geometry_hints(&self) {
+        let to_gtk =
+            |size: Size<Pixels>| (f32::from(size.width) as i32, f32::from(size.height) as i32);
+        let min_size = self.min_size.get().map(to_gtk);
+        let max_size = self.max_size.get().map(to_gtk);
+        let aspect_ratio = self.aspect_ratio.get();
+        self.run_on_gtk_thread(move |window, _| {
+            let mut hints = gtk::gdk::WindowHints::empty();
+            if min_size.is_some() {
+                hints |= gtk::gdk::WindowHints::MIN_SIZE;
+            }
+            if max_size.is_some() {
+                hints |= gtk::gdk::WindowHints::MAX_SIZE;
+            }
+            if aspect_ratio.is_some() {
+                hints |= gtk::gdk::WindowHints::ASPECT;
+            }
+            let (min_width, min_height) = min_size.unwrap_or_default();
+            let (max_width, max_height) = max_size.unwrap_or_default();
+            let aspect_ratio = aspect_ratio.unwrap_or_default();
+            let geometry = gtk::gdk::Geometry::new(
+                min_width,
+                min_height,
+                max_width,
+                max_height,
+                0,
+                0,
+                0,
+                0,
+                aspect_ratio,
+                aspect_ratio,
+                gtk::gdk::Gravity::NorthWest,
+            );
+            window.set_geometry_hints(None::<&gtk::Widget>, Some(&geometry), hints);
+        });
+    }
+
+    /// Whether the user can resize the window. A non-resizable window keeps its current size.
+    pub fn set_resizable(&self, resizable: bool) {
+        self.run_on_gtk_thread(move |window, _| {
+            if resizable {
+                window.set_size_request(-1, -1);
+            } else {
+                // Otherwise GTK shrinks the window to the webview's natural size.
+                let (width, height) = window.size();
+                window.set_size_request(width, height);
+            }
+            window.set_resizable(resizable);
+        });
+    }
+
+    /// Hiding keeps the page loaded, preserving its scroll position and session, so a hidden
+    /// manager can be shown again instead of recreated.
+    pub fn set_hidden(&self, hidden: bool) {
+        self.run_on_gtk_thread(move |window, _| {
+            if hidden {
+                window.hide();
+            } else {
+                window.set_focus_on_map(true);
+                window.show_all();
+            }
+        });
+        if !hidden {
+            self.apply_pending_bounds();
+        }
+    }
+
+    /// Asks the window manager to show the window without focusing it. Whether it complies is up
+    /// to the window manager.
+    pub fn show_without_activating(&self) {
+        self.run_on_gtk_thread(|window, _| {
+            window.set_focus_on_map(false);
+            window.show_all();
+        });
+        self.apply_pending_bounds();
+    }
+
+    pub fn focus(&self) {
+        self.run_on_gtk_thread(|window, webview| {
+            window.present();
+            webview.grab_focus();
+        });
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.query_gtk_thread(|window, webview| window.is_active() && webview.has_focus())
+            .unwrap_or(false)
+    }
+
+    /// Clears the focus within the window. The window manager decides which window is activated
+    /// next.
+    pub fn blur(&self) {
+        self.run_on_gtk_thread(|window, _| window.set_focus(None::<&gtk::Widget>));
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.query_gtk_thread(|_, webview| webview.is_loading())
+            .unwrap_or(false)
+    }
+
+    /// WebKitGTK has no API to suspend a page, so this only tells the page it is hidden, letting
+    /// well-behaved pages stop their own timers and animations. Pages that ignore visibility keep
+    /// running.
+    pub fn set_suspended(&self, suspended: bool) {
+        self.evaluate_javascript_fire_and_forget(&page_visibility_script(suspended));
+    }
+
+    pub(super) fn remember_context_menu_disabled(&self, disabled: bool) {
+        self.context_menu_disabled
+            .store(disabled, Ordering::Relaxed);
+    }
+
+    /// Mutes the current page's media elements. The page loaded by the next navigation plays
+    /// sound again, since the enabled WebKitGTK API version has no native mute.
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.set(muted);
+        self.evaluate_javascript_fire_and_forget(&mute_script(muted));
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.get()
+    }
+
+    pub fn minimize(&self) {
+        self.run_on_gtk_thread(|window, _| window.iconify());
+    }
+
+    /// Restores a minimized window and brings it to the front, showing it if it was hidden.
+    pub fn restore(&self) {
+        self.run_on_gtk_thread(|window, _| {
+            window.deiconify();
+            window.show_all();
+            window.present();
+        });
+        self.apply_pending_bounds();
+    }
+
+    /// Toggles between maximized and restored, like the titlebar's maximize button.
+    pub fn toggle_zoom(&self) {
+        self.run_on_gtk_thread(|window, _| {
+            if window.is_maximized() {
+                window.unmaximize();
+            } else {
+                window.maximize();
+            }
+        });
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.query_gtk_thread(|window, _| window.is_visible())
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for WebViewManager {
+    fn drop(&mut self) {
+        webview_info!("Cleaning up floating webview window");
+        let id = self.id;
+        glib::MainContext::default().invoke(move || {
+            if let Some((window, _)) = WEBVIEWS.with_borrow_mut(|webviews| webviews.remove(&id)) {
+                // SAFETY: the window was removed from the registry, so nothing else refers to it.
+                unsafe { window.destroy() };
+            }
+        });
+    }
+}
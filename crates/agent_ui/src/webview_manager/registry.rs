@@ -0,0 +1,163 @@
+use super::{WebViewBackend, WebViewManager, WebViewManagerBuilder};
+use anyhow::{Context as _, Result};
+use collections::HashMap;
+use gpui::App;
+
+/// Identifies a webview owned by a [`WebViewRegistry`]. Ids are never reused within a registry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WebViewId(u64);
+
+/// Owns every webview opened by a panel, so that tearing the panel down closes all of them.
+pub struct WebViewRegistry<B: WebViewBackend = WebViewManager> {
+    webviews: HashMap<WebViewId, B>,
+    next_id: u64,
+    max_open: Option<usize>,
+}
+
+impl<B: WebViewBackend> Default for WebViewRegistry<B> {
+    fn default() -> Self {
+        Self {
+            webviews: HashMap::default(),
+            next_id: 0,
+            max_open: None,
+        }
+    }
+}
+
+impl WebViewRegistry {
+    pub fn create(
+        &mut self,
+        builder: WebViewManagerBuilder,
+        parent_window_ptr: *mut std::ffi::c_void,
+        cx: &App,
+    ) -> Result<WebViewId> {
+        self.ensure_capacity()?;
+        let manager = builder.build(parent_window_ptr, cx)?;
+        self.insert(manager)
+    }
+}
+
+impl<B: WebViewBackend> WebViewRegistry<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes [`Self::create`] and [`Self::insert`] fail while `max_open` webviews are open.
+    pub fn with_max_open(max_open: usize) -> Self {
+        Self {
+            max_open: Some(max_open),
+            ..Self::default()
+        }
+    }
+
+    /// Takes ownership of an already created webview.
+    pub fn insert(&mut self, webview: B) -> Result<WebViewId> {
+        self.ensure_capacity()?;
+        let id = WebViewId(self.next_id);
+        self.next_id += 1;
+        self.webviews.insert(id, webview);
+        Ok(id)
+    }
+
+    fn ensure_capacity(&self) -> Result<()> {
+        if let Some(max_open) = self.max_open {
+            anyhow::ensure!(
+                self.webviews.len() < max_open,
+                "cannot open more than {max_open} webviews"
+            );
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: WebViewId) -> Option<&B> {
+        self.webviews.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: WebViewId) -> Option<&mut B> {
+        self.webviews.get_mut(&id)
+    }
+
+    pub fn navigate(&mut self, id: WebViewId, url: &str) -> Result<()> {
+        self.get_mut(id)
+            .with_context(|| format!("no open webview with id {id:?}"))?
+            .navigate(url)
+    }
+
+    /// Closes the webview's window and frees it. Returns whether `id` was open.
+    pub fn close(&mut self, id: WebViewId) -> bool {
+        self.webviews.remove(&id).is_some()
+    }
+
+    pub fn close_all(&mut self) {
+        self.webviews.clear();
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = WebViewId> + '_ {
+        self.webviews.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.webviews.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webviews.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webview_manager::MockWebViewBackend;
+
+    #[test]
+    fn test_registry_lifecycle() {
+        let mut registry = WebViewRegistry::<MockWebViewBackend>::with_max_open(2);
+        let first = registry
+            .insert(MockWebViewBackend::new("about:blank").unwrap())
+            .unwrap();
+        let second = registry
+            .insert(MockWebViewBackend::new("about:blank").unwrap())
+            .unwrap();
+        assert_ne!(first, second);
+        assert!(
+            registry
+                .insert(MockWebViewBackend::new("about:blank").unwrap())
+                .is_err()
+        );
+
+        registry.navigate(first, "zed.dev").unwrap();
+        assert_eq!(
+            registry.get_mut(first).map(|webview| webview.current_url()),
+            Some("https://zed.dev/")
+        );
+        assert_eq!(
+            registry
+                .get_mut(second)
+                .map(|webview| webview.current_url()),
+            Some("about:blank")
+        );
+
+        assert!(registry.close(first));
+        assert!(!registry.close(first));
+        assert!(registry.navigate(first, "zed.dev").is_err());
+
+        let third = registry
+            .insert(MockWebViewBackend::new("about:blank").unwrap())
+            .unwrap();
+        assert_ne!(third, first);
+        registry.close_all();
+        assert!(registry.is_empty());
+    }
+
+    #[gpui::test]
+    fn test_create_respects_max_open(cx: &mut gpui::TestAppContext) {
+        let mut registry: WebViewRegistry = WebViewRegistry::with_max_open(0);
+        assert!(cx.update(|cx| {
+            registry
+                .create(WebViewManager::builder(), std::ptr::null_mut(), cx)
+                .is_err()
+        }));
+        assert!(registry.is_empty());
+    }
+}
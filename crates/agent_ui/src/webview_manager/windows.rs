@@ -0,0 +1,2357 @@
+use super::{
+    Appearance, AuthChallenge, AuthMethod, AuthResponse, AutoplayPolicy, CLEAR_FIND_SCRIPT,
+    CachePolicy, ContextMenuContext, Cookie, DeferredScript, MenuItem, NavigationDecision,
+    NavigationHistory, PendingBounds, PermissionKind, ScreenSelector, TitlebarStyle,
+    WebViewManager, WebViewManagerBuilder, WebViewState, WindowLevel, clamp_zoom,
+    context_menu_flag_script, crop_png, element_rect_script, file_url, find_script,
+    find_script_matched, from_unix_seconds, normalize_url, parse_element_rect, unix_seconds,
+};
+use anyhow::{Context as _, Result, anyhow};
+use gpui::{App, Bounds, Edges, Pixels, Size, point, px, size};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Once,
+    sync::mpsc,
+};
+use util::ResultExt as _;
+use webview2_com::{
+    AddScriptToExecuteOnDocumentCreatedCompletedHandler, BasicAuthenticationRequestedEventHandler,
+    CallDevToolsProtocolMethodCompletedHandler, CapturePreviewCompletedHandler,
+    ClearBrowsingDataCompletedHandler, CoreWebView2EnvironmentOptions,
+    CreateCoreWebView2ControllerCompletedHandler, CreateCoreWebView2EnvironmentCompletedHandler,
+    DocumentTitleChangedEventHandler, DownloadStartingEventHandler, ExecuteScriptCompletedHandler,
+    GetCookiesCompletedHandler, Microsoft::Web::WebView2::Win32::*,
+    NavigationCompletedEventHandler, NavigationStartingEventHandler,
+    NewWindowRequestedEventHandler, PermissionRequestedEventHandler, PrintToPdfCompletedHandler,
+    ProcessFailedEventHandler, ScriptDialogOpeningEventHandler,
+    ServerCertificateErrorDetectedEventHandler, StateChangedEventHandler,
+    TrySuspendCompletedHandler, WebMessageReceivedEventHandler,
+};
+use windows::{
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::{
+            GetMonitorInfoW, HMONITOR, MONITOR_DEFAULTTONEAREST, MONITOR_DEFAULTTOPRIMARY,
+            MONITORINFO, MonitorFromPoint, MonitorFromWindow,
+        },
+        System::{
+            Com::{IStream, STREAM_SEEK_SET},
+            LibraryLoader::GetModuleHandleW,
+        },
+        UI::{
+            HiDpi::{AdjustWindowRectExForDpi, GetDpiForWindow},
+            Shell::{SHCreateMemStream, ShellExecuteW},
+            WindowsAndMessaging::*,
+        },
+    },
+    core::{BOOL, HSTRING, Interface as _, PCWSTR, PWSTR, w},
+};
+
+const WINDOW_CLASS_NAME: PCWSTR = w!("Zed::WebView");
+
+fn register_window_class() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| unsafe {
+        let instance = GetModuleHandleW(None).log_err().unwrap_or_default();
+        let window_class = WNDCLASSW {
+            lpfnWndProc: Some(window_procedure),
+            hInstance: instance.into(),
+            lpszClassName: WINDOW_CLASS_NAME,
+            ..Default::default()
+        };
+        if RegisterClassW(&window_class) == 0 {
+            log::error!("Failed to register webview window class");
+        }
+    });
+}
+
+/// Owned by the window through its `GWLP_USERDATA`, so the window procedure can reach the
+/// webview.
+struct WindowData {
+    controller: ICoreWebView2Controller,
+    state: Rc<RefCell<WebViewState>>,
+    /// Set while the user drags or resizes the window, so bounds are reported once it ends.
+    in_size_move: Cell<bool>,
+    /// Content size limits enforced while the user resizes the window.
+    min_size: Cell<Option<Size<Pixels>>>,
+    max_size: Cell<Option<Size<Pixels>>>,
+    /// Content width divided by height, kept while the user resizes the window.
+    aspect_ratio: Cell<Option<f64>>,
+    /// Set by `set_viewport_size` to keep the webview at that size instead of filling the window.
+    viewport_size: Cell<Option<Size<Pixels>>>,
+    /// Set by `set_content_insets` to keep the webview away from the client area's edges.
+    content_insets: Cell<Edges<Pixels>>,
+    /// Set by `set_chrome_height` to reserve a strip above the webview.
+    chrome_height: Cell<Pixels>,
+}
+
+unsafe extern "system" fn window_procedure(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    unsafe {
+        match message {
+            WM_SIZE => {
+                let window_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowData;
+                if let Some(window_data) = window_data.as_ref() {
+                    if let Some(rect) = webview_rect(hwnd, window_data).log_err() {
+                        window_data.controller.SetBounds(rect).log_err();
+                    }
+                    if !window_data.in_size_move.get() && wparam.0 != SIZE_MINIMIZED as usize {
+                        WebViewState::report_bounds(&window_data.state, window_bounds(hwnd));
+                    }
+                }
+                LRESULT(0)
+            }
+            WM_MOVE => {
+                let window_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowData;
+                if let Some(window_data) = window_data.as_ref()
+                    && !window_data.in_size_move.get()
+                {
+                    WebViewState::report_bounds(&window_data.state, window_bounds(hwnd));
+                }
+                LRESULT(0)
+            }
+            WM_GETMINMAXINFO => {
+                let window_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowData;
+                let info = lparam.0 as *mut MINMAXINFO;
+                if let Some(window_data) = window_data.as_ref()
+                    && let Some(info) = info.as_mut()
+                {
+                    if let Some(size) = window_data.min_size.get() {
+                        info.ptMinTrackSize = window_size_for_content(hwnd, size);
+                    }
+                    if let Some(size) = window_data.max_size.get() {
+                        info.ptMaxTrackSize = window_size_for_content(hwnd, size);
+                    }
+                }
+                LRESULT(0)
+            }
+            WM_SIZING => {
+                let window_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowData;
+                let rect = lparam.0 as *mut RECT;
+                if let Some(window_data) = window_data.as_ref()
+                    && let Some(aspect_ratio) = window_data.aspect_ratio.get()
+                    && let Some(rect) = rect.as_mut()
+                {
+                    keep_aspect_ratio(hwnd, rect, wparam.0 as u32, aspect_ratio);
+                    return LRESULT(1);
+                }
+                DefWindowProcW(hwnd, message, wparam, lparam)
+            }
+            WM_ENTERSIZEMOVE | WM_EXITSIZEMOVE => {
+                let window_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowData;
+                if let Some(window_data) = window_data.as_ref() {
+                    let entering = message == WM_ENTERSIZEMOVE;
+                    window_data.in_size_move.set(entering);
+                    if !entering {
+                        WebViewState::report_bounds(&window_data.state, window_bounds(hwnd));
+                    }
+                }
+                LRESULT(0)
+            }
+            // Closing only hides the window, matching `setReleasedWhenClosed:NO` on macOS: the
+            // manager owns the window and destroys it on drop.
+            WM_CLOSE => {
+                let _ = ShowWindow(hwnd, SW_HIDE);
+                let window_data = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowData;
+                if let Some(window_data) = window_data.as_ref() {
+                    WebViewState::notify_closed(&window_data.state);
+                }
+                LRESULT(0)
+            }
+            // `Drop` takes the data back first, so this only frees it when `init` fails.
+            WM_NCDESTROY => {
+                let window_data = SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0) as *mut WindowData;
+                if !window_data.is_null() {
+                    let window_data = Box::from_raw(window_data);
+                    window_data.controller.Close().log_err();
+                }
+                DefWindowProcW(hwnd, message, wparam, lparam)
+            }
+            _ => DefWindowProcW(hwnd, message, wparam, lparam),
+        }
+    }
+}
+
+fn scale_factor(hwnd: HWND) -> f32 {
+    unsafe { GetDpiForWindow(hwnd) as f32 / 96.0 }
+}
+
+/// Returns the window frame in gpui's logical screen coordinates.
+fn window_bounds(hwnd: HWND) -> Bounds<Pixels> {
+    let mut rect = RECT::default();
+    unsafe {
+        GetWindowRect(hwnd, &mut rect).log_err();
+    }
+    logical_bounds(rect, scale_factor(hwnd))
+}
+
+/// The webview's bounds within the window's client area: the viewport size anchored at the
+/// top-left corner of the content insets below the chrome strip, or all the space within them.
+fn webview_rect(hwnd: HWND, window_data: &WindowData) -> Result<RECT> {
+    let scale = scale_factor(hwnd);
+    let physical = |length: Pixels| (f32::from(length) * scale) as i32;
+    let insets = window_data.content_insets.get();
+    let chrome_height = window_data.chrome_height.get();
+    let mut client_rect = RECT::default();
+    unsafe {
+        GetClientRect(hwnd, &mut client_rect)?;
+    }
+    let left = client_rect.left + physical(insets.left);
+    let top = client_rect.top + physical(chrome_height + insets.top);
+    Ok(match window_data.viewport_size.get() {
+        Some(size) => RECT {
+            left,
+            top,
+            right: left + physical(size.width),
+            bottom: top + physical(size.height),
+        },
+        None => RECT {
+            left,
+            top,
+            right: (client_rect.right - physical(insets.right)).max(left),
+            bottom: (client_rect.bottom - physical(insets.bottom)).max(top),
+        },
+    })
+}
+
+/// The size in physical pixels of a window whose client area is `content_size`.
+fn window_size_for_content(hwnd: HWND, content_size: Size<Pixels>) -> POINT {
+    let scale = scale_factor(hwnd);
+    let mut rect = RECT {
+        left: 0,
+        top: 0,
+        right: (f32::from(content_size.width) * scale) as i32,
+        bottom: (f32::from(content_size.height) * scale) as i32,
+    };
+    unsafe {
+        let style = WINDOW_STYLE(GetWindowLongPtrW(hwnd, GWL_STYLE) as u32);
+        let ex_style = WINDOW_EX_STYLE(GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32);
+        AdjustWindowRectExForDpi(&mut rect, style, false, ex_style, GetDpiForWindow(hwnd))
+            .log_err();
+    }
+    POINT {
+        x: rect.right - rect.left,
+        y: rect.bottom - rect.top,
+    }
+}
+
+/// Adjusts the window `rect` being dragged by `edge` so its client area keeps `aspect_ratio`.
+/// Dragging a side changes the other dimension; dragging a corner follows the width.
+fn keep_aspect_ratio(hwnd: HWND, rect: &mut RECT, edge: u32, aspect_ratio: f64) {
+    let frame = window_size_for_content(hwnd, Size::default());
+    let content_width = (rect.right - rect.left - frame.x) as f64;
+    let content_height = (rect.bottom - rect.top - frame.y) as f64;
+    if edge == WMSZ_TOP || edge == WMSZ_BOTTOM {
+        rect.right = rect.left + (content_height * aspect_ratio).round() as i32 + frame.x;
+    } else {
+        let height = (content_width / aspect_ratio).round() as i32 + frame.y;
+        if edge == WMSZ_TOPLEFT || edge == WMSZ_TOPRIGHT {
+            rect.top = rect.bottom - height;
+        } else {
+            rect.bottom = rect.top + height;
+        }
+    }
+}
+
+/// Returns the monitor `selector` picks. `scale` converts a selected point to physical pixels.
+fn select_monitor(selector: ScreenSelector, parent_hwnd: HWND, scale: f32) -> HMONITOR {
+    unsafe {
+        match selector {
+            ScreenSelector::Parent if !parent_hwnd.is_invalid() => {
+                MonitorFromWindow(parent_hwnd, MONITOR_DEFAULTTONEAREST)
+            }
+            ScreenSelector::ContainingPoint(point) => MonitorFromPoint(
+                POINT {
+                    x: (f32::from(point.x) * scale) as i32,
+                    y: (f32::from(point.y) * scale) as i32,
+                },
+                MONITOR_DEFAULTTOPRIMARY,
+            ),
+            ScreenSelector::Parent | ScreenSelector::Main => {
+                MonitorFromPoint(POINT::default(), MONITOR_DEFAULTTOPRIMARY)
+            }
+        }
+    }
+}
+
+/// The monitor's work area in physical pixels, excluding the taskbar.
+fn monitor_work_area(monitor: HMONITOR) -> Option<RECT> {
+    let mut info = MONITORINFO {
+        cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    unsafe { GetMonitorInfoW(monitor, &mut info) }
+        .as_bool()
+        .then_some(info.rcWork)
+}
+
+fn logical_bounds(rect: RECT, scale: f32) -> Bounds<Pixels> {
+    Bounds {
+        origin: point(px(rect.left as f32 / scale), px(rect.top as f32 / scale)),
+        size: size(
+            px((rect.right - rect.left) as f32 / scale),
+            px((rect.bottom - rect.top) as f32 / scale),
+        ),
+    }
+}
+
+/// Unwraps string results from `ExecuteScript`, which are JSON-encoded, so they match the
+/// plain strings returned on macOS.
+fn script_result_to_string(json: String) -> String {
+    match serde_json::from_str::<serde_json::Value>(&json) {
+        Ok(serde_json::Value::String(string)) => string,
+        Ok(serde_json::Value::Null) => String::new(),
+        _ => json,
+    }
+}
+
+fn add_script_on_document_created(webview: &ICoreWebView2, source: &str, at_document_start: bool) {
+    // WebView2 only injects at document creation, so defer end-of-document scripts until the
+    // DOM is ready.
+    let source = if at_document_start {
+        source.to_string()
+    } else {
+        format!("document.addEventListener('DOMContentLoaded', () => {{\n{source}\n}});")
+    };
+    unsafe {
+        webview
+            .AddScriptToExecuteOnDocumentCreated(
+                PCWSTR(HSTRING::from(source).as_ptr()),
+                &AddScriptToExecuteOnDocumentCreatedCompletedHandler::create(Box::new(|_, _| {
+                    Ok(())
+                })),
+            )
+            .log_err();
+    }
+}
+
+/// Runs the scripts queued until the first load ended, in call order. User scripts are only
+/// installed, without running, when `run_user_scripts` is false.
+fn run_deferred_scripts(
+    webview: &ICoreWebView2,
+    scripts: Vec<DeferredScript>,
+    run_user_scripts: bool,
+) {
+    for script in scripts {
+        match script {
+            DeferredScript::Evaluate(script, callback) => {
+                execute_script(webview, &script, callback)
+            }
+            DeferredScript::UserScript {
+                source,
+                at_document_start,
+            } => {
+                add_script_on_document_created(webview, &source, at_document_start);
+                if run_user_scripts {
+                    execute_script(webview, &source, None);
+                }
+            }
+        }
+    }
+}
+
+/// Evaluates `script` in `webview`, passing its result to `callback` if there is one.
+fn execute_script(
+    webview: &ICoreWebView2,
+    script: &str,
+    callback: Option<Box<dyn FnOnce(Result<String>)>>,
+) {
+    let handler = ExecuteScriptCompletedHandler::create(Box::new(move |error_code, result| {
+        if let Some(callback) = callback {
+            callback(
+                error_code
+                    .map(|()| script_result_to_string(result))
+                    .map_err(anyhow::Error::from),
+            );
+        }
+        Ok(())
+    }));
+    unsafe {
+        webview
+            .ExecuteScript(PCWSTR(HSTRING::from(script).as_ptr()), &handler)
+            .log_err();
+    }
+}
+
+fn message_handler_shim(name: &str) -> String {
+    let name = serde_json::Value::from(name);
+    format!(
+        "(() => {{
+            window.webkit = window.webkit || {{}};
+            window.webkit.messageHandlers = window.webkit.messageHandlers || {{}};
+            window.webkit.messageHandlers[{name}] = {{
+                postMessage: (body) =>
+                    window.chrome.webview.postMessage(JSON.stringify({{ name: {name}, body }})),
+            }};
+        }})();"
+    )
+}
+
+/// Splits a message posted by [`message_handler_shim`] into the handler name and its body. String
+/// bodies are delivered as-is and other values as JSON, matching macOS.
+fn parse_web_message(message: &str) -> Option<(String, String)> {
+    let serde_json::Value::Object(mut message) = serde_json::from_str(message).ok()? else {
+        return None;
+    };
+    let serde_json::Value::String(name) = message.remove("name")? else {
+        return None;
+    };
+    let body = match message.remove("body") {
+        Some(serde_json::Value::String(body)) => body,
+        Some(body) => body.to_string(),
+        None => String::new(),
+    };
+    Some((name, body))
+}
+
+impl WebViewState {
+    fn report_progress(this: &RefCell<Self>, progress: f64) {
+        this.borrow_mut().estimated_progress = progress;
+        Self::invoke(
+            this,
+            |state| &mut state.progress,
+            |handler| handler(progress),
+        );
+    }
+}
+
+unsafe fn handle_download_starting(
+    state: &Rc<RefCell<WebViewState>>,
+    args: &ICoreWebView2DownloadStartingEventArgs,
+) -> windows::core::Result<()> {
+    unsafe {
+        if state.borrow().download.is_none() {
+            return Ok(());
+        }
+
+        let mut default_path = PWSTR::null();
+        args.ResultFilePath(&mut default_path)?;
+        let default_path = PathBuf::from(webview2_com::take_pwstr(default_path));
+        let suggested_filename = default_path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let Some(destination) = WebViewState::download_destination(state, suggested_filename)
+        else {
+            return args.SetCancel(true);
+        };
+        args.SetResultFilePath(PCWSTR(HSTRING::from(destination.as_os_str()).as_ptr()))?;
+        // Suppresses WebView2's download UI, since the handler chose the destination.
+        args.SetHandled(true)?;
+
+        let operation = args.DownloadOperation()?;
+        let state_changed = StateChangedEventHandler::create(Box::new({
+            let state = state.clone();
+            move |operation, _| unsafe {
+                let Some(operation) = operation else {
+                    return Ok(());
+                };
+                let mut download_state = COREWEBVIEW2_DOWNLOAD_STATE::default();
+                operation.State(&mut download_state)?;
+                if download_state == COREWEBVIEW2_DOWNLOAD_STATE_COMPLETED {
+                    WebViewState::finish_download(&state, Ok(destination.clone()));
+                } else if download_state == COREWEBVIEW2_DOWNLOAD_STATE_INTERRUPTED {
+                    let mut reason = COREWEBVIEW2_DOWNLOAD_INTERRUPT_REASON::default();
+                    operation.InterruptReason(&mut reason)?;
+                    WebViewState::finish_download(
+                        &state,
+                        Err(anyhow!("download interrupted (reason {})", reason.0)),
+                    );
+                }
+                Ok(())
+            }
+        }));
+        // The handler is released along with the operation once the download ends.
+        let mut token = 0;
+        operation.add_StateChanged(&state_changed, &mut token)
+    }
+}
+
+fn set_script_enabled(webview: &ICoreWebView2, enabled: bool) {
+    unsafe {
+        webview
+            .Settings()
+            .and_then(|settings| settings.SetIsScriptEnabled(enabled))
+            .log_err();
+    }
+}
+
+fn set_reputation_checking_required(webview: &ICoreWebView2, required: bool) {
+    unsafe {
+        webview
+            .Settings()
+            .and_then(|settings| settings.cast::<ICoreWebView2Settings8>())
+            .and_then(|settings| settings.SetIsReputationCheckingRequired(required))
+            .log_err();
+    }
+}
+
+fn document_title(webview: &ICoreWebView2) -> String {
+    let mut title = PWSTR::null();
+    match unsafe { webview.DocumentTitle(&mut title) } {
+        Ok(()) => webview2_com::take_pwstr(title),
+        Err(error) => {
+            log::error!("Failed to read document title: {error}");
+            String::new()
+        }
+    }
+}
+
+/// Captures the visible page as PNG bytes.
+fn capture_preview(webview: &ICoreWebView2, callback: impl FnOnce(Result<Vec<u8>>) + 'static) {
+    let Some(stream) = (unsafe { SHCreateMemStream(None) }) else {
+        callback(Err(anyhow!("failed to create snapshot stream")));
+        return;
+    };
+    let handler = CapturePreviewCompletedHandler::create(Box::new({
+        let stream = stream.clone();
+        move |error_code| {
+            callback(
+                error_code
+                    .context("failed to capture snapshot")
+                    .and_then(|()| read_stream(&stream)),
+            );
+            Ok(())
+        }
+    }));
+    unsafe {
+        webview
+            .CapturePreview(
+                COREWEBVIEW2_CAPTURE_PREVIEW_IMAGE_FORMAT_PNG,
+                &stream,
+                &handler,
+            )
+            .log_err();
+    }
+}
+
+fn read_stream(stream: &IStream) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 64 * 1024];
+    unsafe {
+        stream.Seek(0, STREAM_SEEK_SET, None)?;
+        loop {
+            let mut read = 0;
+            stream
+                .Read(
+                    chunk.as_mut_ptr() as *mut c_void,
+                    chunk.len() as u32,
+                    Some(&mut read as *mut u32),
+                )
+                .ok()?;
+            if read == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..read as usize]);
+        }
+    }
+    Ok(bytes)
+}
+
+pub(super) fn open_url(url: &str) -> Result<()> {
+    let instance = unsafe {
+        ShellExecuteW(
+            None,
+            w!("open"),
+            PCWSTR(HSTRING::from(url).as_ptr()),
+            None,
+            None,
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values above 32 indicate success.
+    anyhow::ensure!(
+        instance.0 as isize > 32,
+        "failed to open {url} in the default browser (error {})",
+        instance.0 as isize
+    );
+    Ok(())
+}
+
+/// Reduces a URL to its origin, like `https://example.com:8443`, keeping the URL as is when it
+/// can't be parsed.
+fn origin_of(url: &str) -> String {
+    url::Url::parse(url)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+fn read_pwstr(
+    read: impl FnOnce(&mut PWSTR) -> windows::core::Result<()>,
+) -> windows::core::Result<String> {
+    let mut value = PWSTR::null();
+    read(&mut value)?;
+    Ok(webview2_com::take_pwstr(value))
+}
+
+unsafe fn cookies_from_list(list: &ICoreWebView2CookieList) -> Result<Vec<Cookie>> {
+    unsafe {
+        let mut count = 0;
+        list.Count(&mut count)?;
+        let mut cookies = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let native_cookie = list.GetValueAtIndex(index)?;
+            let name = read_pwstr(|value| native_cookie.Name(value))?;
+            let value = read_pwstr(|value| native_cookie.Value(value))?;
+            let domain = read_pwstr(|value| native_cookie.Domain(value))?;
+            let path = read_pwstr(|value| native_cookie.Path(value))?;
+            let mut secure = BOOL::default();
+            native_cookie.IsSecure(&mut secure)?;
+            let mut http_only = BOOL::default();
+            native_cookie.IsHttpOnly(&mut http_only)?;
+            let mut is_session = BOOL::default();
+            native_cookie.IsSession(&mut is_session)?;
+            let expires = if is_session.as_bool() {
+                None
+            } else {
+                let mut seconds = 0.0;
+                native_cookie.Expires(&mut seconds)?;
+                from_unix_seconds(seconds)
+            };
+            cookies.push(Cookie {
+                name,
+                value,
+                domain,
+                path,
+                secure: secure.as_bool(),
+                http_only: http_only.as_bool(),
+                expires,
+            });
+        }
+        Ok(cookies)
+    }
+}
+
+/// Chromium's default already only autoplays muted media, matching
+/// [`AutoplayPolicy::RequireUserGesture`].
+fn autoplay_browser_arguments(policy: AutoplayPolicy) -> Option<&'static str> {
+    match policy {
+        AutoplayPolicy::Allow => Some("--autoplay-policy=no-user-gesture-required"),
+        AutoplayPolicy::RequireUserGesture => None,
+        AutoplayPolicy::Block => Some("--autoplay-policy=user-gesture-required"),
+    }
+}
+
+fn browser_arguments(options: &WebViewManagerBuilder) -> Result<Option<String>> {
+    let mut arguments = Vec::new();
+    if let Some(argument) = autoplay_browser_arguments(options.media_autoplay) {
+        arguments.push(argument.to_string());
+    }
+    if let Some(proxy) = &options.proxy {
+        anyhow::ensure!(
+            proxy.credentials.is_none(),
+            "webview proxy credentials are not supported on Windows"
+        );
+        arguments.push(format!("--proxy-server={}:{}", proxy.host, proxy.port));
+    }
+    Ok((!arguments.is_empty()).then(|| arguments.join(" ")))
+}
+
+/// Non-persistent controllers use InPrivate mode, whose profile data is discarded on close. Named
+/// data stores map to WebView2 profiles of the same name.
+///
+/// Browser arguments apply to the WebView2 browser process, which every webview in the app
+/// shares, so mixing autoplay policies or proxies across webviews fails to create the environment.
+fn create_controller(
+    hwnd: HWND,
+    options: &WebViewManagerBuilder,
+) -> Result<ICoreWebView2Controller> {
+    let persistent = options.data_store.is_persistent();
+    let profile_name = options
+        .data_store
+        .identifier()
+        .map(|identifier| identifier.to_string());
+    let environment = {
+        let (tx, rx) = mpsc::channel();
+        let environment_options = CoreWebView2EnvironmentOptions::default();
+        if let Some(arguments) = browser_arguments(options)? {
+            unsafe { environment_options.set_additional_browser_arguments(arguments) };
+        }
+        let environment_options = ICoreWebView2EnvironmentOptions::from(environment_options);
+        CreateCoreWebView2EnvironmentCompletedHandler::wait_for_async_operation(
+            Box::new(move |handler| unsafe {
+                CreateCoreWebView2EnvironmentWithOptions(
+                    PCWSTR::null(),
+                    PCWSTR::null(),
+                    &environment_options,
+                    &handler,
+                )
+                .map_err(webview2_com::Error::WindowsError)
+            }),
+            Box::new(move |error_code, environment| {
+                error_code?;
+                tx.send(environment.ok_or_else(|| windows::core::Error::from(E_POINTER)))
+                    .ok();
+                Ok(())
+            }),
+        )
+        .map_err(|error| anyhow!("failed to create WebView2 environment: {error:?}"))?;
+        rx.recv()
+            .context("WebView2 environment creation was cancelled")??
+    };
+
+    let options = if persistent && profile_name.is_none() {
+        None
+    } else {
+        let environment = environment
+            .cast::<ICoreWebView2Environment10>()
+            .context("InPrivate and named profile webviews require a newer WebView2 runtime")?;
+        unsafe {
+            let options = environment.CreateCoreWebView2ControllerOptions()?;
+            options.SetIsInPrivateModeEnabled(!persistent)?;
+            if let Some(profile_name) = &profile_name {
+                options.SetProfileName(PCWSTR(HSTRING::from(profile_name.as_str()).as_ptr()))?;
+            }
+            Some((environment, options))
+        }
+    };
+
+    let (tx, rx) = mpsc::channel();
+    CreateCoreWebView2ControllerCompletedHandler::wait_for_async_operation(
+        Box::new(move |handler| unsafe {
+            match &options {
+                Some((environment, options)) => {
+                    environment.CreateCoreWebView2ControllerWithOptions(hwnd, options, &handler)
+                }
+                None => environment.CreateCoreWebView2Controller(hwnd, &handler),
+            }
+            .map_err(webview2_com::Error::WindowsError)
+        }),
+        Box::new(move |error_code, controller| {
+            error_code?;
+            tx.send(controller.ok_or_else(|| windows::core::Error::from(E_POINTER)))
+                .ok();
+            Ok(())
+        }),
+    )
+    .map_err(|error| anyhow!("failed to create WebView2 controller: {error:?}"))?;
+    Ok(rx
+        .recv()
+        .context("WebView2 controller creation was cancelled")??)
+}
+
+impl WebViewManager {
+    /// Whether the WebView2 runtime is installed, checked without creating a webview.
+    pub fn is_supported() -> bool {
+        let mut version = PWSTR::null();
+        let installed =
+            unsafe { GetAvailableCoreWebView2BrowserVersionString(PCWSTR::null(), &mut version) }
+                .is_ok();
+        let version = webview2_com::take_pwstr(version);
+        installed && !version.is_empty()
+    }
+
+    /// Creating the WebView2 environment and controller is asynchronous; this pumps the message
+    /// loop until both are ready, so the returned manager is immediately usable.
+    pub(super) fn create(
+        parent_window_ptr: *mut c_void,
+        options: &WebViewManagerBuilder,
+        cx: &App,
+    ) -> Result<Self> {
+        let url = &normalize_url(&options.url)?;
+        if options.cache_policy != CachePolicy::Default {
+            log::warn!(
+                "WebView cache policy {:?} is not supported on Windows",
+                options.cache_policy
+            );
+        }
+        register_window_class();
+        unsafe {
+            let instance = GetModuleHandleW(None).context("failed to get module handle")?;
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
+                WINDOW_CLASS_NAME,
+                w!(""),
+                if options.titlebar_style == TitlebarStyle::Hidden {
+                    WS_POPUP | WS_THICKFRAME
+                } else {
+                    WS_OVERLAPPEDWINDOW
+                },
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                // An owned window minimizes and closes with its owner and stays above it.
+                (options.attach_to_parent && !parent_window_ptr.is_null())
+                    .then(|| HWND(parent_window_ptr)),
+                None,
+                Some(instance.into()),
+                None,
+            )
+            .context("failed to create webview window")?;
+
+            match Self::init(hwnd, parent_window_ptr, url, options) {
+                Ok(manager) => Ok(manager),
+                Err(error) => {
+                    DestroyWindow(hwnd).log_err();
+                    Err(error)
+                }
+            }
+        }
+    }
+
+    unsafe fn init(
+        hwnd: HWND,
+        parent_window_ptr: *mut c_void,
+        url: &str,
+        options: &WebViewManagerBuilder,
+    ) -> Result<Self> {
+        unsafe {
+            let bounds = options.bounds;
+            let scale = scale_factor(hwnd);
+            let offset = (100.0 * scale) as i32;
+            let monitor = select_monitor(options.screen, HWND(parent_window_ptr), scale);
+            let area = monitor_work_area(monitor).unwrap_or(RECT {
+                right: i32::MAX,
+                bottom: i32::MAX,
+                ..RECT::default()
+            });
+            let width = ((f32::from(bounds.size.width) * scale) as i32).min(area.right - area.left);
+            let height =
+                ((f32::from(bounds.size.height) * scale) as i32).min(area.bottom - area.top);
+            SetWindowPos(
+                hwnd,
+                None,
+                (area.left + offset).min(area.right - width),
+                (area.top + offset).min(area.bottom - height),
+                width,
+                height,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )
+            .context("failed to position webview window")?;
+
+            let controller = create_controller(hwnd, options)?;
+            // Attached right away so that, if anything below fails, destroying the window
+            // releases the controller through `WM_NCDESTROY`.
+            let state = Rc::new(RefCell::new(WebViewState::default()));
+            SetWindowLongPtrW(
+                hwnd,
+                GWLP_USERDATA,
+                Box::into_raw(Box::new(WindowData {
+                    controller: controller.clone(),
+                    state: state.clone(),
+                    in_size_move: Cell::new(false),
+                    min_size: Cell::new(None),
+                    max_size: Cell::new(None),
+                    aspect_ratio: Cell::new(None),
+                    viewport_size: Cell::new(None),
+                    content_insets: Cell::new(Edges::default()),
+                    chrome_height: Cell::new(px(0.0)),
+                })) as isize,
+            );
+            let mut client_rect = RECT::default();
+            GetClientRect(hwnd, &mut client_rect)?;
+            controller.SetBounds(client_rect)?;
+            controller.SetIsVisible(true)?;
+            let webview = controller.CoreWebView2()?;
+
+            let navigation_starting = NavigationStartingEventHandler::create(Box::new({
+                let state = state.clone();
+                move |sender, args| unsafe {
+                    let (Some(sender), Some(args)) = (sender, args) else {
+                        return Ok(());
+                    };
+                    let mut uri = PWSTR::null();
+                    args.Uri(&mut uri)?;
+                    let url = webview2_com::take_pwstr(uri);
+                    let decision = WebViewState::decide_navigation(&state, &url);
+                    if decision == NavigationDecision::Allow {
+                        WebViewState::report_progress(&state, 0.0);
+                        return Ok(());
+                    }
+                    args.SetCancel(true)?;
+                    if decision == NavigationDecision::OpenExternally {
+                        super::open_in_default_browser(&url).log_err();
+                    }
+                    let deferred_scripts = WebViewState::cancel_pending_navigation(&state, &url);
+                    run_deferred_scripts(&sender, deferred_scripts, true);
+                    Ok(())
+                }
+            }));
+            let mut navigation_starting_token = 0;
+            webview.add_NavigationStarting(&navigation_starting, &mut navigation_starting_token)?;
+
+            let navigation_completed = NavigationCompletedEventHandler::create(Box::new({
+                let state = state.clone();
+                move |sender, args| unsafe {
+                    let (Some(sender), Some(args)) = (sender, args) else {
+                        return Ok(());
+                    };
+                    WebViewState::report_progress(&state, 1.0);
+                    let mut source = PWSTR::null();
+                    sender.Source(&mut source)?;
+                    let url = webview2_com::take_pwstr(source);
+
+                    let mut is_success = BOOL::default();
+                    args.IsSuccess(&mut is_success)?;
+                    if is_success.as_bool() {
+                        state.borrow_mut().page_url = Some(url.clone());
+                        if state.borrow().context_menu_disabled {
+                            execute_script(&sender, &context_menu_flag_script(true), None);
+                        }
+                        let deferred_scripts =
+                            WebViewState::finish_pending_navigation(&state, Ok(()));
+                        run_deferred_scripts(&sender, deferred_scripts, true);
+                        WebViewState::invoke(
+                            &state,
+                            |state| &mut state.did_finish_navigation,
+                            |handler| handler(url),
+                        );
+                    } else {
+                        let mut status = COREWEBVIEW2_WEB_ERROR_STATUS::default();
+                        args.WebErrorStatus(&mut status)?;
+                        let code = status.0 as i64;
+                        log::warn!("WebView navigation to {} failed with code {}", url, code);
+                        let deferred_scripts = WebViewState::finish_pending_navigation(
+                            &state,
+                            Err(anyhow!("navigation to {url} failed with code {code}")),
+                        );
+                        run_deferred_scripts(&sender, deferred_scripts, true);
+                        WebViewState::invoke(
+                            &state,
+                            |state| &mut state.navigation_failed,
+                            |handler| handler(url, code),
+                        );
+                    }
+                    Ok(())
+                }
+            }));
+            let mut navigation_completed_token = 0;
+            webview
+                .add_NavigationCompleted(&navigation_completed, &mut navigation_completed_token)?;
+
+            let web_message_received = WebMessageReceivedEventHandler::create(Box::new({
+                let state = state.clone();
+                move |_, args| unsafe {
+                    let Some(args) = args else {
+                        return Ok(());
+                    };
+                    let mut message = PWSTR::null();
+                    args.TryGetWebMessageAsString(&mut message)?;
+                    let message = webview2_com::take_pwstr(message);
+                    match parse_web_message(&message) {
+                        Some((name, body)) => {
+                            WebViewState::invoke_message_handler(&state, name, body)
+                        }
+                        None => log::warn!("WebView received a malformed message: {message}"),
+                    }
+                    Ok(())
+                }
+            }));
+            let mut web_message_received_token = 0;
+            webview
+                .add_WebMessageReceived(&web_message_received, &mut web_message_received_token)?;
+
+            let document_title_changed = DocumentTitleChangedEventHandler::create(Box::new({
+                let state = state.clone();
+                move |sender, _| {
+                    let Some(sender) = sender else {
+                        return Ok(());
+                    };
+                    WebViewState::update_title(&state, document_title(&sender));
+                    Ok(())
+                }
+            }));
+            let mut document_title_changed_token = 0;
+            webview.add_DocumentTitleChanged(
+                &document_title_changed,
+                &mut document_title_changed_token,
+            )?;
+
+            // Without a handler, WebView2 opens popups in a separate browser window, so they load in
+            // place instead unless a new-window handler takes them.
+            let new_window_requested = NewWindowRequestedEventHandler::create(Box::new({
+                let state = state.clone();
+                move |sender, args| unsafe {
+                    let (Some(sender), Some(args)) = (sender, args) else {
+                        return Ok(());
+                    };
+                    let mut uri = PWSTR::null();
+                    args.Uri(&mut uri)?;
+                    let url = webview2_com::take_pwstr(uri);
+                    args.SetHandled(true)?;
+                    let handled = WebViewState::invoke(
+                        &state,
+                        |state| &mut state.new_window,
+                        |handler| handler(url.clone()),
+                    );
+                    if handled.is_none() {
+                        sender.Navigate(PCWSTR(HSTRING::from(url).as_ptr()))?;
+                    }
+                    Ok(())
+                }
+            }));
+            let mut new_window_requested_token = 0;
+            webview
+                .add_NewWindowRequested(&new_window_requested, &mut new_window_requested_token)?;
+
+            // Only raised once default dialogs are disabled, which registering a handler does.
+            let script_dialog_opening = ScriptDialogOpeningEventHandler::create(Box::new({
+                let state = state.clone();
+                move |_, args| unsafe {
+                    let Some(args) = args else {
+                        return Ok(());
+                    };
+                    let mut kind = COREWEBVIEW2_SCRIPT_DIALOG_KIND::default();
+                    args.Kind(&mut kind)?;
+                    let mut message = PWSTR::null();
+                    args.Message(&mut message)?;
+                    let message = webview2_com::take_pwstr(message);
+                    match kind {
+                        COREWEBVIEW2_SCRIPT_DIALOG_KIND_ALERT => {
+                            WebViewState::run_js_alert(&state, message);
+                            args.Accept()?;
+                        }
+                        COREWEBVIEW2_SCRIPT_DIALOG_KIND_CONFIRM => {
+                            if WebViewState::run_js_confirm(&state, message) {
+                                args.Accept()?;
+                            }
+                        }
+                        COREWEBVIEW2_SCRIPT_DIALOG_KIND_PROMPT => {
+                            let mut default_text = PWSTR::null();
+                            args.DefaultText(&mut default_text)?;
+                            let default_text = webview2_com::take_pwstr(default_text);
+                            if let Some(text) =
+                                WebViewState::run_js_prompt(&state, message, default_text)
+                            {
+                                args.SetResultText(PCWSTR(HSTRING::from(text).as_ptr()))?;
+                                args.Accept()?;
+                            }
+                        }
+                        // Leave the page when asked by `beforeunload`.
+                        _ => args.Accept()?,
+                    }
+                    Ok(())
+                }
+            }));
+            let mut script_dialog_opening_token = 0;
+            webview.add_ScriptDialogOpening(
+                &script_dialog_opening,
+                &mut script_dialog_opening_token,
+            )?;
+
+            // Permissions the page can ask for beyond those in `PermissionKind` keep WebView2's
+            // default handling.
+            let permission_requested = PermissionRequestedEventHandler::create(Box::new({
+                let state = state.clone();
+                move |_, args| unsafe {
+                    let Some(args) = args else {
+                        return Ok(());
+                    };
+                    let mut kind = COREWEBVIEW2_PERMISSION_KIND::default();
+                    args.PermissionKind(&mut kind)?;
+                    let kind = match kind {
+                        COREWEBVIEW2_PERMISSION_KIND_CAMERA => PermissionKind::Camera,
+                        COREWEBVIEW2_PERMISSION_KIND_MICROPHONE => PermissionKind::Microphone,
+                        COREWEBVIEW2_PERMISSION_KIND_GEOLOCATION => PermissionKind::Geolocation,
+                        _ => return Ok(()),
+                    };
+                    let mut uri = PWSTR::null();
+                    args.Uri(&mut uri)?;
+                    let origin = origin_of(&webview2_com::take_pwstr(uri));
+                    let permission_state = if WebViewState::decide_permission(&state, kind, origin)
+                    {
+                        COREWEBVIEW2_PERMISSION_STATE_ALLOW
+                    } else {
+                        COREWEBVIEW2_PERMISSION_STATE_DENY
+                    };
+                    args.SetState(permission_state)?;
+                    Ok(())
+                }
+            }));
+            let mut permission_requested_token = 0;
+            webview
+                .add_PermissionRequested(&permission_requested, &mut permission_requested_token)?;
+
+            let process_failed = ProcessFailedEventHandler::create(Box::new({
+                let state = state.clone();
+                move |sender, args| unsafe {
+                    let (Some(sender), Some(args)) = (sender, args) else {
+                        return Ok(());
+                    };
+                    let mut kind = COREWEBVIEW2_PROCESS_FAILED_KIND::default();
+                    args.ProcessFailedKind(&mut kind)?;
+                    // Other kinds are either recovered by WebView2 itself or leave nothing to
+                    // reload.
+                    if kind == COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED {
+                        let (reload, user_scripts) = WebViewState::handle_crash(&state);
+                        run_deferred_scripts(&sender, user_scripts, false);
+                        if reload {
+                            sender.Reload()?;
+                        }
+                    }
+                    Ok(())
+                }
+            }));
+            let mut process_failed_token = 0;
+            webview.add_ProcessFailed(&process_failed, &mut process_failed_token)?;
+
+            // WebView2 only raises this for certificates that failed validation, so handlers see
+            // far fewer challenges than on macOS.
+            let server_certificate_error_token = match webview.cast::<ICoreWebView2_14>() {
+                Ok(webview) => {
+                    let server_certificate_error =
+                        ServerCertificateErrorDetectedEventHandler::create(Box::new({
+                            let state = state.clone();
+                            move |_, args| unsafe {
+                                let Some(args) = args else {
+                                    return Ok(());
+                                };
+                                let mut uri = PWSTR::null();
+                                args.RequestUri(&mut uri)?;
+                                let uri = webview2_com::take_pwstr(uri);
+                                let url = url::Url::parse(&uri).ok();
+                                let challenge = AuthChallenge {
+                                    host: url
+                                        .as_ref()
+                                        .and_then(|url| url.host_str())
+                                        .unwrap_or_default()
+                                        .to_string(),
+                                    port: url
+                                        .as_ref()
+                                        .and_then(|url| url.port_or_known_default())
+                                        .unwrap_or(0),
+                                    method: AuthMethod::ServerTrust,
+                                };
+                                let action = match WebViewState::respond_to_auth_challenge(
+                                    &state, challenge,
+                                ) {
+                                    AuthResponse::UseCredential => {
+                                        COREWEBVIEW2_SERVER_CERTIFICATE_ERROR_ACTION_ALWAYS_ALLOW
+                                    }
+                                    AuthResponse::PerformDefault => {
+                                        COREWEBVIEW2_SERVER_CERTIFICATE_ERROR_ACTION_DEFAULT
+                                    }
+                                    AuthResponse::Cancel => {
+                                        COREWEBVIEW2_SERVER_CERTIFICATE_ERROR_ACTION_CANCEL
+                                    }
+                                };
+                                args.SetAction(action)?;
+                                Ok(())
+                            }
+                        }));
+                    let mut token = 0;
+                    webview.add_ServerCertificateErrorDetected(
+                        &server_certificate_error,
+                        &mut token,
+                    )?;
+                    Some(token)
+                }
+                Err(error) => {
+                    log::warn!(
+                        "WebView2 runtime doesn't support certificate error events: {error}"
+                    );
+                    None
+                }
+            };
+
+            let basic_authentication_token = match webview.cast::<ICoreWebView2_10>() {
+                Ok(webview) => {
+                    let basic_authentication =
+                        BasicAuthenticationRequestedEventHandler::create(Box::new({
+                            let state = state.clone();
+                            move |_, args| unsafe {
+                                let Some(args) = args else {
+                                    return Ok(());
+                                };
+                                let basic_auth = state.borrow().basic_auth.clone();
+                                let Some(basic_auth) = basic_auth else {
+                                    return Ok(());
+                                };
+                                let mut uri = PWSTR::null();
+                                args.Uri(&mut uri)?;
+                                let uri = webview2_com::take_pwstr(uri);
+                                let Some((host, port)) =
+                                    url::Url::parse(&uri).ok().and_then(|url| {
+                                        Some((
+                                            url.host_str()?.to_string(),
+                                            url.port_or_known_default()?,
+                                        ))
+                                    })
+                                else {
+                                    return Ok(());
+                                };
+                                if !basic_auth.matches(&host, port) {
+                                    return Ok(());
+                                }
+                                let response = args.Response()?;
+                                response.SetUserName(PCWSTR(
+                                    HSTRING::from(basic_auth.username).as_ptr(),
+                                ))?;
+                                response.SetPassword(PCWSTR(
+                                    HSTRING::from(basic_auth.password).as_ptr(),
+                                ))?;
+                                Ok(())
+                            }
+                        }));
+                    let mut token = 0;
+                    webview.add_BasicAuthenticationRequested(&basic_authentication, &mut token)?;
+                    Some(token)
+                }
+                Err(error) => {
+                    log::warn!(
+                        "WebView2 runtime doesn't support basic authentication events: {error}"
+                    );
+                    None
+                }
+            };
+
+            let download_starting_token = match webview.cast::<ICoreWebView2_4>() {
+                Ok(webview) => {
+                    let download_starting = DownloadStartingEventHandler::create(Box::new({
+                        let state = state.clone();
+                        move |_, args| unsafe {
+                            let Some(args) = args else {
+                                return Ok(());
+                            };
+                            handle_download_starting(&state, &args)
+                        }
+                    }));
+                    let mut token = 0;
+                    webview.add_DownloadStarting(&download_starting, &mut token)?;
+                    Some(token)
+                }
+                Err(error) => {
+                    log::warn!("WebView2 runtime doesn't support download events: {error}");
+                    None
+                }
+            };
+
+            if !options.javascript_enabled {
+                set_script_enabled(&webview, false);
+            }
+            if !options.fraudulent_website_warning {
+                set_reputation_checking_required(&webview, false);
+            }
+            if !options.load_images {
+                log::warn!("Disabling image loading is not supported on Windows");
+            }
+            webview.Navigate(PCWSTR(HSTRING::from(url).as_ptr()))?;
+            if options.initially_hidden {
+                controller.SetIsVisible(false).log_err();
+            } else {
+                let show_command = if options.activate {
+                    SW_SHOW
+                } else {
+                    SW_SHOWNOACTIVATE
+                };
+                let _ = ShowWindow(hwnd, show_command);
+            }
+
+            webview_info!("Created floating webview window ({}x{})", width, height);
+
+            Ok(Self {
+                hwnd,
+                parent_hwnd: HWND(parent_window_ptr),
+                controller,
+                webview,
+                navigation_starting_token,
+                navigation_completed_token,
+                web_message_received_token,
+                document_title_changed_token,
+                new_window_requested_token,
+                script_dialog_opening_token,
+                permission_requested_token,
+                process_failed_token,
+                server_certificate_error_token,
+                basic_authentication_token,
+                download_starting_token,
+                state,
+                current_url: url.to_string(),
+                history: NavigationHistory::new(url),
+                context_menu_listener_installed: Cell::new(false),
+                pending_bounds: PendingBounds::default(),
+                window_title: String::new(),
+                title: String::new(),
+                data_store: options.data_store.clone(),
+                foreground_executor: cx.foreground_executor().clone(),
+                background_executor: cx.background_executor().clone(),
+                _slot: None,
+                _not_send: PhantomData,
+            })
+        }
+    }
+
+    /// Loads `url`, adding `https://` when it has no scheme. Does nothing when the webview is
+    /// already showing `url`, unless its last load failed. Fails when `url` is invalid.
+    pub fn navigate(&mut self, url: &str) -> Result<()> {
+        let url = self.start_navigation(url, |this, url| unsafe {
+            this.webview.Navigate(PCWSTR(HSTRING::from(url).as_ptr()))?;
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!("WebView navigated to: {}", url);
+        }
+        Ok(())
+    }
+
+    /// Navigates like [`Self::navigate`], sending `headers` with the request, e.g. for
+    /// `Authorization`. Headers only apply to this request, not to subresources or later
+    /// navigations.
+    pub fn navigate_with_headers(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<()> {
+        let url = self.start_navigation(url, |this, url| {
+            this.navigate_with_request(url, "GET", None, headers)
+        })?;
+        if let Some(url) = url {
+            webview_info!(
+                "WebView navigated to: {} with {} headers",
+                url,
+                headers.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Loads `url` with a POST request carrying `body`. Unlike [`Self::navigate`], repeated loads of
+    /// the same URL are not skipped, since each one submits data. Fails when `url` is invalid.
+    pub fn load_post(&mut self, url: &str, body: &[u8], content_type: &str) -> Result<()> {
+        let url = self.start_load(url, |this, url| {
+            this.navigate_with_request(url, "POST", Some(body), &[("Content-Type", content_type)])
+        })?;
+        webview_info!("WebView posted {} bytes to: {}", body.len(), url);
+        Ok(())
+    }
+
+    /// Loads the local file at `path`. WebView2 lets file pages read other local files, so no
+    /// extra access needs granting. Like [`Self::navigate`], does nothing when the file is already
+    /// shown. Fails when `path` doesn't exist or isn't a file.
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let (_, url) = file_url(path)?;
+        let url = self.start_navigation(&url, |this, url| unsafe {
+            this.webview.Navigate(PCWSTR(HSTRING::from(url).as_ptr()))?;
+            Ok(())
+        })?;
+        if let Some(url) = url {
+            webview_info!("WebView loaded file: {}", url);
+        }
+        Ok(())
+    }
+
+    fn navigate_with_request(
+        &self,
+        url: &str,
+        method: &str,
+        body: Option<&[u8]>,
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect::<String>();
+        unsafe {
+            let webview = self
+                .webview
+                .cast::<ICoreWebView2_2>()
+                .context("custom requests require a newer WebView2 runtime")?;
+            let environment = webview.Environment()?.cast::<ICoreWebView2Environment2>()?;
+            let body = body.and_then(|body| SHCreateMemStream(Some(body)));
+            let request = environment.CreateWebResourceRequest(
+                PCWSTR(HSTRING::from(url).as_ptr()),
+                PCWSTR(HSTRING::from(method).as_ptr()),
+                body.as_ref(),
+                PCWSTR(HSTRING::from(headers).as_ptr()),
+            )?;
+            webview.NavigateWithWebResourceRequest(&request)?;
+        }
+        Ok(())
+    }
+
+    /// Renders `html` directly. WebView2 has no base URL for string content, so relative
+    /// references in the document won't resolve.
+    pub fn load_html(&mut self, html: &str, base_url: Option<&str>) {
+        self.current_url = base_url.unwrap_or("about:blank").to_string();
+
+        unsafe {
+            self.webview
+                .NavigateToString(PCWSTR(HSTRING::from(html).as_ptr()))
+                .log_err();
+        }
+        webview_info!("WebView loaded HTML string ({} bytes)", html.len());
+    }
+
+    pub fn reload(&self) {
+        unsafe {
+            self.webview.Reload().log_err();
+        }
+        webview_info!("WebView reloaded: {}", self.current_url);
+    }
+
+    /// Reloads the current page, bypassing the HTTP cache via the DevTools protocol.
+    pub fn reload_ignoring_cache(&self) {
+        unsafe {
+            self.webview
+                .CallDevToolsProtocolMethod(
+                    w!("Page.reload"),
+                    w!(r#"{"ignoreCache":true}"#),
+                    &CallDevToolsProtocolMethodCompletedHandler::create(Box::new(|_, _| Ok(()))),
+                )
+                .log_err();
+        }
+        webview_info!("WebView reloaded from origin: {}", self.current_url);
+    }
+
+    /// Cancels any in-flight load. Safe to call when nothing is loading.
+    pub fn stop_loading(&self) {
+        unsafe {
+            self.webview.Stop().log_err();
+        }
+    }
+
+    pub fn go_back(&self) {
+        unsafe {
+            self.webview.GoBack().log_err();
+        }
+    }
+
+    pub fn go_forward(&self) {
+        unsafe {
+            self.webview.GoForward().log_err();
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        let mut can_go_back = BOOL::default();
+        unsafe {
+            self.webview.CanGoBack(&mut can_go_back).log_err();
+        }
+        can_go_back.as_bool()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        let mut can_go_forward = BOOL::default();
+        unsafe {
+            self.webview.CanGoForward(&mut can_go_forward).log_err();
+        }
+        can_go_forward.as_bool()
+    }
+
+    /// Moves and resizes the window. `bounds` is in gpui's logical screen coordinates.
+    pub fn set_bounds(&self, bounds: Bounds<Pixels>) {
+        self.remember_bounds_if_hidden(bounds);
+        let scale = scale_factor(self.hwnd);
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                None,
+                (f32::from(bounds.origin.x) * scale) as i32,
+                (f32::from(bounds.origin.y) * scale) as i32,
+                (f32::from(bounds.size.width) * scale) as i32,
+                (f32::from(bounds.size.height) * scale) as i32,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )
+            .context("failed to set webview window bounds")
+            .log_err();
+        }
+    }
+
+    /// Returns the window frame in gpui's logical screen coordinates.
+    pub fn get_bounds(&self) -> Bounds<Pixels> {
+        window_bounds(self.hwnd)
+    }
+
+    /// The work area of the window's monitor, excluding the taskbar.
+    pub(super) fn work_area(&self) -> Option<Bounds<Pixels>> {
+        let monitor = unsafe { MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST) };
+        monitor_work_area(monitor).map(|rect| logical_bounds(rect, scale_factor(self.hwnd)))
+    }
+
+    /// Registers a handler called with the window frame, in the coordinates used by
+    /// [`Self::get_bounds`], after the user moves or resizes the window.
+    pub fn on_bounds_changed(&mut self, handler: impl FnMut(Bounds<Pixels>) + 'static) {
+        self.state.borrow_mut().bounds_changed = Some(Box::new(handler));
+    }
+
+    /// Evaluates `script` in the page and passes its result, converted to a string, to `callback`.
+    ///
+    /// WebView2 invokes the completion handler on the UI thread, so `callback` runs there too.
+    pub fn evaluate_javascript(
+        &self,
+        script: &str,
+        callback: impl FnOnce(Result<String>) + 'static,
+    ) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::Evaluate(
+                script.to_string(),
+                Some(Box::new(callback)),
+            ));
+            return;
+        }
+        execute_script(&self.webview, script, Some(Box::new(callback)));
+    }
+
+    pub fn evaluate_javascript_fire_and_forget(&self, script: &str) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::Evaluate(script.to_string(), None));
+            return;
+        }
+        execute_script(&self.webview, script, None);
+    }
+
+    /// Registers a handler invoked with the final URL whenever a main-frame navigation finishes.
+    pub fn on_did_finish_navigation(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().did_finish_navigation = Some(Box::new(handler));
+    }
+
+    /// Registers a handler invoked with the failing URL and `COREWEBVIEW2_WEB_ERROR_STATUS`
+    /// when a navigation fails.
+    pub fn on_navigation_failed(&mut self, handler: impl FnMut(String, i64) + 'static) {
+        self.state.borrow_mut().navigation_failed = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called when the page's renderer process exits, leaving it blank.
+    /// Without a handler, the page is reloaded, unless it crashed again before that reload
+    /// finished.
+    pub fn on_crash(&mut self, handler: impl FnMut() + 'static) {
+        self.state.borrow_mut().crash = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called with the page's load progress. WebView2 doesn't report
+    /// intermediate progress, so the handler only receives 0.0 when a navigation starts and 1.0
+    /// when it completes.
+    pub fn on_progress(&mut self, handler: impl FnMut(f64) + 'static) {
+        self.state.borrow_mut().progress = Some(Box::new(handler));
+    }
+
+    pub fn estimated_progress(&self) -> f64 {
+        self.state.borrow().estimated_progress
+    }
+
+    /// Consults `policy` with the URL of every navigation before it starts, including those
+    /// triggered by the page itself.
+    pub fn set_navigation_policy(
+        &mut self,
+        policy: impl FnMut(&str) -> NavigationDecision + 'static,
+    ) {
+        self.state.borrow_mut().navigation_policy = Some(Box::new(policy));
+    }
+
+    /// Routes downloads to `handler`, which receives the suggested file name and returns where to
+    /// save the file, or `None` to cancel. Without a handler, WebView2 saves downloads to the
+    /// user's Downloads folder.
+    pub fn on_download(&mut self, handler: impl FnMut(String) -> Option<PathBuf> + 'static) {
+        self.state.borrow_mut().download = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called with the destination of each completed download, or the error
+    /// that interrupted it.
+    pub fn on_download_finished(&mut self, handler: impl FnMut(Result<PathBuf>) + 'static) {
+        self.state.borrow_mut().download_finished = Some(Box::new(handler));
+    }
+
+    /// Registers a handler called once when the user closes the window. Closing only hides it, so
+    /// the manager stays usable until it is dropped.
+    pub fn on_close(&mut self, handler: impl FnOnce() + 'static) {
+        self.state.borrow_mut().close = Some(Box::new(handler));
+    }
+
+    /// Registers a handler for URLs the page asks to open in a new window, such as
+    /// `target="_blank"` links. Without a handler they load in this webview.
+    pub fn on_new_window(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().new_window = Some(Box::new(handler));
+    }
+
+    /// Registers a handler deciding whether a page may use the camera, microphone, or location,
+    /// called with the kind of access and the requesting origin. Requests are denied without a
+    /// handler.
+    pub fn on_permission_request(
+        &mut self,
+        handler: impl FnMut(PermissionKind, String) -> bool + 'static,
+    ) {
+        self.state.borrow_mut().permission_request = Some(Box::new(handler));
+    }
+
+    /// Registers a handler answering TLS certificate errors. Returning
+    /// [`AuthResponse::UseCredential`] accepts the invalid certificate, so only do so for hosts you
+    /// control, such as a local development server. Other authentication methods keep WebView2's
+    /// default handling.
+    pub fn on_auth_challenge(
+        &mut self,
+        handler: impl FnMut(AuthChallenge) -> AuthResponse + 'static,
+    ) {
+        self.state.borrow_mut().auth_challenge = Some(Box::new(handler));
+    }
+
+    /// Answers HTTP basic auth challenges from `host`, on `port` or any port when `None`, with
+    /// these credentials instead of WebView2's sign-in prompt.
+    pub fn set_basic_auth(
+        &mut self,
+        host: &str,
+        port: Option<u16>,
+        username: &str,
+        password: &str,
+    ) {
+        self.set_basic_auth_state(host, port, username, password);
+    }
+
+    pub fn clear_basic_auth(&mut self) {
+        self.state.borrow_mut().basic_auth = None;
+    }
+
+    /// Custom context menu items aren't supported on Windows yet, so WebView2's menu is shown.
+    pub fn on_context_menu(
+        &mut self,
+        _handler: impl FnMut(ContextMenuContext) -> Vec<MenuItem> + 'static,
+    ) {
+    }
+
+    /// Every shortcut reaches the webview first on Windows.
+    pub fn set_key_passthrough(&self, _keys: &[&str]) {}
+
+    /// WebView2 doesn't close the window on Ctrl-W, so there's nothing to configure on Windows.
+    pub fn set_close_on_cmd_w(&self, _close: bool) {}
+
+    /// Registers a handler for `alert()` dialogs, called with the message. Registering any dialog
+    /// handler replaces WebView2's built-in dialogs; alerts without a handler are then dismissed
+    /// immediately.
+    pub fn on_js_alert(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().js_alert = Some(Box::new(handler));
+        self.disable_default_script_dialogs();
+    }
+
+    /// Registers a handler for `confirm()` dialogs, returning whether the user confirmed. Once
+    /// any dialog handler is registered, confirms without a handler are accepted.
+    pub fn on_js_confirm(&mut self, handler: impl FnMut(String) -> bool + 'static) {
+        self.state.borrow_mut().js_confirm = Some(Box::new(handler));
+        self.disable_default_script_dialogs();
+    }
+
+    /// Registers a handler for `prompt()` dialogs, called with the message and default text and
+    /// returning the answer, or `None` to cancel. Once any dialog handler is registered, prompts
+    /// without a handler return the default text.
+    pub fn on_js_prompt(
+        &mut self,
+        handler: impl FnMut(String, String) -> Option<String> + 'static,
+    ) {
+        self.state.borrow_mut().js_prompt = Some(Box::new(handler));
+        self.disable_default_script_dialogs();
+    }
+
+    fn disable_default_script_dialogs(&self) {
+        unsafe {
+            self.webview
+                .Settings()
+                .and_then(|settings| settings.SetAreDefaultScriptDialogsEnabled(false))
+                .log_err();
+        }
+    }
+
+    /// Registers a handler called with the document title whenever it changes.
+    pub fn on_title_changed(&mut self, handler: impl FnMut(String) + 'static) {
+        self.state.borrow_mut().title_changed = Some(Box::new(handler));
+    }
+
+    /// Enables the DevTools, reachable through the "Inspect" context menu entry or F12.
+    pub fn set_inspectable(&self, inspectable: bool) {
+        unsafe {
+            self.webview
+                .Settings()
+                .and_then(|settings| settings.SetAreDevToolsEnabled(inspectable))
+                .log_err();
+        }
+    }
+
+    /// Disabling scripts doesn't stop [`Self::evaluate_javascript`]. Applies to pages loaded
+    /// afterwards.
+    pub fn set_javascript_enabled(&self, enabled: bool) {
+        set_script_enabled(&self.webview, enabled);
+    }
+
+    /// WebView2 has no setting to stop loading images.
+    pub fn set_load_images(&self, load: bool) {
+        if !load {
+            log::warn!("Disabling image loading is not supported on Windows");
+        }
+    }
+
+    /// Toggles SmartScreen checks. Requires a WebView2 runtime with `ICoreWebView2Settings8`.
+    pub fn set_fraudulent_website_warning_enabled(&self, enabled: bool) {
+        set_reputation_checking_required(&self.webview, enabled);
+    }
+
+    /// WebView2 has no content blocker equivalent to WebKit's rule lists.
+    pub fn set_content_rules(
+        &self,
+        _json_rules: &str,
+        callback: impl FnOnce(Result<()>) + 'static,
+    ) {
+        callback(Err(anyhow!("Content rules are not supported on Windows")));
+    }
+
+    /// Captures the visible page as PNG bytes.
+    pub fn take_snapshot(&self, callback: impl FnOnce(Result<Vec<u8>>) + 'static) {
+        capture_preview(&self.webview, callback);
+    }
+
+    /// Captures the first element matching the CSS `selector` as PNG bytes, clipped to the
+    /// visible part of the page. Fails if nothing matches.
+    pub fn snapshot_element(
+        &self,
+        selector: &str,
+        callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+    ) {
+        let webview = self.webview.clone();
+        let selector = selector.to_string();
+        self.evaluate_javascript(&element_rect_script(&selector), move |result| {
+            let rect = result.and_then(|result| {
+                parse_element_rect(&result)
+                    .ok_or_else(|| anyhow!("no visible element matches {selector:?}"))
+            });
+            match rect {
+                // WebView2 can only capture the whole viewport, so crop that.
+                Ok(rect) => capture_preview(&webview, move |png| {
+                    callback(png.and_then(|png| crop_png(&png, rect)))
+                }),
+                Err(error) => callback(Err(error)),
+            }
+        });
+    }
+
+    /// Searches the page for `query`, selecting and scrolling to the next match after the current
+    /// one. `callback` receives whether a match was found.
+    pub fn find(&self, query: &str, forward: bool, callback: impl FnOnce(bool) + 'static) {
+        self.evaluate_javascript(&find_script(query, forward), move |result| {
+            callback(result.is_ok_and(|result| find_script_matched(&result)))
+        });
+    }
+
+    /// Removes the selection left by [`Self::find`].
+    pub fn clear_find(&self) {
+        self.evaluate_javascript_fire_and_forget(CLEAR_FIND_SCRIPT);
+    }
+
+    /// Renders the current page as a PDF and writes it to `path`.
+    pub fn print_to_pdf(&self, path: &Path, callback: impl FnOnce(Result<()>) + 'static) {
+        let webview = match self.webview.cast::<ICoreWebView2_7>() {
+            Ok(webview) => webview,
+            Err(error) => {
+                callback(Err(anyhow!(
+                    "printing to PDF requires a newer WebView2 runtime: {error}"
+                )));
+                return;
+            }
+        };
+        let display_path = path.display().to_string();
+        let handler = PrintToPdfCompletedHandler::create(Box::new(move |error_code, succeeded| {
+            callback(error_code.context("failed to print PDF").and_then(|()| {
+                if succeeded.as_bool() {
+                    Ok(())
+                } else {
+                    Err(anyhow!("failed to write PDF to {display_path}"))
+                }
+            }));
+            Ok(())
+        }));
+        unsafe {
+            webview
+                .PrintToPdf(
+                    PCWSTR(HSTRING::from(path.as_os_str()).as_ptr()),
+                    None,
+                    &handler,
+                )
+                .log_err();
+        }
+    }
+
+    /// The profile directory holding this webview's cookies, cache, and storage.
+    pub(super) fn data_store_directories(&self) -> Vec<PathBuf> {
+        let profile_path = unsafe {
+            self.webview
+                .cast::<ICoreWebView2_13>()
+                .and_then(|webview| webview.Profile())
+                .and_then(|profile| {
+                    let mut path = PWSTR::null();
+                    profile.ProfilePath(&mut path)?;
+                    Ok(webview2_com::take_pwstr(path))
+                })
+        };
+        match profile_path {
+            Ok(path) => vec![PathBuf::from(path)],
+            Err(error) => {
+                log::warn!("Failed to get the WebView2 profile path: {error}");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Removes all cookies, caches, and storage from this webview's profile, then invokes
+    /// `callback`.
+    pub fn clear_website_data(&self, callback: impl FnOnce() + 'static) {
+        self.clear_browsing_data(None, callback);
+    }
+
+    /// Removes only cookies from this webview's profile, then invokes `callback`.
+    pub fn clear_cookies_only(&self, callback: impl FnOnce() + 'static) {
+        self.clear_browsing_data(Some(COREWEBVIEW2_BROWSING_DATA_KINDS_COOKIES), callback);
+    }
+
+    /// Adds or replaces a cookie in this webview's profile, then invokes `callback`. Unlike
+    /// `document.cookie`, this can set `HttpOnly` cookies.
+    pub fn set_cookie(&self, cookie: Cookie, callback: impl FnOnce() + 'static) {
+        unsafe { self.add_or_update_cookie(&cookie) }
+            .with_context(|| format!("failed to set cookie {}", cookie.name))
+            .log_err();
+        callback();
+    }
+
+    unsafe fn add_or_update_cookie(&self, cookie: &Cookie) -> Result<()> {
+        unsafe {
+            let cookie_manager = self.webview.cast::<ICoreWebView2_2>()?.CookieManager()?;
+            let native_cookie = cookie_manager.CreateCookie(
+                PCWSTR(HSTRING::from(&cookie.name).as_ptr()),
+                PCWSTR(HSTRING::from(&cookie.value).as_ptr()),
+                PCWSTR(HSTRING::from(&cookie.domain).as_ptr()),
+                PCWSTR(HSTRING::from(&cookie.path).as_ptr()),
+            )?;
+            native_cookie.SetIsSecure(cookie.secure)?;
+            native_cookie.SetIsHttpOnly(cookie.http_only)?;
+            if let Some(expires) = cookie.expires {
+                native_cookie.SetExpires(unix_seconds(expires))?;
+            }
+            cookie_manager.AddOrUpdateCookie(&native_cookie)?;
+            Ok(())
+        }
+    }
+
+    /// Invokes `callback` with the cookies in this webview's profile that would be sent with a
+    /// request to `url`, including `HttpOnly` cookies.
+    pub fn get_cookies(&self, url: &str, callback: impl FnOnce(Vec<Cookie>) + 'static) {
+        let cookie_manager = normalize_url(url).and_then(|url| unsafe {
+            let cookie_manager = self.webview.cast::<ICoreWebView2_2>()?.CookieManager()?;
+            Ok((url, cookie_manager))
+        });
+        let (url, cookie_manager) = match cookie_manager {
+            Ok(cookie_manager) => cookie_manager,
+            Err(error) => {
+                log::error!("Failed to get cookies: {error}");
+                callback(Vec::new());
+                return;
+            }
+        };
+        let handler = GetCookiesCompletedHandler::create(Box::new(move |error_code, list| {
+            let cookies = error_code
+                .map_err(anyhow::Error::from)
+                .and_then(|()| match list {
+                    Some(list) => unsafe { cookies_from_list(&list) },
+                    None => Ok(Vec::new()),
+                })
+                .context("failed to get cookies")
+                .log_err()
+                .unwrap_or_default();
+            callback(cookies);
+            Ok(())
+        }));
+        unsafe {
+            cookie_manager
+                .GetCookies(PCWSTR(HSTRING::from(url).as_ptr()), &handler)
+                .log_err();
+        }
+    }
+
+    /// Clears every kind of browsing data when `kinds` is `None`.
+    fn clear_browsing_data(
+        &self,
+        kinds: Option<COREWEBVIEW2_BROWSING_DATA_KINDS>,
+        callback: impl FnOnce() + 'static,
+    ) {
+        let profile = unsafe {
+            self.webview
+                .cast::<ICoreWebView2_13>()
+                .and_then(|webview| webview.Profile())
+                .and_then(|profile| profile.cast::<ICoreWebView2Profile2>())
+        };
+        let profile = match profile {
+            Ok(profile) => profile,
+            Err(error) => {
+                log::error!("Failed to clear website data: {error}");
+                callback();
+                return;
+            }
+        };
+        let handler = ClearBrowsingDataCompletedHandler::create(Box::new(move |error_code| {
+            error_code.log_err();
+            callback();
+            Ok(())
+        }));
+        unsafe {
+            match kinds {
+                Some(kinds) => profile.ClearBrowsingData(kinds, &handler),
+                None => profile.ClearBrowsingDataAll(&handler),
+            }
+            .log_err();
+        }
+    }
+
+    /// WebView2 always lets elements use the Fullscreen API, filling the webview rather than the
+    /// screen.
+    pub fn set_allow_element_fullscreen(&self, _allow: bool) {}
+
+    /// WebView2 opens dropped files by default, without filtering by type like macOS does.
+    /// Disabling drops also stops files from being dropped into the page itself.
+    pub fn set_accepts_file_drops(&self, accept: bool) {
+        unsafe {
+            self.controller
+                .cast::<ICoreWebView2Controller4>()
+                .and_then(|controller| controller.SetAllowExternalDrop(accept))
+                .log_err();
+        }
+    }
+
+    /// Scales the page content. `factor` is clamped to 0.25..=5.0, where 1.0 is the default.
+    pub fn set_zoom(&self, factor: f64) {
+        unsafe {
+            self.controller.SetZoomFactor(clamp_zoom(factor)).log_err();
+        }
+    }
+
+    pub fn zoom(&self) -> f64 {
+        let mut factor = 1.0;
+        unsafe {
+            self.controller.ZoomFactor(&mut factor).log_err();
+        }
+        factor
+    }
+
+    /// Overrides the user agent sent with requests. An empty string leaves the current user
+    /// agent in place, since WebView2 has no way to restore its default.
+    pub fn set_user_agent(&self, user_agent: &str) {
+        if user_agent.is_empty() {
+            return;
+        }
+        unsafe {
+            self.webview
+                .Settings()
+                .and_then(|settings| settings.cast::<ICoreWebView2Settings2>())
+                .and_then(|settings| {
+                    settings.SetUserAgent(PCWSTR(HSTRING::from(user_agent).as_ptr()))
+                })
+                .log_err();
+        }
+    }
+
+    /// Injects `source` into every page loaded from now on, either before any page script runs or
+    /// once the document has finished parsing. Before the first load ends, the script is queued
+    /// and also runs once in the page that loaded; see [`Self::has_loaded`].
+    pub fn add_user_script(&self, source: &str, at_document_start: bool) {
+        if !self.has_loaded() {
+            self.defer_script(DeferredScript::UserScript {
+                source: source.to_string(),
+                at_document_start,
+            });
+            return;
+        }
+        add_script_on_document_created(&self.webview, source, at_document_start);
+    }
+
+    /// Registers a handler for messages posted by the page through
+    /// `window.webkit.messageHandlers.<name>.postMessage(body)`, which is shimmed onto
+    /// `window.chrome.webview.postMessage` for parity with macOS. Registering the same name again
+    /// replaces the previous handler.
+    pub fn register_message_handler(&mut self, name: &str, handler: impl FnMut(String) + 'static) {
+        let previous = self
+            .state
+            .borrow_mut()
+            .message_handlers
+            .insert(name.to_string(), Box::new(handler));
+        if previous.is_none() {
+            self.inject_script(&message_handler_shim(name));
+        }
+    }
+
+    /// Sets the opacity of the whole window, from 0.0 (invisible) to 1.0 (opaque).
+    pub fn set_opacity(&self, alpha: f64) {
+        unsafe {
+            let ex_style = GetWindowLongPtrW(self.hwnd, GWL_EXSTYLE);
+            SetWindowLongPtrW(self.hwnd, GWL_EXSTYLE, ex_style | WS_EX_LAYERED.0 as isize);
+            SetLayeredWindowAttributes(
+                self.hwnd,
+                COLORREF(0),
+                (alpha.clamp(0.0, 1.0) * 255.0).round() as u8,
+                LWA_ALPHA,
+            )
+            .log_err();
+        }
+    }
+
+    /// Lets the window background show through wherever the page doesn't paint a background.
+    pub fn set_background_transparent(&self, transparent: bool) {
+        let color = if transparent {
+            COREWEBVIEW2_COLOR {
+                A: 0,
+                R: 0,
+                G: 0,
+                B: 0,
+            }
+        } else {
+            COREWEBVIEW2_COLOR {
+                A: 255,
+                R: 255,
+                G: 255,
+                B: 255,
+            }
+        };
+        unsafe {
+            self.controller
+                .cast::<ICoreWebView2Controller2>()
+                .and_then(|controller| controller.SetDefaultBackgroundColor(color))
+                .log_err();
+        }
+    }
+
+    /// Windows only distinguishes topmost windows, so every level above `Normal` behaves the same.
+    pub fn set_window_level(&self, level: WindowLevel) {
+        let insert_after = if level == WindowLevel::Normal {
+            HWND_NOTOPMOST
+        } else {
+            HWND_TOPMOST
+        };
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                Some(insert_after),
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            )
+            .log_err();
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.window_title = title.to_string();
+        unsafe {
+            SetWindowTextW(self.hwnd, PCWSTR(HSTRING::from(title).as_ptr())).log_err();
+        }
+    }
+
+    /// Window titles are always shown on Windows.
+    pub fn set_title_visible(&self, _visible: bool) {}
+
+    /// The shadow is drawn by the desktop window manager on Windows.
+    pub fn set_has_shadow(&self, _shadow: bool) {}
+
+    /// WebView2 stores the preferred color scheme in the profile, so this also applies to other
+    /// webviews sharing it.
+    pub fn set_appearance(&self, appearance: Appearance) {
+        let color_scheme = match appearance {
+            Appearance::Light => COREWEBVIEW2_PREFERRED_COLOR_SCHEME_LIGHT,
+            Appearance::Dark => COREWEBVIEW2_PREFERRED_COLOR_SCHEME_DARK,
+            Appearance::System => COREWEBVIEW2_PREFERRED_COLOR_SCHEME_AUTO,
+        };
+        unsafe {
+            self.webview
+                .cast::<ICoreWebView2_13>()
+                .and_then(|webview| webview.Profile())
+                .and_then(|profile| profile.SetPreferredColorScheme(color_scheme))
+        }
+        .log_err();
+    }
+
+    /// Keeps the user from resizing the window's content below `size`.
+    pub fn set_min_size(&self, size: Size<Pixels>) {
+        if let Some(window_data) = self.window_data() {
+            window_data.min_size.set(Some(size));
+        }
+    }
+
+    /// Keeps the user from resizing the window's content above `size`.
+    pub fn set_max_size(&self, size: Size<Pixels>) {
+        if let Some(window_data) = self.window_data() {
+            window_data.max_size.set(Some(size));
+        }
+    }
+
+    /// Keeps the window's content at `ratio` (width, height) while the user resizes it, e.g. for
+    /// video. `None` lets it resize freely again.
+    pub fn set_aspect_ratio(&self, ratio: Option<(f64, f64)>) {
+        if let Some(window_data) = self.window_data() {
+            window_data.aspect_ratio.set(
+                ratio
+                    .filter(|(width, height)| *width > 0.0 && *height > 0.0)
+                    .map(|(width, height)| width / height),
+            );
+        }
+    }
+
+    /// Lays the page out at `size` instead of the window's client size, e.g. to preview a mobile
+    /// layout in a desktop window. The webview stays pinned to the top-left corner of the
+    /// [content insets](Self::set_content_insets) and is clipped when larger than the window.
+    /// Only the CSS viewport changes; pages still see a desktop user agent and device pixel
+    /// ratio, and `<meta name="viewport">` is ignored.
+    pub fn set_viewport_size(&self, size: Size<Pixels>) {
+        if let Some(window_data) = self.window_data() {
+            window_data.viewport_size.set(Some(size));
+            self.layout_webview(window_data);
+        }
+    }
+
+    /// Makes the webview fill the window again after [`Self::set_viewport_size`].
+    pub fn clear_viewport_size(&self) {
+        if let Some(window_data) = self.window_data() {
+            window_data.viewport_size.set(None);
+            self.layout_webview(window_data);
+        }
+    }
+
+    /// Keeps the webview `insets` away from the edges of the window's client area, e.g. to leave
+    /// room for a toolbar drawn above the page. The margins stay fixed as the window resizes.
+    /// Defaults to no insets.
+    pub fn set_content_insets(&self, insets: Edges<Pixels>) {
+        if let Some(window_data) = self.window_data() {
+            window_data.content_insets.set(insets);
+            self.layout_webview(window_data);
+        }
+    }
+
+    /// Reserves a `height` strip across the top of the window, moving the webview below it. The
+    /// content insets apply to the area below the strip. WebView2 can't host native controls in
+    /// the strip, so it only shows the window background. Defaults to no strip.
+    pub fn set_chrome_height(&self, height: Pixels) {
+        if let Some(window_data) = self.window_data() {
+            window_data.chrome_height.set(height.max(px(0.0)));
+            self.layout_webview(window_data);
+        }
+    }
+
+    fn layout_webview(&self, window_data: &WindowData) {
+        if let Some(rect) = webview_rect(self.hwnd, window_data).log_err() {
+            unsafe {
+                self.controller.SetBounds(rect).log_err();
+            }
+        }
+    }
+
+    fn window_data(&self) -> Option<&WindowData> {
+        unsafe { (GetWindowLongPtrW(self.hwnd, GWLP_USERDATA) as *const WindowData).as_ref() }
+    }
+
+    /// Whether the user can resize or maximize the window by removing its sizing border.
+    pub fn set_resizable(&self, resizable: bool) {
+        unsafe {
+            let sizing_style = (WS_THICKFRAME | WS_MAXIMIZEBOX).0 as isize;
+            let style = GetWindowLongPtrW(self.hwnd, GWL_STYLE);
+            let style = if resizable {
+                style | sizing_style
+            } else {
+                style & !sizing_style
+            };
+            SetWindowLongPtrW(self.hwnd, GWL_STYLE, style);
+            SetWindowPos(
+                self.hwnd,
+                None,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED,
+            )
+            .log_err();
+        }
+    }
+
+    /// Hiding keeps the page loaded, preserving its scroll position and session, so a hidden
+    /// manager can be shown again instead of recreated.
+    pub fn set_hidden(&self, hidden: bool) {
+        unsafe {
+            if hidden {
+                let _ = ShowWindow(self.hwnd, SW_HIDE);
+            } else {
+                let _ = ShowWindow(self.hwnd, SW_SHOW);
+            }
+            self.controller.SetIsVisible(!hidden).log_err();
+        }
+        if !hidden {
+            self.apply_pending_bounds();
+        }
+    }
+
+    /// Shows the window without taking keyboard focus from the app.
+    pub fn show_without_activating(&self) {
+        unsafe {
+            let _ = ShowWindow(self.hwnd, SW_SHOWNOACTIVATE);
+            self.controller.SetIsVisible(true).log_err();
+        }
+        self.apply_pending_bounds();
+    }
+
+    /// Brings the window to the foreground and gives the webview keyboard focus.
+    pub fn focus(&self) {
+        unsafe {
+            let _ = SetForegroundWindow(self.hwnd);
+            self.controller
+                .MoveFocus(COREWEBVIEW2_MOVE_FOCUS_REASON_PROGRAMMATIC)
+                .log_err();
+        }
+    }
+
+    /// The webview's input windows belong to the browser process, so this checks whether the
+    /// window hosting them is in the foreground.
+    pub fn is_focused(&self) -> bool {
+        unsafe { GetForegroundWindow() == self.hwnd }
+    }
+
+    /// Hands keyboard focus back to the parent window.
+    pub fn blur(&self) {
+        if !self.parent_hwnd.is_invalid() {
+            unsafe {
+                let _ = SetForegroundWindow(self.parent_hwnd);
+            }
+        }
+    }
+
+    /// Whether a navigation is in progress.
+    pub fn is_loading(&self) -> bool {
+        self.state.borrow().estimated_progress < 1.0
+    }
+
+    /// Asks WebView2 to suspend script timers and animations. WebView2 only suspends hidden
+    /// webviews and resumes them automatically when they are shown.
+    pub fn set_suspended(&self, suspended: bool) {
+        unsafe {
+            let webview = match self.webview.cast::<ICoreWebView2_3>() {
+                Ok(webview) => webview,
+                Err(error) => {
+                    log::warn!("Suspending requires a newer WebView2 runtime: {error}");
+                    return;
+                }
+            };
+            if suspended {
+                webview
+                    .TrySuspend(&TrySuspendCompletedHandler::create(Box::new(
+                        |error_code, suspended| {
+                            error_code?;
+                            if !suspended.as_bool() {
+                                log::warn!("WebView2 declined to suspend the webview");
+                            }
+                            Ok(())
+                        },
+                    )))
+                    .log_err();
+            } else {
+                webview.Resume().log_err();
+            }
+        }
+    }
+
+    /// Mutes all audio the page plays. Requires a WebView2 runtime with `ICoreWebView2_8`.
+    pub fn set_muted(&self, muted: bool) {
+        unsafe {
+            match self.webview.cast::<ICoreWebView2_8>() {
+                Ok(webview) => {
+                    webview.SetIsMuted(muted).log_err();
+                }
+                Err(error) => log::warn!("Muting requires a newer WebView2 runtime: {error}"),
+            }
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        let mut muted = BOOL::default();
+        unsafe {
+            if let Ok(webview) = self.webview.cast::<ICoreWebView2_8>() {
+                webview.IsMuted(&mut muted).log_err();
+            }
+        }
+        muted.as_bool()
+    }
+
+    /// Minimizes the window to the taskbar.
+    pub fn minimize(&self) {
+        unsafe {
+            let _ = ShowWindow(self.hwnd, SW_MINIMIZE);
+        }
+    }
+
+    /// Restores a minimized or maximized window and brings it to the front, showing it if it was
+    /// hidden.
+    pub fn restore(&self) {
+        unsafe {
+            let _ = ShowWindow(self.hwnd, SW_RESTORE);
+            let _ = SetForegroundWindow(self.hwnd);
+            self.controller.SetIsVisible(true).log_err();
+        }
+        self.apply_pending_bounds();
+    }
+
+    /// Toggles between maximized and restored, like the titlebar's maximize button.
+    pub fn toggle_zoom(&self) {
+        unsafe {
+            let command = if IsZoomed(self.hwnd).as_bool() {
+                SW_RESTORE
+            } else {
+                SW_MAXIMIZE
+            };
+            let _ = ShowWindow(self.hwnd, command);
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        unsafe { IsWindowVisible(self.hwnd).as_bool() }
+    }
+}
+
+impl Drop for WebViewManager {
+    fn drop(&mut self) {
+        unsafe {
+            webview_info!("Cleaning up floating webview window");
+            self.webview
+                .remove_NavigationStarting(self.navigation_starting_token)
+                .log_err();
+            self.webview
+                .remove_NavigationCompleted(self.navigation_completed_token)
+                .log_err();
+            self.webview
+                .remove_WebMessageReceived(self.web_message_received_token)
+                .log_err();
+            self.webview
+                .remove_DocumentTitleChanged(self.document_title_changed_token)
+                .log_err();
+            self.webview
+                .remove_NewWindowRequested(self.new_window_requested_token)
+                .log_err();
+            self.webview
+                .remove_ScriptDialogOpening(self.script_dialog_opening_token)
+                .log_err();
+            self.webview
+                .remove_PermissionRequested(self.permission_requested_token)
+                .log_err();
+            self.webview
+                .remove_ProcessFailed(self.process_failed_token)
+                .log_err();
+            if let Some(token) = self.server_certificate_error_token {
+                self.webview
+                    .cast::<ICoreWebView2_14>()
+                    .and_then(|webview| webview.remove_ServerCertificateErrorDetected(token))
+                    .log_err();
+            }
+            if let Some(token) = self.basic_authentication_token {
+                self.webview
+                    .cast::<ICoreWebView2_10>()
+                    .and_then(|webview| webview.remove_BasicAuthenticationRequested(token))
+                    .log_err();
+            }
+            if let Some(token) = self.download_starting_token {
+                self.webview
+                    .cast::<ICoreWebView2_4>()
+                    .and_then(|webview| webview.remove_DownloadStarting(token))
+                    .log_err();
+            }
+            let window_data = SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0) as *mut WindowData;
+            if !window_data.is_null() {
+                drop(Box::from_raw(window_data));
+            }
+            self.controller.Close().log_err();
+            DestroyWindow(self.hwnd).log_err();
+        }
+    }
+}
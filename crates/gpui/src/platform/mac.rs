@@ -44,6 +44,8 @@ use std::{
     ops::Range,
 };
 
+pub use events::key_to_native;
+
 pub(crate) use dispatcher::*;
 pub(crate) use display::*;
 pub(crate) use display_link::*;
@@ -26,6 +26,8 @@ pub(crate) const ESCAPE_KEY: u16 = 0x1b;
 const TAB_KEY: u16 = 0x09;
 const SHIFT_TAB_KEY: u16 = 0x19;
 
+/// Converts a key name used by [`crate::Keystroke`], such as `up` or `f1`, into the characters
+/// macOS reports for that key. Other keys are returned unchanged.
 pub fn key_to_native(key: &str) -> Cow<'_, str> {
     use cocoa::appkit::*;
     let code = match key {